@@ -10,7 +10,9 @@ use syn::{
 /// just so its easier to read.
 ///
 /// You can call this like `register_component(marker)` to automagically
-/// insert a stub implementation of Component that registers nothing.
+/// insert a stub implementation of Component that registers nothing,
+/// and `register_component(transient)` to never save it with the world,
+/// so it doesn't need to be serializable.
 #[proc_macro_attribute]
 pub fn register_component(
   args: TokenStream,
@@ -29,25 +31,35 @@ pub fn register_component(
   );
 
   let mut marker = false;
+  let mut transient = false;
   for directive in directives.iter() {
     match directive.to_string().as_str() {
       "marker" => {
         marker = true;
       }
+      "transient" => {
+        transient = true;
+      }
       _ => directive
         .span()
         .unwrap()
-        .error("only `marker` can go here")
+        .error("only `marker` or `transient` can go here")
         .emit(),
     }
   }
 
+  let register = if transient {
+    quote! { ::palkia::manually_register_component!(#struct_name, transient); }
+  } else {
+    quote! { ::palkia::manually_register_component!(#struct_name); }
+  };
+
   let mut expanded = quote! {
     // attr macros don't automatically pass through the body
     // so do that
     #input
 
-    ::palkia::manually_register_component!(#struct_name);
+    #register
   };
 
   if marker {
@@ -5,7 +5,7 @@
 
 // should this go in the access module?
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use crate::{
   prelude::{Component, Entity, ListenerWorldAccess, World},
@@ -20,11 +20,17 @@ use crate::{
 /// an immediate mode for when you have mutable access to the builder
 /// and a lazy mode that adds the entity only once [`World::finalize`] is
 /// called.
+///
+/// Builders can also edit entities that already exist, from
+/// [`World::edit`] or [`World::lazy_edit`]. Then, `build` puts the
+/// components back and runs create callbacks for the component types that
+/// were added, and remove callbacks for the ones that were removed.
 #[must_use = "Does nothing until `.build()` is called."]
 pub struct EntityBuilder<'a, 'w> {
   pub entity: Entity,
   tracker: EntityBuilderComponentTracker,
   access: EntityBuilderAccess<'a, 'w>,
+  mode: EntityBuilderMode,
 }
 
 impl<'a, 'w> EntityBuilder<'a, 'w> {
@@ -36,6 +42,7 @@ impl<'a, 'w> EntityBuilder<'a, 'w> {
       entity,
       tracker: EntityBuilderComponentTracker::new(),
       access: EntityBuilderAccess::Lazy(lazy),
+      mode: EntityBuilderMode::Spawn,
     }
   }
 
@@ -44,6 +51,7 @@ impl<'a, 'w> EntityBuilder<'a, 'w> {
       entity,
       tracker: EntityBuilderComponentTracker::new(),
       access: EntityBuilderAccess::LazyWorld(world),
+      mode: EntityBuilderMode::Spawn,
     }
   }
 
@@ -52,6 +60,105 @@ impl<'a, 'w> EntityBuilder<'a, 'w> {
       entity,
       tracker: EntityBuilderComponentTracker::new(),
       access: EntityBuilderAccess::Immediate(world),
+      mode: EntityBuilderMode::Spawn,
+    }
+  }
+
+  /// Edit an entity whose data has already been taken out of the world.
+  pub(crate) fn new_edit(
+    world: &'w mut World,
+    entity: Entity,
+    assoc: EntityAssoc,
+  ) -> Self {
+    let mut tracker = EntityBuilderComponentTracker::new();
+    for (_, comp) in assoc.into_iter() {
+      tracker.insert_raw(comp.into_inner().unwrap());
+    }
+    let original = tracker.component_idxs.keys().copied().collect();
+
+    Self {
+      entity,
+      tracker,
+      access: EntityBuilderAccess::Immediate(world),
+      mode: EntityBuilderMode::Edit {
+        original,
+        removed: BTreeMap::new(),
+      },
+    }
+  }
+
+  pub(crate) fn new_lazy_edit(
+    lazy: &'a ListenerWorldAccess<'w>,
+    entity: Entity,
+  ) -> Self {
+    Self {
+      entity,
+      tracker: EntityBuilderComponentTracker::new(),
+      access: EntityBuilderAccess::Lazy(lazy),
+      mode: EntityBuilderMode::LazyEdit {
+        removed: BTreeSet::new(),
+      },
+    }
+  }
+
+  pub(crate) fn new_lazy_world_edit(world: &'w World, entity: Entity) -> Self {
+    Self {
+      entity,
+      tracker: EntityBuilderComponentTracker::new(),
+      access: EntityBuilderAccess::LazyWorld(world),
+      mode: EntityBuilderMode::LazyEdit {
+        removed: BTreeSet::new(),
+      },
+    }
+  }
+
+  /// Turn a lazy spawning builder into one that also copies the source
+  /// entity's components on finalize.
+  pub(crate) fn lazily_cloning(mut self, source: Entity) -> Self {
+    self.mode = EntityBuilderMode::LazyClone {
+      source,
+      removed: BTreeSet::new(),
+    };
+    self
+  }
+
+  fn world(&self) -> &World {
+    match self.access {
+      EntityBuilderAccess::Immediate(ref world) => world,
+      EntityBuilderAccess::Lazy(lazy) => lazy.world,
+      EntityBuilderAccess::LazyWorld(world) => world,
+    }
+  }
+
+  /// The live entity in the world whose components this builder will get on
+  /// finalize, and the types it's removed from it.
+  fn live(&self) -> Option<(Entity, &BTreeSet<TypeIdWrapper>)> {
+    match &self.mode {
+      EntityBuilderMode::LazyEdit { removed } => Some((self.entity, removed)),
+      EntityBuilderMode::LazyClone { source, removed } => {
+        Some((*source, removed))
+      }
+      _ => None,
+    }
+  }
+
+  /// The components of the live entity, if there is one and it's been
+  /// finished. An entity that's only lazily spawned or already dead has none.
+  fn live_assoc(&self) -> Option<(&EntityAssoc, &BTreeSet<TypeIdWrapper>)> {
+    let (entity, removed) = self.live()?;
+    let assoc = self.world().entities.try_get(entity)?;
+    Some((assoc, removed))
+  }
+
+  /// If this is lazily editing or cloning an entity, check if the live entity
+  /// in the world has a component of the given type that hasn't been removed
+  /// by this builder.
+  fn live_has(&self, tid: TypeIdWrapper) -> bool {
+    match self.live_assoc() {
+      Some((assoc, removed)) => {
+        !removed.contains(&tid) && assoc.components().contains_key(&tid)
+      }
+      None => false,
     }
   }
 
@@ -60,15 +167,18 @@ impl<'a, 'w> EntityBuilder<'a, 'w> {
   /// replaces and returns the old component.
   ///
   /// You should probably not be calling this; try [`insert`][EntityBuilder::insert].
+  ///
+  /// When lazily editing an entity, this only returns components that were
+  /// inserted into this builder; the live component is clobbered on finalize.
   pub fn insert_raw(
     &mut self,
     component: Box<dyn Component>,
   ) -> Option<Box<dyn Component>> {
-    let _world = match self.access {
-      EntityBuilderAccess::Immediate(ref world) => world,
-      EntityBuilderAccess::Lazy(lazy) => lazy.world,
-      EntityBuilderAccess::LazyWorld(ref world) => world,
-    };
+    if let EntityBuilderMode::LazyEdit { removed }
+    | EntityBuilderMode::LazyClone { removed, .. } = &mut self.mode
+    {
+      removed.remove(&(*component).type_id_wrapper());
+    }
     self.tracker.insert_raw(component)
   }
 
@@ -94,9 +204,63 @@ impl<'a, 'w> EntityBuilder<'a, 'w> {
     self
   }
 
+  /// Remove the component of the given type from the entity, returning
+  /// whether there was one to remove.
+  ///
+  /// The removed component is dropped once the builder is built, after the
+  /// remove callbacks are run on it.
+  pub fn remove<C: Component>(&mut self) -> bool {
+    self.remove_raw(TypeIdWrapper::of::<C>())
+  }
+
+  /// Remove the component with the given type ID from the entity.
+  ///
+  /// You should probably not be calling this; try [`remove`][EntityBuilder::remove].
+  pub fn remove_raw(&mut self, tid: TypeIdWrapper) -> bool {
+    let live = self.live_has(tid);
+    let removed = self.tracker.remove_raw(tid);
+    match &mut self.mode {
+      EntityBuilderMode::Spawn => removed.is_some(),
+      EntityBuilderMode::Edit {
+        original,
+        removed: removed_originals,
+      } => match removed {
+        Some(comp) => {
+          if original.contains(&tid) {
+            removed_originals.entry(tid).or_insert(comp);
+          }
+          true
+        }
+        None => false,
+      },
+      EntityBuilderMode::LazyEdit {
+        removed: removed_tids,
+      }
+      | EntityBuilderMode::LazyClone {
+        removed: removed_tids,
+        ..
+      } => {
+        if live {
+          removed_tids.insert(tid);
+        }
+        live || removed.is_some()
+      }
+    }
+  }
+
   /// Get the number of components that will be attached to the given entity.
   pub fn len(&self) -> usize {
-    self.tracker.components.len()
+    let live = match self.live_assoc() {
+      Some((assoc, _)) => assoc
+        .components()
+        .keys()
+        .filter(|tid| {
+          self.live_has(**tid) && !self.tracker.component_idxs.contains_key(tid)
+        })
+        .count(),
+      None => 0,
+    };
+    self.tracker.components.len() + live
   }
 
   /// Return true if no components will be attached to this entity.
@@ -146,26 +310,42 @@ impl<'a, 'w> EntityBuilder<'a, 'w> {
 
   /// Consume this and insert the entity into the world, returning it to the caller.
   ///
-  /// Note that if you *don't* call this, there will be panics.
+  /// If this is editing an entity, this puts the changed components back on it.
+  /// An edit builder that's dropped without being built puts the components
+  /// back without running any callbacks: removed components come back and new
+  /// types are dropped, but components that were mutated or clobbered with
+  /// [`insert`](EntityBuilder::insert) keep their new values.
+  ///
+  /// Note that if you *don't* call this when spawning, there will be panics.
   pub fn build(mut self) -> Entity {
+    let components = std::mem::take(&mut self.tracker.components);
+    // Leave a mode that `drop` has nothing to do for
+    let mode = std::mem::replace(&mut self.mode, EntityBuilderMode::Spawn);
     match self.access {
-      EntityBuilderAccess::Immediate(ref mut world) => {
-        world
-          .finish_spawn(self.entity, EntityAssoc::new(self.tracker.components));
-      }
+      EntityBuilderAccess::Immediate(ref mut world) => match mode {
+        EntityBuilderMode::Spawn => {
+          world.finish_spawn(self.entity, EntityAssoc::new(components));
+        }
+        EntityBuilderMode::Edit { original, removed } => {
+          world.finish_edit(
+            self.entity,
+            EntityAssoc::new(components),
+            original,
+            removed.into_values().collect(),
+          );
+        }
+        EntityBuilderMode::LazyEdit { .. }
+        | EntityBuilderMode::LazyClone { .. } => {
+          unreachable!("immediate builders never lazily edit or clone")
+        }
+      },
       EntityBuilderAccess::Lazy(lazy) => {
-        lazy.queue_update(LazyUpdate::FinishEntity(
-          self.tracker.components,
-          self.entity,
-        ));
+        lazy.queue_update(mode.into_lazy_update(components, self.entity));
       }
       EntityBuilderAccess::LazyWorld(world) => {
         world
           .lazy_sender
-          .send(LazyUpdate::FinishEntity(
-            self.tracker.components,
-            self.entity,
-          ))
+          .send(mode.into_lazy_update(components, self.entity))
           .unwrap();
       }
     }
@@ -203,9 +383,12 @@ impl<'a, 'w> EntityBuilder<'a, 'w> {
   }
 
   /// Returns if this builder contains the given component.
+  ///
+  /// When lazily editing an entity, this also checks the live entity, even though
+  /// [`get_component`][EntityBuilder::get_component] can't see its components.
   pub fn has_component<C: Component>(&self) -> bool {
     let tid = TypeIdWrapper::of::<C>();
-    self.tracker.component_idxs.contains_key(&tid)
+    self.tracker.component_idxs.contains_key(&tid) || self.live_has(tid)
   }
 
   /// Create a new [`EntityBuilder`] from this one. It will be lazy or unlazy
@@ -227,6 +410,32 @@ impl<'a, 'w> EntityBuilder<'a, 'w> {
   }
 }
 
+impl Drop for EntityBuilder<'_, '_> {
+  fn drop(&mut self) {
+    // An immediate edit holds the entity's components, so they have to go
+    // back even if it's never built
+    let (
+      EntityBuilderMode::Edit { original, removed },
+      EntityBuilderAccess::Immediate(world),
+    ) = (&mut self.mode, &mut self.access)
+    else {
+      return;
+    };
+    let mut components = Vec::new();
+    for comp in std::mem::take(&mut self.tracker.components) {
+      let tid = (*comp).type_id_wrapper();
+      if original.contains(&tid) {
+        // Prefer the original if it was removed and something else inserted
+        components.push(removed.remove(&tid).unwrap_or(comp));
+      }
+    }
+    components.extend(std::mem::take(removed).into_values());
+    world
+      .entities
+      .finish_spawn(self.entity, EntityAssoc::new(components));
+  }
+}
+
 /// Access that an EntityBuilder gets to the world, whether immediate or deferred.
 pub enum EntityBuilderAccess<'a, 'w> {
   Immediate(&'w mut World),
@@ -234,6 +443,58 @@ pub enum EntityBuilderAccess<'a, 'w> {
   LazyWorld(&'a World),
 }
 
+/// Whether a builder is creating a new entity or changing an old one.
+enum EntityBuilderMode {
+  /// The entity is brand new.
+  Spawn,
+  /// The entity's components were moved into the builder, and get put back
+  /// on build.
+  Edit {
+    /// Types the entity had when the edit started.
+    original: BTreeSet<TypeIdWrapper>,
+    /// Original components that were removed, kept around for their callbacks.
+    removed: BTreeMap<TypeIdWrapper, Box<dyn Component>>,
+  },
+  /// The entity's components stay in the world; the builder only tracks
+  /// new components and removed types, applied on finalize.
+  LazyEdit { removed: BTreeSet<TypeIdWrapper> },
+  /// The entity is brand new, and gets copies of the source entity's
+  /// components on finalize, except the removed types and the ones the
+  /// builder has.
+  LazyClone {
+    source: Entity,
+    removed: BTreeSet<TypeIdWrapper>,
+  },
+}
+
+impl EntityBuilderMode {
+  fn into_lazy_update(
+    self,
+    components: Vec<Box<dyn Component>>,
+    entity: Entity,
+  ) -> LazyUpdate {
+    match self {
+      EntityBuilderMode::Spawn => LazyUpdate::FinishEntity(components, entity),
+      EntityBuilderMode::LazyEdit { removed } => LazyUpdate::EditEntity {
+        entity,
+        inserted: components,
+        removed: removed.into_iter().collect(),
+      },
+      EntityBuilderMode::LazyClone { source, removed } => {
+        LazyUpdate::CloneEntity {
+          source,
+          entity,
+          inserted: components,
+          removed,
+        }
+      }
+      EntityBuilderMode::Edit { .. } => {
+        unreachable!("lazy builders never immediately edit")
+      }
+    }
+  }
+}
+
 #[derive(Default)]
 pub(crate) struct EntityBuilderComponentTracker {
   pub(crate) components: Vec<Box<dyn Component>>,
@@ -268,4 +529,19 @@ impl EntityBuilderComponentTracker {
       None
     }
   }
+
+  /// Remove the component with the given type, keeping the order of the rest.
+  pub(crate) fn remove_raw(
+    &mut self,
+    tid: TypeIdWrapper,
+  ) -> Option<Box<dyn Component>> {
+    let idx = self.component_idxs.remove(&tid)?;
+    let old = self.components.remove(idx);
+    for later in self.component_idxs.values_mut() {
+      if *later > idx {
+        *later -= 1;
+      }
+    }
+    Some(old)
+  }
 }
@@ -3,13 +3,16 @@
 use std::marker::PhantomData;
 
 use downcast::{downcast, Any};
-use serde::de::DeserializeOwned;
+use serde::{de::DeserializeOwned, Serialize};
 
 use crate::{
   callback::CallbackWorldAccess,
+  entities::{EntityMap, MapEntities},
   messages::{Message, MsgHandlerInner, MsgHandlerRead, MsgHandlerWrite},
   prelude::{Entity, ListenerWorldAccess},
-  vtablesathome::{self, ComponentVtable, DeserializeFn},
+  vtablesathome::{
+    self, CloneFn, ComponentVtable, DeserializeFn, MapEntitiesFn, SerializeFn,
+  },
   TypeIdWrapper,
 };
 
@@ -18,7 +21,11 @@ use crate::{
 /// Components all have a "friendly name". This is the name used to read it from
 /// a blueprint, and used in ser/de as well. By default it is
 /// [`std::any::type_name`].
-pub trait Component: Any + erased_serde::Serialize {
+///
+/// Components are saved with the world, so they have to be
+/// `Serialize + DeserializeOwned`, unless they're registered as transient with
+/// `#[register_component(transient)]`.
+pub trait Component: Any {
   /// Register what message types this listens to and what it does with them.
   fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
   where
@@ -35,7 +42,7 @@ pub struct ComponentRegisterer<C: Component> {
 
 impl<C> ComponentRegisterer<C>
 where
-  C: Component + DeserializeOwned,
+  C: Component,
 {
   /// Tell the world to send the given type of message to this component to be handled with read access.
   pub fn handle_read<M: Message>(
@@ -106,6 +113,7 @@ where
   ///
   /// These are called immediately after spawning an entity with a world, and during [`World::finalize`][crate::world::World::finalize],
   /// for each new instance of that component type.
+  /// They are also called when an edit adds a component type the entity didn't have.
   ///
   /// Panics if another insert callback has already been registered to this component type or if the component
   /// type has not been registered.
@@ -131,11 +139,14 @@ where
   ///
   /// These are called immediately after deleting an entity from a world, and during [`World::finalize`][crate::world::World::finalize],
   /// for each new instance of that component type.
+  /// They are also called when a component is removed from an entity by
+  /// [`World::edit`][crate::world::World::edit] or its lazy version.
   ///
   /// Panics if another removal callback has already been registered to this component type or if the component
   /// type has not been registered.
   ///
-  /// **NOTE THAT** the entity given in the callback will ALWAYS be dead.
+  /// **NOTE THAT** the entity given in the callback will ALWAYS be dead,
+  /// unless the component was removed by an edit.
   pub fn register_remove_callback(
     mut self,
     cb: fn(&C, Entity, &CallbackWorldAccess),
@@ -168,19 +179,134 @@ where
     self
   }
 
-  #[doc(hidden)]
-  pub fn into_vtable(self) -> ComponentVtable {
-    let friendly_name = self
-      .inner
-      .friendly_name
-      .unwrap_or_else(vtablesathome::default_friendly_type_name::<C>);
+  /// Use this component's `Clone` impl when cloning entities with it,
+  /// instead of round-tripping it through serde.
+  pub fn register_clone(mut self) -> Self
+  where
+    C: Clone,
+  {
+    let clone = |comp: &dyn Component| -> Box<dyn Component> {
+      // SAFETY: this will only ever be called with a component of the right concrete type
+      let concrete_comp: &C = unsafe { comp.downcast_ref().unwrap_unchecked() };
+      Box::new(concrete_comp.clone())
+    } as CloneFn<dyn Component>;
+    self.inner.clone = Some(clone);
+    self
+  }
+
+  /// Rewrite the entities stored in this component with its [`MapEntities`] impl
+  /// when it's moved to another world, or saved with
+  /// [`World::serialize_compacted`](crate::world::World::serialize_compacted).
+  pub fn register_map_entities(mut self) -> Self
+  where
+    C: MapEntities,
+  {
+    let map_entities: MapEntitiesFn<dyn Component> =
+      |comp: &mut dyn Component, map: &EntityMap| {
+        // SAFETY: this will only ever be called with a component of the right concrete type
+        let concrete_comp: &mut C =
+          unsafe { comp.downcast_mut().unwrap_unchecked() };
+        concrete_comp.map_entities(map);
+      };
+    self.inner.map_entities = Some(map_entities);
+    self
+  }
 
+  /// Register a function to convert this component out of worlds saved with an
+  /// older [schema version](crate::serde::schema_version), when its layout was different.
+  ///
+  /// `version` is the last schema version the old layout was saved with, and the
+  /// migration turns data in that layout into the next one. When loading a world saved
+  /// with schema version `v`, every migration with `version >= v` is run in order,
+  /// and the result is loaded normally.
+  /// So, when you change the layout of a component, register a migration from the
+  /// current schema version, and leave the older ones alone.
+  ///
+  /// The old data is given as a [`Value`](crate::serde::Value), so this only works with self-describing
+  /// formats.
+  ///
+  /// Panics if a migration from this version was already registered.
+  pub fn migrate_from(
+    mut self,
+    version: u32,
+    migration: fn(serde_value::Value) -> eyre::Result<serde_value::Value>,
+  ) -> Self {
+    if self.inner.migrations.insert(version, migration).is_some() {
+      panic!(
+        "already registered a migration from version {} to component type {}",
+        version,
+        std::any::type_name::<C>(),
+      );
+    }
+    self
+  }
+
+  /// Never save this component with the world.
+  ///
+  /// Use this for things that shouldn't be saved, like handles to
+  /// sounds or textures. Components that can't be saved at all, because they
+  /// don't implement `Serialize` or `Deserialize`, have to be registered with
+  /// `#[register_component(transient)]` instead, which calls this.
+  pub fn transient(mut self) -> Self {
+    self.inner.transient = true;
+    self
+  }
+
+  /// Never save this component with the world, but remember which entities
+  /// had it, and give them its `Default` when the world is loaded.
+  ///
+  /// Its data is replaced with a placeholder in the save.
+  /// Like with [`transient`](Self::transient), if it isn't serializable the
+  /// registration macro has to be told it's transient too.
+  pub fn transient_default(mut self) -> Self
+  where
+    C: Default,
+  {
+    self.inner.transient = true;
+    self.inner.rebuild = Some(|| Box::<C>::default());
+    self
+  }
+
+  /// Send this component to replicas of the world, with a
+  /// [`Replicator`](crate::replication::Replicator).
+  pub fn replicated(mut self) -> Self
+  where
+    C: Serialize + DeserializeOwned,
+  {
+    self.inner.replicated = true;
+    self
+  }
+
+  /// Save this component with the world. This is automatically called by the
+  /// registration macros unless the component is registered as transient.
+  #[doc(hidden)]
+  pub fn register_serde(mut self) -> Self
+  where
+    C: Serialize + DeserializeOwned,
+  {
+    let ser = |comp: &dyn Component| -> &dyn erased_serde::Serialize {
+      // SAFETY: this will only ever be called with a component of the right concrete type
+      let concrete_comp: &C = unsafe { comp.downcast_ref().unwrap_unchecked() };
+      concrete_comp
+    } as SerializeFn<dyn Component>;
     let deser = |deser: &mut dyn erased_serde::Deserializer| -> erased_serde::Result<Box<dyn Component>> {
       let this = C::deserialize(deser)?;
       Ok(Box::new(this) as _)
     }
       as DeserializeFn<dyn Component>;
 
+    self.inner.ser = Some(ser);
+    self.inner.deser = Some(deser);
+    self
+  }
+
+  #[doc(hidden)]
+  pub fn into_vtable(self) -> ComponentVtable {
+    let friendly_name = self
+      .inner
+      .friendly_name
+      .unwrap_or_else(vtablesathome::default_friendly_type_name::<C>);
+
     ComponentVtable {
       tid: TypeIdWrapper::of::<C>(),
 
@@ -188,7 +314,14 @@ where
       msg_table: self.inner.handlers,
       create_cbs: self.inner.create_cbs,
       remove_cbs: self.inner.remove_cbs,
-      deser,
+      ser: self.inner.ser,
+      deser: self.inner.deser,
+      transient: self.inner.transient,
+      rebuild: self.inner.rebuild,
+      clone: self.inner.clone,
+      map_entities: self.inner.map_entities,
+      migrations: self.inner.migrations,
+      replicated: self.inner.replicated,
     }
   }
 }
@@ -201,6 +334,9 @@ pub mod __private {
   use crate::{
     callback::{OnCreateCallback, OnRemoveCallback},
     messages::MsgHandlerInner,
+    vtablesathome::{
+      CloneFn, DefaultFn, DeserializeFn, MapEntitiesFn, MigrateFn, SerializeFn,
+    },
     TypeIdWrapper,
   };
 
@@ -210,6 +346,14 @@ pub mod __private {
     pub(crate) handlers: BTreeMap<TypeIdWrapper, MsgHandlerInner>,
     pub(crate) create_cbs: Vec<OnCreateCallback>,
     pub(crate) remove_cbs: Vec<OnRemoveCallback>,
+    pub(crate) ser: Option<SerializeFn<dyn Component>>,
+    pub(crate) deser: Option<DeserializeFn<dyn Component>>,
+    pub(crate) transient: bool,
+    pub(crate) rebuild: Option<DefaultFn<dyn Component>>,
+    pub(crate) clone: Option<CloneFn<dyn Component>>,
+    pub(crate) map_entities: Option<MapEntitiesFn<dyn Component>>,
+    pub(crate) migrations: BTreeMap<u32, MigrateFn>,
+    pub(crate) replicated: bool,
   }
 
   impl ComponentRegistererErased {
@@ -219,6 +363,14 @@ pub mod __private {
         create_cbs: Vec::new(),
         remove_cbs: Vec::new(),
         friendly_name: None,
+        ser: None,
+        deser: None,
+        transient: false,
+        rebuild: None,
+        clone: None,
+        map_entities: None,
+        migrations: BTreeMap::new(),
+        replicated: false,
       }
     }
 
@@ -232,12 +384,27 @@ pub mod __private {
 }
 pub(crate) use __private::*;
 
+/// Used by the registration macros to save components and resources with the
+/// world, which needs them to be `Serialize + DeserializeOwned`, unless they're
+/// transient.
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __register_serde {
+  ($regi:expr) => {
+    $regi.register_serde()
+  };
+  ($regi:expr, transient) => {
+    $regi.transient()
+  };
+}
+
 /// Longhand component register macro. You can call this as
 /// `manually_register_component(MyComponent)` if you're allergic to
-/// attribute macros for some reason.
+/// attribute macros for some reason, or
+/// `manually_register_component(MyComponent, transient)` for transient ones.
 #[macro_export]
 macro_rules! manually_register_component {
-  ($component_ty:ty) => {
+  ($component_ty:ty $(, $transient:ident)?) => {
     $crate::__private::paste! {
       #[doc(hidden)]
       #[allow(non_snake_case)]
@@ -249,6 +416,7 @@ macro_rules! manually_register_component {
         (regi: $crate::__private::ComponentRegistererErased)
         -> $crate::__private::ComponentVtable {
         let wrapped = regi.wrap();
+        let wrapped = $crate::__register_serde!(wrapped $(, $transient)?);
         <$component_ty as $crate::component::Component>::register(wrapped).into_vtable()
       }
     }
@@ -2,6 +2,7 @@
 
 use std::{collections::hash_map, fmt, iter};
 
+use ahash::AHashMap;
 use generational_arena::Index;
 use serde::{Deserialize, Serialize};
 
@@ -91,3 +92,88 @@ pub enum EntityLiveness {
   /// The entity *will* be alive once [`World::finalize`] is called.
   PartiallySpawned,
 }
+
+/// Mapping from entities in one world to the entities they became in another,
+/// made when moving entities between worlds.
+///
+/// Entities that weren't moved aren't in the map, and [`EntityMap::map`]
+/// turns them into a dead entity.
+#[derive(Debug, Clone)]
+pub struct EntityMap {
+  map: AHashMap<Entity, Entity>,
+  dead: Entity,
+}
+
+impl EntityMap {
+  pub(crate) fn new(dead: Entity) -> Self {
+    Self {
+      map: AHashMap::new(),
+      dead,
+    }
+  }
+
+  pub(crate) fn insert(&mut self, old: Entity, new: Entity) {
+    self.map.insert(old, new);
+  }
+
+  pub(crate) fn remove(&mut self, old: Entity) -> Option<Entity> {
+    self.map.remove(&old)
+  }
+
+  /// Get what the given entity became, if it was moved.
+  pub fn get(&self, old: Entity) -> Option<Entity> {
+    self.map.get(&old).copied()
+  }
+
+  /// Get what the given entity became. If it wasn't moved, return an entity
+  /// that is guaranteed to be dead in the new world.
+  pub fn map(&self, old: Entity) -> Entity {
+    self.get(old).unwrap_or(self.dead)
+  }
+
+  /// Iterate over `(old, new)` pairs, in no particular order.
+  pub fn iter(&self) -> impl Iterator<Item = (Entity, Entity)> + '_ {
+    self.map.iter().map(|(old, new)| (*old, *new))
+  }
+
+  pub fn len(&self) -> usize {
+    self.map.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.map.is_empty()
+  }
+}
+
+/// Things storing [`Entity`]s that need to be rewritten when they're moved
+/// to another world.
+///
+/// Components and resources that store entities should implement this and call
+/// [`ComponentRegisterer::register_map_entities`](crate::component::ComponentRegisterer::register_map_entities)
+/// or [`ResourceRegisterer::register_map_entities`](crate::resource::ResourceRegisterer::register_map_entities),
+/// or their entities will point to the wrong things after an import.
+pub trait MapEntities {
+  fn map_entities(&mut self, map: &EntityMap);
+}
+
+impl MapEntities for Entity {
+  fn map_entities(&mut self, map: &EntityMap) {
+    *self = map.map(*self);
+  }
+}
+
+impl<T: MapEntities> MapEntities for Option<T> {
+  fn map_entities(&mut self, map: &EntityMap) {
+    if let Some(it) = self {
+      it.map_entities(map);
+    }
+  }
+}
+
+impl<T: MapEntities> MapEntities for Vec<T> {
+  fn map_entities(&mut self, map: &EntityMap) {
+    for it in self.iter_mut() {
+      it.map_entities(map);
+    }
+  }
+}
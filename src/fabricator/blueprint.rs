@@ -1,9 +1,16 @@
 //! Internal workings of the library. You probably don't need to look here.
 
-use std::fmt::Display;
+use std::{
+  cell::RefCell,
+  collections::BTreeMap,
+  fmt::Display,
+  fs, io,
+  path::{Path, PathBuf},
+  sync::Arc,
+};
 
 use ahash::AHashMap;
-use kdl::{KdlDocument, KdlNode};
+use kdl::{KdlDocument, KdlEntry, KdlNode, KdlValue};
 use miette::{
   Diagnostic, LabeledSpan, NamedSource, Severity, SourceCode, SourceSpan,
 };
@@ -11,11 +18,58 @@ use smol_str::SmolStr;
 
 use thiserror::Error;
 
+use super::template::{self, Env};
+
 /// Raw instructions for instantiating an entity, as loaded from disc.
+#[derive(Clone)]
 pub struct RawBlueprint {
   pub name: SmolStr,
   pub merge: MergeMode,
+  /// Parameters declared on the top-level node, like `level=1`.
+  pub params: Vec<BlueprintParam>,
+  /// `abstract=true` and `(meta)` properties on the top-level node.
+  pub meta: BlueprintMeta,
   pub components: Vec<BlueprintElement>,
+  /// Where the top-level node is, for error reporting.
+  pub span: SourceSpan,
+  pub src: Arc<NamedSource>,
+  /// Every file that defined or merged into this blueprint, in order.
+  ///
+  /// This is filled in when it's inserted into a [`BlueprintLibrary`].
+  pub origins: Vec<BlueprintOrigin>,
+}
+
+/// Where a blueprint was loaded from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlueprintOrigin {
+  /// The layer that was on top when it was loaded, if any.
+  pub layer: Option<SmolStr>,
+  pub file: SmolStr,
+}
+
+/// Metadata about a blueprint, for things like spawn tables.
+///
+/// This is `abstract=true` and any props marked with `(meta)` on the
+/// top-level node, like `swamp-goblin biome=(meta)"swamp" rarity=(meta)3`.
+/// Metadata isn't inherited through splices, and isn't a parameter, so
+/// splices can't override it.
+#[derive(Debug, Clone, Default)]
+pub struct BlueprintMeta {
+  pub props: BTreeMap<SmolStr, KdlValue>,
+}
+
+impl BlueprintMeta {
+  pub fn get(&self, key: &str) -> Option<&KdlValue> {
+    self.props.get(key)
+  }
+
+  /// Abstract blueprints can only be spliced in, not instantiated.
+  pub fn is_abstract(&self) -> bool {
+    self
+      .get("abstract")
+      .and_then(KdlValue::as_bool)
+      .unwrap_or(false)
+  }
 }
 
 impl RawBlueprint {
@@ -23,6 +77,7 @@ impl RawBlueprint {
     doc: &KdlDocument,
     src: NamedSource,
   ) -> Result<Vec<RawBlueprint>, RawBlueprintDeserError> {
+    let src = Arc::new(src);
     let mut out = Vec::new();
     for kid in doc.nodes() {
       let comps = match kid.children() {
@@ -31,12 +86,14 @@ impl RawBlueprint {
           return Err(RawBlueprintDeserError {
             span: *kid.span(),
             kind: RawBlueprintParseErrorKind::NoChildren,
-            src,
+            src: src.clone(),
           })
         }
       };
 
       let mut merge = None;
+      let mut params = Vec::<BlueprintParam>::new();
+      let mut meta = BlueprintMeta::default();
       for entry in kid.entries() {
         let key = if let Some(key) = entry.name() {
           key
@@ -44,17 +101,46 @@ impl RawBlueprint {
           return Err(RawBlueprintDeserError {
             span: *entry.span(),
             kind: RawBlueprintParseErrorKind::TopLevelArgument,
-            src,
+            src: src.clone(),
           });
         };
 
-        match key.value() {
-          "merge" => {
+        // Reserved keys can't be annotated, so `abstract=(meta)"yes"` doesn't
+        // sneak past the checks
+        let reserved = matches!(key.value(), "abstract" | "merge");
+        if reserved && entry.ty().is_some() {
+          return Err(RawBlueprintDeserError {
+            span: *entry.span(),
+            kind: RawBlueprintParseErrorKind::TopLevelAnnotation,
+            src: src.clone(),
+          });
+        }
+
+        match (key.value(), entry.ty().map(|ty| ty.value())) {
+          ("abstract", _) => {
+            if entry.value().as_bool().is_none() {
+              return Err(RawBlueprintDeserError {
+                span: *entry.span(),
+                kind: RawBlueprintParseErrorKind::BadAbstract,
+                src: src.clone(),
+              });
+            }
+            let clobbered =
+              meta.props.insert("abstract".into(), entry.value().clone());
+            if clobbered.is_some() {
+              return Err(RawBlueprintDeserError {
+                span: *entry.span(),
+                kind: RawBlueprintParseErrorKind::ClobberMeta,
+                src: src.clone(),
+              });
+            }
+          }
+          ("merge", _) => {
             if merge.is_some() {
               return Err(RawBlueprintDeserError {
                 span: *entry.span(),
                 kind: RawBlueprintParseErrorKind::ClobberInherit,
-                src,
+                src: src.clone(),
               });
             }
 
@@ -64,7 +150,7 @@ impl RawBlueprint {
               return Err(RawBlueprintDeserError {
                 span: *entry.span(),
                 kind: RawBlueprintParseErrorKind::BadMerge,
-                src,
+                src: src.clone(),
               });
             };
             let mode = match mode.to_lowercase().as_str() {
@@ -74,70 +160,64 @@ impl RawBlueprint {
                 return Err(RawBlueprintDeserError {
                   span: *entry.span(),
                   kind: RawBlueprintParseErrorKind::BadMerge,
-                  src,
+                  src: src.clone(),
                 })
               }
             };
             merge = Some(mode);
           }
-          _ => {
+          (name, None | Some("param")) => {
+            if params.iter().any(|param| param.name == name) {
+              return Err(RawBlueprintDeserError {
+                span: *entry.span(),
+                kind: RawBlueprintParseErrorKind::ClobberParam,
+                src: src.clone(),
+              });
+            }
+            params.push(BlueprintParam {
+              name: name.into(),
+              default: entry.value().clone(),
+              span: *entry.span(),
+              src: src.clone(),
+            });
+          }
+          (name, Some("meta")) => {
+            let clobbered =
+              meta.props.insert(name.into(), entry.value().clone());
+            if clobbered.is_some() {
+              return Err(RawBlueprintDeserError {
+                span: *entry.span(),
+                kind: RawBlueprintParseErrorKind::ClobberMeta,
+                src: src.clone(),
+              });
+            }
+          }
+          (_, Some(_)) => {
             return Err(RawBlueprintDeserError {
               span: *entry.span(),
-              kind: RawBlueprintParseErrorKind::InvalidKey,
-              src,
+              kind: RawBlueprintParseErrorKind::TopLevelAnnotation,
+              src: src.clone(),
             })
           }
         }
-
-        // We check down here because it's the "least important" error
-        if entry.ty().is_some() {
-          return Err(RawBlueprintDeserError {
-            span: *entry.span(),
-            kind: RawBlueprintParseErrorKind::TopLevelAnnotation,
-            src,
-          });
-        }
       }
       let merge = merge.unwrap_or_default();
 
-      // We can't use .map here for borrowck reasons
-      let components = {
-        let mut components = Vec::new();
-        for node in comps.nodes() {
-          let entry = match node.ty() {
-            None => BlueprintElement::Component(node.clone()),
-            Some(ann) => {
-              if !node.entries().is_empty() || node.children().is_some() {
-                return Err(RawBlueprintDeserError {
-                  span: *node.span(),
-                  kind: RawBlueprintParseErrorKind::BadAnnotation,
-                  src,
-                });
-              } else {
-                match ann.value() {
-                  "splice" => {
-                    BlueprintElement::Splice(node.name().value().into())
-                  }
-                  _ => {
-                    return Err(RawBlueprintDeserError {
-                      span: *node.span(),
-                      kind: RawBlueprintParseErrorKind::BadAnnotation,
-                      src,
-                    })
-                  }
-                }
-              }
-            }
-          };
-          components.push(entry);
-        }
-        components
-      };
+      let components = comps
+        .nodes()
+        .iter()
+        .map(|node| parse_element(node, &src))
+        .collect::<Result<Vec<_>, _>>()?;
 
       let bp = RawBlueprint {
         name: kid.name().value().into(),
         merge,
+        params,
+        meta,
         components,
+        span: *kid.span(),
+        src: src.clone(),
+        origins: Vec::new(),
       };
       out.push(bp)
     }
@@ -146,65 +226,195 @@ impl RawBlueprint {
   }
 }
 
+/// A parameter declared on a blueprint, like `level=1`.
+#[derive(Clone)]
+pub struct BlueprintParam {
+  pub name: SmolStr,
+  /// The value used when the parameter isn't passed, or `null` if it must be.
+  ///
+  /// Unless it's `null`, passed values must also have the same type.
+  pub default: KdlValue,
+  pub span: SourceSpan,
+  pub src: Arc<NamedSource>,
+}
+
+#[derive(Clone)]
 pub enum BlueprintElement {
   /// Define a new component
-  Component(KdlNode),
-  /// Splice in the contents of another blueprint
-  Splice(SmolStr),
+  Component {
+    node: KdlNode,
+    src: Arc<NamedSource>,
+  },
+  /// Splice in the contents of another blueprint, passing it the props as
+  /// parameters
+  Splice {
+    name: SmolStr,
+    args: Vec<KdlEntry>,
+    span: SourceSpan,
+    src: Arc<NamedSource>,
+  },
+  /// Merge the props and children onto the last component with this name that
+  /// came before, whether it was spliced in or merged
+  Patch {
+    node: KdlNode,
+    src: Arc<NamedSource>,
+  },
+  /// Remove all the components with this name that came before, whether they
+  /// were spliced in or merged
+  Remove {
+    name: SmolStr,
+    span: SourceSpan,
+    src: Arc<NamedSource>,
+  },
+  /// Pick one of the options at random each time it's instantiated, weighted
+  /// by the `choose-weight` props on them
+  Choose {
+    options: Vec<(f64, BlueprintElement)>,
+    span: SourceSpan,
+    src: Arc<NamedSource>,
+  },
+  /// Only include the elements some of the time
+  Maybe {
+    chance: f64,
+    elements: Vec<BlueprintElement>,
+    span: SourceSpan,
+    src: Arc<NamedSource>,
+  },
 }
 
 /// Instructions for instantiating an entity, with all inheritors folded in.
 pub struct RenderedBlueprint {
   pub name: SmolStr,
-  pub components: Vec<KdlNode>,
+  pub components: Vec<RenderedComponent>,
+}
+
+/// One component of a [`RenderedBlueprint`].
+#[derive(Clone)]
+pub struct RenderedComponent {
+  /// The node, with parameters filled in and patches applied.
+  pub node: KdlNode,
+  pub location: ComponentLocation,
+}
+
+/// Where a rendered component came from, for error reporting.
+#[derive(Debug, Clone)]
+pub struct ComponentLocation {
+  /// Where the node is in its file.
+  pub span: SourceSpan,
+  pub src: Arc<NamedSource>,
+  /// The `(splice)`s that brought it in, starting from the blueprint being
+  /// looked up.
+  pub splices: Vec<SpliceSite>,
+}
+
+/// A `(splice)` node that brought in a component.
+#[derive(Debug, Clone, Error, Diagnostic)]
+#[error("spliced in by the blueprint {blueprint}")]
+pub struct SpliceSite {
+  /// The blueprint with the splice in it.
+  pub blueprint: SmolStr,
+  #[label("spliced in here")]
+  pub span: SourceSpan,
+  #[source_code]
+  pub src: Arc<NamedSource>,
 }
 
 /// A library of all the blueprints.
 pub struct BlueprintLibrary {
   /// Map blueprint names to their blueprint.
   prints: AHashMap<SmolStr, RawBlueprint>,
+  /// The layer new blueprints are loaded into.
+  layer: Option<SmolStr>,
+  /// Everything inserted, in order, so it can be merged again when reloading.
+  history: Vec<LoadedFile>,
+}
+
+/// Blueprints inserted all at once.
+struct LoadedFile {
+  /// `None` if they were inserted with [`BlueprintLibrary::insert_raw`].
+  file: Option<SmolStr>,
+  /// Set if it was loaded from disc.
+  path: Option<PathBuf>,
+  layer: Option<SmolStr>,
+  raws: Vec<RawBlueprint>,
 }
 
 impl BlueprintLibrary {
   pub fn new() -> Self {
     Self {
       prints: AHashMap::new(),
+      layer: None,
+      history: Vec::new(),
     }
   }
 
-  pub fn insert_raw(&mut self, blueprint: RawBlueprint) {
-    match self.prints.get_mut(&blueprint.name) {
-      None => {
-        self.prints.insert(blueprint.name.clone(), blueprint);
-      }
-      Some(old) => match blueprint.merge {
-        MergeMode::Clobber => {
-          *old = blueprint;
-        }
-        MergeMode::Merge => {
-          for comp in blueprint.components.into_iter() {
-            let clobberee = match &comp {
-              BlueprintElement::Splice(_) => None,
-              BlueprintElement::Component(new_node) => {
-                // we must have no nodes with the same name
-                old.components.iter_mut().find(|old_comp| {
-                  if let BlueprintElement::Component(it) = old_comp {
-                    it.name() == new_node.name()
-                  } else {
-                    false
-                  }
-                })
-              }
-            };
-            if let Some(clobberee) = clobberee {
-              *clobberee = comp;
-            } else {
-              old.components.push(comp);
-            }
+  /// Start loading blueprints into a new layer, like for a mod.
+  ///
+  /// Layers are just for bookkeeping; blueprints loaded later always merge
+  /// into or clobber earlier ones, so push layers in load order.
+  pub fn push_layer(&mut self, name: &str) {
+    self.layer = Some(name.into());
+  }
+
+  /// Every file that defined or merged into the blueprint, in order.
+  pub fn origins(&self, name: &str) -> Option<&[BlueprintOrigin]> {
+    self.prints.get(name).map(|it| it.origins.as_slice())
+  }
+
+  pub fn meta(&self, name: &str) -> Option<&BlueprintMeta> {
+    self.prints.get(name).map(|it| &it.meta)
+  }
+
+  /// The names of all the blueprints whose metadata matches, sorted.
+  pub fn names_where(
+    &self,
+    mut pred: impl FnMut(&BlueprintMeta) -> bool,
+  ) -> Vec<&str> {
+    let mut out = self
+      .prints
+      .values()
+      .filter(|it| pred(&it.meta))
+      .map(|it| it.name.as_str())
+      .collect::<Vec<_>>();
+    out.sort_unstable();
+    out
+  }
+
+  /// Whether the blueprint splices in the ancestor, directly or through
+  /// other splices.
+  pub fn inherits_from(&self, name: &str, ancestor: &str) -> bool {
+    let mut seen = Vec::<&str>::new();
+    let mut todo = vec![name];
+    while let Some(name) = todo.pop() {
+      let Some(raw) = self.prints.get(name) else {
+        continue;
+      };
+      for element in raw.components.iter() {
+        if let BlueprintElement::Splice { name: parent, .. } = element {
+          if parent == ancestor {
+            return true;
+          }
+          // Don't get stuck in inheritance loops
+          if !seen.contains(&parent.as_str()) {
+            seen.push(parent);
+            todo.push(parent);
           }
         }
-      },
+      }
     }
+    false
+  }
+
+  /// Insert the blueprint, merging into or clobbering any old one with the
+  /// same name.
+  pub fn insert_raw(&mut self, blueprint: RawBlueprint) {
+    self.history.push(LoadedFile {
+      file: None,
+      path: None,
+      layer: self.layer.clone(),
+      raws: vec![blueprint.clone()],
+    });
+    merge_raw(&mut self.prints, blueprint);
   }
 
   /// Insert all the nodes from the given src string.
@@ -213,78 +423,980 @@ impl BlueprintLibrary {
     src: &str,
     filename: &str,
   ) -> Result<(), BlueprintParseError> {
-    let doc = src.parse()?;
-    let source = NamedSource::new(filename, src.to_owned());
-    let raws = RawBlueprint::load_from_kdl(&doc, source)?;
-    for raw in raws {
-      self.insert_raw(raw);
+    self.load_str_from(src, filename, None)
+  }
+
+  fn load_str_from(
+    &mut self,
+    src: &str,
+    filename: &str,
+    path: Option<PathBuf>,
+  ) -> Result<(), BlueprintParseError> {
+    let raws = parse_file(src, filename, &self.layer)?;
+    for raw in raws.iter() {
+      merge_raw(&mut self.prints, raw.clone());
+    }
+    self.history.push(LoadedFile {
+      file: Some(filename.into()),
+      path,
+      layer: self.layer.clone(),
+      raws,
+    });
+
+    Ok(())
+  }
+
+  /// Reload a file loaded earlier, replacing all the blueprints it added.
+  ///
+  /// Everything is merged again in the order it was first loaded in, so files
+  /// loaded after this one still override it, and it stays in its old layer.
+  /// If the new version has errors, the old version is kept.
+  ///
+  /// If more than one file was loaded with this name, like in different
+  /// layers, only the last one is replaced; use
+  /// [`reload_str_in_layer`](Self::reload_str_in_layer) to pick another.
+  /// Files that were never loaded are just loaded like with
+  /// [`load_str`](Self::load_str).
+  pub fn reload_str(
+    &mut self,
+    src: &str,
+    filename: &str,
+  ) -> Result<(), BlueprintParseError> {
+    let idx = self
+      .history
+      .iter()
+      .rposition(|it| it.file.as_deref() == Some(filename));
+    match idx {
+      Some(idx) => self.reload_entries(src, filename, &[idx]),
+      None => self.load_str(src, filename),
+    }
+  }
+
+  /// Reload the file with this name that was loaded into the given layer,
+  /// or before any layer was pushed if it's `None`.
+  ///
+  /// Otherwise, this works like [`reload_str`](Self::reload_str).
+  pub fn reload_str_in_layer(
+    &mut self,
+    src: &str,
+    filename: &str,
+    layer: Option<&str>,
+  ) -> Result<(), BlueprintParseError> {
+    let idx = self.history.iter().rposition(|it| {
+      it.file.as_deref() == Some(filename) && it.layer.as_deref() == layer
+    });
+    match idx {
+      Some(idx) => self.reload_entries(src, filename, &[idx]),
+      None => {
+        let layer = std::mem::replace(&mut self.layer, layer.map(Into::into));
+        let res = self.load_str(src, filename);
+        self.layer = layer;
+        res
+      }
+    }
+  }
+
+  /// Read the file from disc again and [reload](Self::reload_str) it.
+  ///
+  /// If the file was loaded more than once, like into different layers, each
+  /// of them is reloaded.
+  pub fn reload_file(&mut self, path: &Path) -> Result<(), BlueprintLoadError> {
+    let src = read_file(path)?;
+    let filename = path.to_string_lossy();
+    let idxs = self
+      .history
+      .iter()
+      .enumerate()
+      .filter(|(_, it)| it.path.as_deref() == Some(path))
+      .map(|(idx, _)| idx)
+      .collect::<Vec<_>>();
+    if idxs.is_empty() {
+      self.load_str_from(&src, &filename, Some(path.to_owned()))?;
+    } else {
+      self.reload_entries(&src, &filename, &idxs)?;
+    }
+    Ok(())
+  }
+
+  /// Replace what was loaded at these points in the history, and merge
+  /// everything again.
+  fn reload_entries(
+    &mut self,
+    src: &str,
+    filename: &str,
+    idxs: &[usize],
+  ) -> Result<(), BlueprintParseError> {
+    // Parse them all first so nothing changes if there's an error
+    let raws = idxs
+      .iter()
+      .map(|idx| parse_file(src, filename, &self.history[*idx].layer))
+      .collect::<Result<Vec<_>, _>>()?;
+    for (idx, raws) in idxs.iter().zip(raws) {
+      self.history[*idx].raws = raws;
     }
 
+    self.prints.clear();
+    for loaded in self.history.iter() {
+      for raw in loaded.raws.iter() {
+        merge_raw(&mut self.prints, raw.clone());
+      }
+    }
+    Ok(())
+  }
+
+  /// All the files loaded from disc, in load order.
+  pub fn paths(&self) -> impl Iterator<Item = &Path> {
+    self.history.iter().filter_map(|it| it.path.as_deref())
+  }
+
+  /// Load all the `.kdl` files in the directory and its subdirectories,
+  /// sorted by path so the load order is always the same.
+  ///
+  /// If a file fails to load, the files before it stay loaded.
+  pub fn load_dir(&mut self, dir: &Path) -> Result<(), BlueprintLoadError> {
+    fn walk(
+      dir: &Path,
+      seen: &mut Vec<PathBuf>,
+      out: &mut Vec<PathBuf>,
+    ) -> io::Result<()> {
+      // Symlinks can make loops, so don't go in the same directory twice
+      let real = fs::canonicalize(dir)?;
+      if seen.contains(&real) {
+        return Ok(());
+      }
+      seen.push(real);
+
+      for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+          walk(&path, seen, out)?;
+        } else if path.extension().is_some_and(|ext| ext == "kdl") {
+          out.push(path);
+        }
+      }
+      Ok(())
+    }
+
+    let mut paths = Vec::new();
+    walk(dir, &mut Vec::new(), &mut paths).map_err(|err| {
+      BlueprintLoadError::Io {
+        path: dir.to_owned(),
+        err,
+      }
+    })?;
+    paths.sort();
+    self.load_paths(paths)
+  }
+
+  /// Load each file in order. Directories are loaded like [`load_dir`].
+  ///
+  /// [`load_dir`]: Self::load_dir
+  pub fn load_paths<P: AsRef<Path>>(
+    &mut self,
+    paths: impl IntoIterator<Item = P>,
+  ) -> Result<(), BlueprintLoadError> {
+    for path in paths {
+      let path = path.as_ref();
+      if path.is_dir() {
+        self.load_dir(path)?;
+        continue;
+      }
+      let src = read_file(path)?;
+      self.load_str_from(
+        &src,
+        &path.to_string_lossy(),
+        Some(path.to_owned()),
+      )?;
+    }
     Ok(())
   }
 
   /// Attempt to lookup a blueprint in the library and form it into a `KdlNode`.
+  ///
+  /// Any parameters the blueprint declares get their default values.
   pub fn lookup(
     &self,
     name: &str,
   ) -> Result<RenderedBlueprint, BlueprintLookupError> {
-    fn recurse(
-      lib: &BlueprintLibrary,
-      name: &SmolStr,
-      path: Vec<SmolStr>,
-    ) -> Result<Vec<KdlNode>, BlueprintLookupError> {
-      let raw = lib.prints.get(name).ok_or_else(|| match path.as_slice() {
-        [] => BlueprintLookupError::BlueprintNotFound(name.clone()),
-        [.., last] => {
-          BlueprintLookupError::InheriteeNotFound(last.clone(), name.clone())
+    self.lookup_with(name, &[])
+  }
+
+  /// Lookup a blueprint, passing it some parameters.
+  ///
+  /// In blueprints that declare parameters, strings with `{expr}` templates
+  /// in them get filled in, both in components and in the arguments to
+  /// `(splice)`s. Blueprints without parameters are left alone.
+  ///
+  /// `(choose)` and `(maybe)` are rolled with a fresh RNG; use
+  /// [`lookup_with_rng`](Self::lookup_with_rng) to control it.
+  pub fn lookup_with(
+    &self,
+    name: &str,
+    params: &[(&str, KdlValue)],
+  ) -> Result<RenderedBlueprint, BlueprintLookupError> {
+    self.lookup_with_rng(name, params, &fastrand::Rng::new())
+  }
+
+  /// Lookup a blueprint, passing it some parameters and rolling its
+  /// `(choose)` and `(maybe)` elements with the given RNG.
+  pub fn lookup_with_rng(
+    &self,
+    name: &str,
+    params: &[(&str, KdlValue)],
+    rng: &fastrand::Rng,
+  ) -> Result<RenderedBlueprint, BlueprintLookupError> {
+    let args = params
+      .iter()
+      .map(|(name, value)| Arg {
+        name: (*name).into(),
+        value: value.clone(),
+        at: None,
+      })
+      .collect();
+    let components =
+      self.recurse(&name.into(), args, None, Vec::new(), Rolls::Random(rng))?;
+    Ok(RenderedBlueprint {
+      name: name.into(),
+      components,
+    })
+  }
+
+  /// Lookup a blueprint once for every way its `(choose)` and `(maybe)`
+  /// elements could be rolled, stopping after `limit` of them.
+  ///
+  /// This always gives the same results in the same order, so it's good for
+  /// checking blueprints. The first error stops the search.
+  pub fn lookup_every_roll(
+    &self,
+    name: &str,
+    limit: usize,
+  ) -> Result<Vec<RenderedBlueprint>, BlueprintLookupError> {
+    let script = RefCell::new(Script::default());
+    let mut out = Vec::new();
+    loop {
+      let components = self.recurse(
+        &name.into(),
+        Vec::new(),
+        None,
+        Vec::new(),
+        Rolls::Scripted(&script),
+      )?;
+      out.push(RenderedBlueprint {
+        name: name.into(),
+        components,
+      });
+      if out.len() >= limit || !script.borrow_mut().advance() {
+        return Ok(out);
+      }
+    }
+  }
+
+  /// Get a blueprint as it was loaded, with all the files merged in but
+  /// nothing spliced in.
+  pub fn get_raw(&self, name: &str) -> Option<&RawBlueprint> {
+    self.prints.get(name)
+  }
+
+  /// Iterate over all the blueprints as they were loaded, in no particular
+  /// order.
+  pub fn iter_raw(&self) -> impl Iterator<Item = &RawBlueprint> {
+    self.prints.values()
+  }
+
+  fn recurse(
+    &self,
+    name: &SmolStr,
+    args: Vec<Arg>,
+    caller: Option<(SourceSpan, Arc<NamedSource>)>,
+    path: Vec<SmolStr>,
+    rolls: Rolls,
+  ) -> Result<Vec<RenderedComponent>, BlueprintLookupError> {
+    let raw =
+      self
+        .prints
+        .get(name)
+        .ok_or_else(|| match (path.last(), &caller) {
+          (Some(last), Some((span, src))) => {
+            BlueprintLookupError::InheriteeNotFound(
+              last.clone(),
+              name.clone(),
+              *span,
+              src.clone(),
+            )
+          }
+          _ => BlueprintLookupError::BlueprintNotFound(name.clone()),
+        })?;
+    let env = raw.bind_params(args, caller)?;
+    let templated = !raw.params.is_empty();
+
+    let mut out = Vec::new();
+    let ctx = RenderCtx {
+      name,
+      env: templated.then_some(&env),
+      path: &path,
+      rolls,
+    };
+    self.render_elements(&raw.components, ctx, &mut out)?;
+    Ok(out)
+  }
+
+  fn render_elements(
+    &self,
+    elements: &[BlueprintElement],
+    ctx: RenderCtx,
+    out: &mut Vec<RenderedComponent>,
+  ) -> Result<(), BlueprintLookupError> {
+    let RenderCtx {
+      name,
+      env,
+      path,
+      rolls,
+    } = ctx;
+    for comp in elements {
+      match comp {
+        BlueprintElement::Component { node, src } => {
+          let mut node = node.clone();
+          if let Some(env) = env {
+            fill_node(&mut node, env, src)?;
+          }
+          out.push(RenderedComponent {
+            location: ComponentLocation {
+              span: *node.span(),
+              src: src.clone(),
+              splices: Vec::new(),
+            },
+            node,
+          });
+        }
+        BlueprintElement::Patch { node: patch, src } => {
+          let mut patch = patch.clone();
+          if let Some(env) = env {
+            fill_node(&mut patch, env, src)?;
+          }
+          let target = out
+            .iter_mut()
+            .rev()
+            .find(|it| it.node.name().value() == patch.name().value())
+            .ok_or_else(|| BlueprintLookupError::NothingToPatch {
+              blueprint: name.clone(),
+              component: patch.name().value().into(),
+              span: *patch.span(),
+              src: src.clone(),
+            })?;
+          patch_node(&mut target.node, &patch);
+        }
+        BlueprintElement::Remove { name, .. } => {
+          out.retain(|it| it.node.name().value() != name.as_str());
         }
-      })?;
-      let mut out = Vec::new();
-      for comp in raw.components.iter() {
-        match comp {
-          BlueprintElement::Component(node) => {
-            out.push(node.clone());
+        BlueprintElement::Splice {
+          name: parent_name,
+          args,
+          span,
+          src,
+        } => {
+          // Check for loops
+          if let Some(ono) = path
+            .iter()
+            .enumerate()
+            .find_map(|(idx, kid)| (kid == parent_name).then_some(idx))
+          {
+            let mut problem = path[ono..].to_vec();
+            // Push the current one ...
+            problem.push(name.clone());
+            // and the start of the loop
+            problem.push(path[ono].clone());
+            return Err(BlueprintLookupError::InheritanceLoop(
+              problem,
+              *span,
+              src.clone(),
+            ));
           }
-          BlueprintElement::Splice(parent_name) => {
-            // Check for loops
-            if let Some(ono) = path
-              .iter()
-              .enumerate()
-              .find_map(|(idx, kid)| (kid == parent_name).then_some(idx))
-            {
-              let mut problem = path[ono..].to_vec();
-              // Push the current one ...
-              problem.push(name.clone());
-              // and the start of the loop
-              problem.push(path[ono].clone());
-              return Err(BlueprintLookupError::InheritanceLoop(problem));
+
+          let mut splice_args = Vec::new();
+          for entry in args {
+            let mut entry = entry.clone();
+            if let Some(env) = env {
+              fill_entry(&mut entry, env, src)?;
             }
+            splice_args.push(Arg {
+              // We checked they're all props when loading
+              name: entry.name().unwrap().value().into(),
+              value: entry.value().clone(),
+              at: Some((*entry.span(), src.clone())),
+            });
+          }
 
-            let mut path2 = path.clone();
-            path2.push(name.clone());
-            let to_splice = recurse(lib, parent_name, path2)?;
+          let mut path2 = path.to_vec();
+          path2.push(name.clone());
+          let to_splice = self.recurse(
+            parent_name,
+            splice_args,
+            Some((*span, src.clone())),
+            path2,
+            rolls,
+          )?;
 
-            out.extend(to_splice);
+          out.extend(to_splice.into_iter().map(|mut comp| {
+            comp.location.splices.insert(
+              0,
+              SpliceSite {
+                blueprint: name.clone(),
+                span: *span,
+                src: src.clone(),
+              },
+            );
+            comp
+          }));
+        }
+        BlueprintElement::Choose { options, .. } => {
+          if let Some(picked) = rolls.choose(options) {
+            self.render_elements(std::slice::from_ref(picked), ctx, out)?;
+          }
+        }
+        BlueprintElement::Maybe {
+          chance, elements, ..
+        } => {
+          if rolls.maybe(*chance) {
+            self.render_elements(elements, ctx, out)?;
           }
         }
       }
+    }
+
+    Ok(())
+  }
+}
+
+/// Things that stay the same while rendering one blueprint.
+#[derive(Clone, Copy)]
+struct RenderCtx<'a> {
+  name: &'a SmolStr,
+  /// Only set if the blueprint declares parameters.
+  env: Option<&'a Env>,
+  path: &'a [SmolStr],
+  rolls: Rolls<'a>,
+}
 
-      Ok(out)
+/// How `(choose)` and `(maybe)` get decided.
+#[derive(Clone, Copy)]
+enum Rolls<'a> {
+  Random(&'a fastrand::Rng),
+  /// Go through every combination, one lookup at a time.
+  Scripted(&'a RefCell<Script>),
+}
+
+impl<'a> Rolls<'a> {
+  fn choose(
+    self,
+    options: &'a [(f64, BlueprintElement)],
+  ) -> Option<&'a BlueprintElement> {
+    match self {
+      Rolls::Random(rng) => {
+        let total = options.iter().map(|(weight, _)| weight).sum::<f64>();
+        let mut roll = rng.f64() * total;
+        // Fall back to the last one in case of rounding errors
+        options
+          .iter()
+          .find(|(weight, _)| {
+            roll -= weight;
+            roll < 0.0
+          })
+          .or(options.last())
+          .map(|(_, picked)| picked)
+      }
+      Rolls::Scripted(script) => {
+        let idx = script.borrow_mut().pick(options.len());
+        options.get(idx).map(|(_, picked)| picked)
+      }
     }
+  }
 
-    let smol_name = name.into();
-    let components = recurse(self, &smol_name, Vec::new())?;
-    Ok(RenderedBlueprint {
-      name: name.into(),
-      components,
-    })
+  fn maybe(self, chance: f64) -> bool {
+    match self {
+      Rolls::Random(rng) => rng.f64() < chance,
+      // No point trying what can't happen
+      Rolls::Scripted(_) if chance <= 0.0 => false,
+      Rolls::Scripted(_) if chance >= 1.0 => true,
+      Rolls::Scripted(script) => script.borrow_mut().pick(2) == 0,
+    }
   }
 }
 
+/// The decisions made so far while going through every combination of rolls.
+///
+/// Each lookup replays the decisions, and new ones pick the first option.
+/// Then the last decision with options left is moved on to its next one, and
+/// everything after it is forgotten, like an odometer.
+#[derive(Default)]
+struct Script {
+  /// The option picked and how many there were, for each decision.
+  decisions: Vec<(usize, usize)>,
+  next: usize,
+}
+
+impl Script {
+  fn pick(&mut self, count: usize) -> usize {
+    if self.next == self.decisions.len() {
+      self.decisions.push((0, count));
+    }
+    let (picked, _) = self.decisions[self.next];
+    self.next += 1;
+    picked
+  }
+
+  /// Move on to the next combination, or return `false` if there are none
+  /// left.
+  fn advance(&mut self) -> bool {
+    self.decisions.truncate(self.next);
+    self.next = 0;
+    while let Some((picked, count)) = self.decisions.pop() {
+      if picked + 1 < count {
+        self.decisions.push((picked + 1, count));
+        return true;
+      }
+    }
+    false
+  }
+}
+
+/// Parse one child of a blueprint, or of a `(choose)` or `(maybe)`.
+fn parse_element(
+  node: &KdlNode,
+  src: &Arc<NamedSource>,
+) -> Result<BlueprintElement, RawBlueprintDeserError> {
+  let error = |span: &SourceSpan, kind| RawBlueprintDeserError {
+    span: *span,
+    kind,
+    src: src.clone(),
+  };
+  let Some(ann) = node.ty() else {
+    return Ok(BlueprintElement::Component {
+      node: node.clone(),
+      src: src.clone(),
+    });
+  };
+
+  // Splices can only have props, which are the arguments
+  let args_ok = node
+    .entries()
+    .iter()
+    .all(|entry| entry.name().is_some() && entry.ty().is_none());
+  let bare = node.entries().is_empty() && node.children().is_none();
+  let element = match ann.value() {
+    "splice" if args_ok && node.children().is_none() => {
+      BlueprintElement::Splice {
+        name: node.name().value().into(),
+        args: node.entries().to_vec(),
+        span: *node.span(),
+        src: src.clone(),
+      }
+    }
+    "patch" => {
+      let mut node = node.clone();
+      *node.ty_mut() = None;
+      BlueprintElement::Patch {
+        node,
+        src: src.clone(),
+      }
+    }
+    "remove" if bare => BlueprintElement::Remove {
+      name: node.name().value().into(),
+      span: *node.span(),
+      src: src.clone(),
+    },
+    "choose" => {
+      let kids = match node.children() {
+        Some(kids) if node.entries().is_empty() && !kids.nodes().is_empty() => {
+          kids
+        }
+        _ => {
+          return Err(error(node.span(), RawBlueprintParseErrorKind::BadChoose))
+        }
+      };
+      let mut options = Vec::new();
+      for kid in kids.nodes() {
+        let mut kid = kid.clone();
+        let mut weight = 1.0;
+        let mut bad_weight = None;
+        kid.entries_mut().retain(|entry| {
+          let is_weight =
+            entry.name().is_some_and(|it| it.value() == "choose-weight");
+          if !is_weight {
+            return true;
+          }
+          match as_number(entry.value()) {
+            Some(it) if it > 0.0 => weight = it,
+            _ => bad_weight = Some(*entry.span()),
+          }
+          false
+        });
+        if let Some(span) = bad_weight {
+          return Err(error(&span, RawBlueprintParseErrorKind::BadChoose));
+        }
+        options.push((weight, parse_element(&kid, src)?));
+      }
+      BlueprintElement::Choose {
+        options,
+        span: *node.span(),
+        src: src.clone(),
+      }
+    }
+    "maybe" => {
+      let chance = match node.entries() {
+        [entry] if entry.name().is_some_and(|it| it.value() == "chance") => {
+          match as_number(entry.value()) {
+            Some(it) if (0.0..=1.0).contains(&it) => it,
+            _ => {
+              return Err(error(
+                entry.span(),
+                RawBlueprintParseErrorKind::BadMaybe,
+              ))
+            }
+          }
+        }
+        _ => {
+          return Err(error(node.span(), RawBlueprintParseErrorKind::BadMaybe))
+        }
+      };
+      let Some(kids) = node.children() else {
+        return Err(error(node.span(), RawBlueprintParseErrorKind::BadMaybe));
+      };
+      let elements = kids
+        .nodes()
+        .iter()
+        .map(|kid| parse_element(kid, src))
+        .collect::<Result<Vec<_>, _>>()?;
+      BlueprintElement::Maybe {
+        chance,
+        elements,
+        span: *node.span(),
+        src: src.clone(),
+      }
+    }
+    _ => {
+      return Err(error(
+        node.span(),
+        RawBlueprintParseErrorKind::BadAnnotation,
+      ))
+    }
+  };
+  Ok(element)
+}
+
+fn as_number(value: &KdlValue) -> Option<f64> {
+  value
+    .as_f64()
+    .or_else(|| value.as_i64().map(|it| it as f64))
+}
+
+/// Merge the blueprint into or clobber the old one with the same name.
+fn merge_raw(
+  prints: &mut AHashMap<SmolStr, RawBlueprint>,
+  blueprint: RawBlueprint,
+) {
+  match prints.get_mut(&blueprint.name) {
+    None => {
+      prints.insert(blueprint.name.clone(), blueprint);
+    }
+    Some(old) => match blueprint.merge {
+      MergeMode::Clobber => {
+        *old = blueprint;
+      }
+      MergeMode::Merge => {
+        old.origins.extend(blueprint.origins);
+        old.meta.props.extend(blueprint.meta.props);
+        for param in blueprint.params.into_iter() {
+          match old.params.iter_mut().find(|it| it.name == param.name) {
+            Some(clobberee) => *clobberee = param,
+            None => old.params.push(param),
+          }
+        }
+        for comp in blueprint.components.into_iter() {
+          let clobberee = match &comp {
+            BlueprintElement::Splice { .. }
+            | BlueprintElement::Choose { .. }
+            | BlueprintElement::Maybe { .. } => None,
+            BlueprintElement::Remove { name, .. } => {
+              old.components.retain(|old_comp| {
+                !matches!(old_comp, BlueprintElement::Component { node, .. }
+                  if node.name().value() == name.as_str())
+              });
+              // Keep it around in case the old blueprint splices in
+              // something with that name
+              None
+            }
+            BlueprintElement::Patch { node: patch, .. } => {
+              // Patch the old component directly, unless something spliced
+              // or rolled in after it might have the same name
+              let target = old
+                .components
+                .iter()
+                .rposition(|old_comp| {
+                  matches!(old_comp, BlueprintElement::Component { node, .. }
+                    if node.name().value() == patch.name().value())
+                })
+                .filter(|idx| {
+                  !old.components[*idx..].iter().any(|it| {
+                    matches!(
+                      it,
+                      BlueprintElement::Splice { .. }
+                        | BlueprintElement::Choose { .. }
+                        | BlueprintElement::Maybe { .. }
+                    )
+                  })
+                });
+              if let Some(idx) = target {
+                if let BlueprintElement::Component { node, .. } =
+                  &mut old.components[idx]
+                {
+                  patch_node(node, patch);
+                }
+                continue;
+              }
+              None
+            }
+            BlueprintElement::Component { node: new_node, .. } => {
+              // Restating the whole component overrides old patches to it
+              old.components.retain(|old_comp| {
+                !matches!(old_comp, BlueprintElement::Patch { node, .. }
+                  if node.name().value() == new_node.name().value())
+              });
+              // we must have no nodes with the same name
+              old.components.iter_mut().find(|old_comp| {
+                if let BlueprintElement::Component { node: it, .. } = old_comp {
+                  it.name() == new_node.name()
+                } else {
+                  false
+                }
+              })
+            }
+          };
+          if let Some(clobberee) = clobberee {
+            *clobberee = comp;
+          } else {
+            old.components.push(comp);
+          }
+        }
+      }
+    },
+  }
+}
+
+/// Parse a file's blueprints, marking where they came from.
+fn parse_file(
+  src: &str,
+  filename: &str,
+  layer: &Option<SmolStr>,
+) -> Result<Vec<RawBlueprint>, BlueprintParseError> {
+  let doc = src.parse()?;
+  // Say which layer it's from in error messages
+  let display_name = match layer {
+    Some(layer) => format!("{}: {}", layer, filename),
+    None => filename.to_owned(),
+  };
+  let source = NamedSource::new(display_name, src.to_owned());
+  let mut raws = RawBlueprint::load_from_kdl(&doc, source)?;
+  for raw in raws.iter_mut() {
+    raw.origins = vec![BlueprintOrigin {
+      layer: layer.clone(),
+      file: filename.into(),
+    }];
+  }
+  Ok(raws)
+}
+
+fn read_file(path: &Path) -> Result<String, BlueprintLoadError> {
+  fs::read_to_string(path).map_err(|err| BlueprintLoadError::Io {
+    path: path.to_owned(),
+    err,
+  })
+}
+
+/// A value passed to a blueprint parameter.
+struct Arg {
+  name: SmolStr,
+  value: KdlValue,
+  /// Where it was passed from, if it was passed from a splice
+  at: Option<(SourceSpan, Arc<NamedSource>)>,
+}
+
+impl RawBlueprint {
+  /// Match up the passed values with the declared parameters.
+  fn bind_params(
+    &self,
+    args: Vec<Arg>,
+    caller: Option<(SourceSpan, Arc<NamedSource>)>,
+  ) -> Result<Env, BlueprintParamError> {
+    let mut env = Env::default();
+    for arg in args {
+      let (span, src) = arg
+        .at
+        .clone()
+        .unwrap_or_else(|| (self.span, self.src.clone()));
+      let param = match self.params.iter().find(|it| it.name == arg.name) {
+        Some(it) => it,
+        None => {
+          return Err(BlueprintParamError {
+            span,
+            src,
+            kind: BlueprintParamErrorKind::Unknown {
+              blueprint: self.name.clone(),
+              param: arg.name,
+            },
+          })
+        }
+      };
+      let value = match (&param.default, arg.value) {
+        (KdlValue::Null, value) => value,
+        // Integers are fine where floats are expected
+        (default, value)
+          if default.as_f64().is_some() && value.as_i64().is_some() =>
+        {
+          KdlValue::Base10Float(value.as_i64().unwrap() as f64)
+        }
+        (default, value) if type_name(default) == type_name(&value) => value,
+        (default, value) => {
+          // Point at the declaration if there's nowhere better
+          let (span, src) =
+            arg.at.unwrap_or_else(|| (param.span, param.src.clone()));
+          return Err(BlueprintParamError {
+            span,
+            src,
+            kind: BlueprintParamErrorKind::WrongType {
+              param: arg.name,
+              expected: type_name(default),
+              found: type_name(&value),
+            },
+          });
+        }
+      };
+      env.insert(arg.name, value);
+    }
+
+    for param in self.params.iter() {
+      if env.contains_key(&param.name) {
+        continue;
+      }
+      if param.default.is_null() {
+        let (span, src) = caller
+          .clone()
+          .unwrap_or_else(|| (param.span, param.src.clone()));
+        return Err(BlueprintParamError {
+          span,
+          src,
+          kind: BlueprintParamErrorKind::Missing {
+            blueprint: self.name.clone(),
+            param: param.name.clone(),
+          },
+        });
+      }
+      env.insert(param.name.clone(), param.default.clone());
+    }
+
+    Ok(env)
+  }
+}
+
+fn type_name(value: &KdlValue) -> &'static str {
+  if value.as_i64().is_some() {
+    "an integer"
+  } else if value.as_f64().is_some() {
+    "a float"
+  } else if value.is_string_value() {
+    "a string"
+  } else if value.as_bool().is_some() {
+    "a boolean"
+  } else {
+    "null"
+  }
+}
+
+/// Merge a `(patch)` node onto the node it patches.
+///
+/// Args replace all the old args, if there are any. Props replace old props
+/// with the same key, or are added. Children are patched onto the last old
+/// child with the same name, or are added.
+fn patch_node(node: &mut KdlNode, patch: &KdlNode) {
+  let args = patch
+    .entries()
+    .iter()
+    .filter(|entry| entry.name().is_none())
+    .cloned()
+    .collect::<Vec<_>>();
+  if !args.is_empty() {
+    let entries = node.entries_mut();
+    entries.retain(|entry| entry.name().is_some());
+    entries.splice(0..0, args);
+  }
+
+  for prop in patch.entries().iter().filter(|it| it.name().is_some()) {
+    let entries = node.entries_mut();
+    let key = prop.name().map(|it| it.value());
+    match entries
+      .iter_mut()
+      .rev()
+      .find(|it| it.name().map(|it| it.value()) == key)
+    {
+      Some(old) => *old = prop.clone(),
+      None => entries.push(prop.clone()),
+    }
+  }
+
+  if let Some(kids) = patch.children() {
+    let old_kids = node.ensure_children().nodes_mut();
+    for kid in kids.nodes() {
+      match old_kids
+        .iter_mut()
+        .rev()
+        .find(|it| it.name().value() == kid.name().value())
+      {
+        Some(old) => patch_node(old, kid),
+        None => old_kids.push(kid.clone()),
+      }
+    }
+  }
+}
+
+/// Fill in the templates in all the entries of the node and its children.
+fn fill_node(
+  node: &mut KdlNode,
+  env: &Env,
+  src: &Arc<NamedSource>,
+) -> Result<(), BlueprintParamError> {
+  for entry in node.entries_mut() {
+    fill_entry(entry, env, src)?;
+  }
+  if let Some(kids) = node.children_mut() {
+    for kid in kids.nodes_mut() {
+      fill_node(kid, env, src)?;
+    }
+  }
+  Ok(())
+}
+
+fn fill_entry(
+  entry: &mut KdlEntry,
+  env: &Env,
+  src: &Arc<NamedSource>,
+) -> Result<(), BlueprintParamError> {
+  let value = match entry.value() {
+    KdlValue::String(s) if template::is_template(s) => template::render(s, env)
+      .map_err(|msg| BlueprintParamError {
+        span: *entry.span(),
+        src: src.clone(),
+        kind: BlueprintParamErrorKind::Template(msg),
+      })?,
+    _ => return Ok(()),
+  };
+  entry.set_value(value);
+  Ok(())
+}
+
 /// How to handle this blueprint if there's another node with the same name.
 ///
-/// When merging blueprints you can only change the old blueprint's components;
-/// its inheritor, etc are unchangeable once the blueprint is inserted.
+/// When merging blueprints you can only change the old blueprint's components
+/// and parameters; its inheritor, etc are unchangeable once the blueprint is
+/// inserted. Parameters work like components.
 #[derive(Debug, Clone, Copy, Default)]
 pub enum MergeMode {
   /// Merge this node with the old node. This is the default behavior.
@@ -292,6 +1404,10 @@ pub enum MergeMode {
   /// - For components both nodes have, this node's components clobber the old ones.
   /// - For components only this node has, they are all placed after the old nodes.
   /// - Components only the old node has are kept.
+  /// - `(patch)` nodes are merged onto the old node's components with that name,
+  ///   or kept to patch what it splices in.
+  /// - `(remove)` nodes remove the old node's components with that name,
+  ///   including any it splices in.
   #[default]
   Merge,
   /// Completely replace the old node.
@@ -299,16 +1415,121 @@ pub enum MergeMode {
 }
 
 /// Problems when looking up a blueprint.
-#[derive(Debug, Error, PartialEq, Eq)]
+///
+/// Errors compare equal if they're about the same things at the same spans;
+/// the source code itself isn't compared.
+#[derive(Debug, Error, Diagnostic)]
 pub enum BlueprintLookupError {
   #[error("the entrypoint blueprint {0} was not found")]
   BlueprintNotFound(SmolStr),
   #[error("when trying to inherit from another blueprint, the following loop was found: {0:?}")]
-  InheritanceLoop(Vec<SmolStr>),
+  InheritanceLoop(
+    Vec<SmolStr>,
+    #[label("this splice makes a loop")] SourceSpan,
+    #[source_code] Arc<NamedSource>,
+  ),
   #[error(
         "the blueprint {0} tried to inherit from the blueprint {1} but the second was not found"
     )]
-  InheriteeNotFound(SmolStr, SmolStr),
+  InheriteeNotFound(
+    SmolStr,
+    SmolStr,
+    #[label("spliced in here")] SourceSpan,
+    #[source_code] Arc<NamedSource>,
+  ),
+  #[error("the blueprint {blueprint} tried to patch the component {component} but it didn't have one")]
+  NothingToPatch {
+    blueprint: SmolStr,
+    component: SmolStr,
+    #[label("nothing to patch")]
+    span: SourceSpan,
+    #[source_code]
+    src: Arc<NamedSource>,
+  },
+  #[error(transparent)]
+  #[diagnostic(transparent)]
+  Param(#[from] BlueprintParamError),
+}
+
+impl PartialEq for BlueprintLookupError {
+  fn eq(&self, other: &Self) -> bool {
+    use BlueprintLookupError as E;
+    match (self, other) {
+      (E::BlueprintNotFound(a), E::BlueprintNotFound(b)) => a == b,
+      (E::InheritanceLoop(a, a_span, _), E::InheritanceLoop(b, b_span, _)) => {
+        a == b && a_span == b_span
+      }
+      (
+        E::InheriteeNotFound(a, a_parent, a_span, _),
+        E::InheriteeNotFound(b, b_parent, b_span, _),
+      ) => a == b && a_parent == b_parent && a_span == b_span,
+      (
+        E::NothingToPatch {
+          blueprint: a,
+          component: a_comp,
+          span: a_span,
+          ..
+        },
+        E::NothingToPatch {
+          blueprint: b,
+          component: b_comp,
+          span: b_span,
+          ..
+        },
+      ) => a == b && a_comp == b_comp && a_span == b_span,
+      (E::Param(a), E::Param(b)) => a == b,
+      _ => false,
+    }
+  }
+}
+
+impl Eq for BlueprintLookupError {}
+
+/// Problems with the parameters passed to a blueprint.
+#[derive(Debug, Error, Diagnostic)]
+#[error("{kind}")]
+pub struct BlueprintParamError {
+  #[label]
+  pub span: SourceSpan,
+  #[source_code]
+  pub src: Arc<NamedSource>,
+  pub kind: BlueprintParamErrorKind,
+}
+
+impl PartialEq for BlueprintParamError {
+  fn eq(&self, other: &Self) -> bool {
+    self.span == other.span && self.kind == other.kind
+  }
+}
+
+impl Eq for BlueprintParamError {}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum BlueprintParamErrorKind {
+  #[error(
+    "the blueprint {blueprint} needs a value for the parameter `{param}`"
+  )]
+  Missing { blueprint: SmolStr, param: SmolStr },
+  #[error("the blueprint {blueprint} has no parameter named `{param}`")]
+  Unknown { blueprint: SmolStr, param: SmolStr },
+  #[error("the parameter `{param}` should be {expected}, but got {found}")]
+  WrongType {
+    param: SmolStr,
+    expected: &'static str,
+    found: &'static str,
+  },
+  #[error("bad template: {0}")]
+  Template(String),
+}
+
+/// Problems when loading blueprints from disc.
+#[derive(Debug, Error, Diagnostic)]
+pub enum BlueprintLoadError {
+  #[error("could not read {}: {err}", path.display())]
+  Io { path: PathBuf, err: io::Error },
+  #[error(transparent)]
+  #[diagnostic(transparent)]
+  Parse(#[from] BlueprintParseError),
 }
 
 #[derive(Debug, Error)]
@@ -352,13 +1573,14 @@ pub struct RawBlueprintDeserError {
   #[label]
   pub span: SourceSpan,
   #[source_code]
-  pub src: NamedSource,
+  pub src: Arc<NamedSource>,
   pub kind: RawBlueprintParseErrorKind,
 }
 
-const TOP_LEVEL_REQS: &str =
-  r#"only `merge="merge"` or `merge="clobber"` are allowed"#;
-const ANN_REQS: &str = r#"only `(splice)a-blueprint` with no further args/props/children is allowed"#;
+const TOP_LEVEL_REQS: &str = r#"only `merge="merge"` or `merge="clobber"`, `abstract=true`, parameters like `level=1` or `level=(param)1`, and metadata like `biome=(meta)"swamp"` are allowed"#;
+const ANN_REQS: &str = r#"only `(splice)a-blueprint`, optionally with arguments like `level=2` and no further args/children, `(patch)a-component` with the values to change, `(remove)a-component` with no args/props/children, `(choose)`, or `(maybe)`, are allowed"#;
+const CHOOSE_REQS: &str = r#"`(choose)` needs children to pick from and no args/props, and each child can have a positive `choose-weight=2`"#;
+const MAYBE_REQS: &str = r#"`(maybe)` needs children and only a `chance` prop between 0 and 1, like `chance=0.3`"#;
 
 #[derive(Debug, Error)]
 pub enum RawBlueprintParseErrorKind {
@@ -368,14 +1590,22 @@ pub enum RawBlueprintParseErrorKind {
   TopLevelArgument,
   #[error("blueprint node had an annotation; {}", TOP_LEVEL_REQS)]
   TopLevelAnnotation,
-  #[error("blueprint node had an invalid key; {}", TOP_LEVEL_REQS)]
-  InvalidKey,
   #[error(r#"the `merge` key didn't equal "clobber" or "merge""#)]
   BadMerge,
   #[error("redefined `inherit`")]
   ClobberInherit,
   #[error("redefined `merge`")]
   ClobberMerge,
+  #[error("redefined a parameter")]
+  ClobberParam,
+  #[error("redefined a metadata key")]
+  ClobberMeta,
+  #[error("the `abstract` key wasn't `true` or `false`")]
+  BadAbstract,
   #[error("bad annotation; {}", ANN_REQS)]
   BadAnnotation,
+  #[error("bad (choose); {}", CHOOSE_REQS)]
+  BadChoose,
+  #[error("bad (maybe); {}", MAYBE_REQS)]
+  BadMaybe,
 }
@@ -1,9 +1,14 @@
-use std::marker::PhantomData;
+use std::{cell::Cell, marker::PhantomData};
 
 use kdl::KdlNode;
-use serde::de::DeserializeOwned;
+use serde::{
+  de::{DeserializeOwned, Error as _},
+  Deserialize, Deserializer,
+};
 
-use crate::{builder::EntityBuilder, prelude::Component};
+use crate::{
+  builder::EntityBuilder, prelude::Component, vtablesathome::DeserializeFn,
+};
 
 /// Do one step of building an entity from a node. Usually, implementors will:
 /// - Deser a component out of the node
@@ -53,3 +58,42 @@ where
     Ok(builder)
   }
 }
+
+thread_local! {
+  /// The function [`ErasedComponent`] deserializes with.
+  static DESER_FN: Cell<Option<DeserializeFn<dyn Component>>> =
+    const { Cell::new(None) };
+}
+
+/// Load a component out of a node with the `deser` function from its vtable.
+///
+/// This is what the fabricator falls back on for nodes with no factory
+/// registered, when the node's name is a component's friendly name.
+pub(crate) fn component_from_node(
+  node: &KdlNode,
+  deser: DeserializeFn<dyn Component>,
+) -> eyre::Result<Box<dyn Component>> {
+  let old = DESER_FN.with(|it| it.replace(Some(deser)));
+  let res = knurdy::deserialize_node::<ErasedComponent>(node);
+  DESER_FN.with(|it| it.set(old));
+  Ok(res?.0)
+}
+
+/// Knurdy only hands out its deserializer to things that are `Deserialize`,
+/// so this smuggles the vtable's function in through [`DESER_FN`].
+struct ErasedComponent(Box<dyn Component>);
+
+impl<'de> Deserialize<'de> for ErasedComponent {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    let deser = DESER_FN
+      .with(Cell::get)
+      .expect("ErasedComponent can only be loaded by component_from_node");
+    let mut erased = <dyn erased_serde::Deserializer>::erase(deserializer);
+    deser(&mut erased)
+      .map(ErasedComponent)
+      .map_err(D::Error::custom)
+  }
+}
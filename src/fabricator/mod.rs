@@ -4,25 +4,74 @@
 //! Create an empty `EntityFabricator`, add a bunch of KDL files into it,
 //! register factories to teach it how to read different KDL lines into components,
 //! and then build.
+//!
+//! Nodes without a factory are loaded with serde if their name is the friendly
+//! name of a registered component, so factories are only needed for
+//! components with custom loading logic.
+//!
+//! Blueprints can declare parameters with default values as props on their
+//! top-level node, like `goblin level=1 { has-hp start-hp="{level * 10}" }`.
+//! Pass values for them with [`EntityFabricator::instantiate_with`], or from
+//! another blueprint with `(splice)goblin level=3`. A default of `null` means
+//! the parameter has to be passed.
+//!
+//! Every string in a blueprint with parameters is a template, so a literal
+//! brace has to be doubled, like `name "{{level {level}}}"` for `{level 3}`.
+//! Blueprints without parameters are left as they are.
+//!
+//! A blueprint can drop a component it would otherwise get from a splice or an
+//! earlier file with `(remove)component-name`, or change just some of its
+//! values with `(patch)has-hp start-hp=20`.
+//!
+//! `(choose)weapon { sword; axe choose-weight=2; }` picks one of its children
+//! at random each time the blueprint is instantiated, weighted by their
+//! `choose-weight` props (which are taken off before they're loaded, so
+//! components can still have a `weight` of their own), and
+//! `(maybe)hat chance=0.3 { wizard-hat; }` only sometimes includes its
+//! children. The children can be components or `(splice)`s, or even more
+//! `(choose)`s and `(maybe)`s. Seed the RNG with
+//! [`EntityFabricator::seed_rng`] for reproducible results.
+//!
+//! Base blueprints marked `abstract=true` can be spliced in but not
+//! instantiated. Blueprints can also carry metadata, like
+//! `swamp-goblin biome=(meta)"swamp" rarity=(meta)3`, for spawn tables; find
+//! them with [`EntityFabricator::blueprints_where`]. Metadata and parameters
+//! are kept apart: bare props (or ones marked `(param)`) are parameters, and
+//! only `(meta)` props are metadata.
+//!
+//! Call [`EntityFabricator::validate`] after loading everything to find the
+//! problems in every blueprint at once, instead of when instantiating them.
 
 pub mod blueprint;
 pub mod factory;
+mod template;
+mod validate;
+pub mod watch;
 
-use std::collections::BTreeMap;
+pub use validate::{ValidationError, ValidationErrorKind, MAX_VALIDATED_ROLLS};
 
-use blueprint::{BlueprintLibrary, BlueprintLookupError, BlueprintParseError};
+use std::{collections::BTreeMap, fmt::Display, path::Path, sync::Mutex};
+
+use blueprint::{
+  BlueprintLibrary, BlueprintLoadError, BlueprintLookupError, BlueprintMeta,
+  BlueprintOrigin, BlueprintParseError, ComponentLocation, RenderedComponent,
+};
 use factory::ComponentFactory;
 
+use kdl::{KdlNode, KdlValue};
+use miette::{Diagnostic, LabeledSpan, SourceCode};
 use serde::de::DeserializeOwned;
 use smol_str::SmolStr;
 use thiserror::Error;
 
 use crate::{
   builder::EntityBuilder,
-  prelude::{Component, Entity},
+  prelude::{Component, Entity, World},
+  serde::kdl::{entity_to_kdl, same_component, KdlSerdeError},
+  vtablesathome::ComponentVtables,
 };
 
-use self::factory::SerdeComponentFactory;
+use self::factory::{component_from_node, SerdeComponentFactory};
 
 /// A library of blueprints and the ability to instantiate entities from them.
 ///
@@ -32,6 +81,8 @@ pub struct EntityFabricator<Ctx> {
   blueprints: BlueprintLibrary,
   /// Map component names to factories for it.
   factories: BTreeMap<SmolStr, Box<dyn ComponentFactory<Ctx>>>,
+  /// Rolls `(choose)` and `(maybe)` elements.
+  rng: Mutex<fastrand::Rng>,
 }
 
 impl<Ctx> EntityFabricator<Ctx>
@@ -42,9 +93,16 @@ where
     Self {
       blueprints: BlueprintLibrary::new(),
       factories: BTreeMap::new(),
+      rng: Mutex::new(fastrand::Rng::new()),
     }
   }
 
+  /// Reseed the RNG used for `(choose)` and `(maybe)` elements, so the same
+  /// instantiations in the same order give the same entities.
+  pub fn seed_rng(&self, seed: u64) {
+    self.rng.lock().unwrap().seed(seed);
+  }
+
   /// Register a component factory.
   pub fn register<CA: ComponentFactory<Ctx>>(
     &mut self,
@@ -60,6 +118,9 @@ where
   }
 
   /// Convenience function to register a factory that just loads the thing with serde.
+  ///
+  /// Serializable components are already loaded this way under their friendly
+  /// names, so this is only needed to load one under a different name.
   pub fn register_serde<C: DeserializeOwned + Component>(
     &mut self,
     name: &str,
@@ -70,6 +131,7 @@ where
   /// Load the KDL string into the fabricator as a list of blueprints.
   ///
   /// The `filepath` argument is just for error reporting purposes; this doesn't load anything from disc.
+  /// Use [`load_dir`](Self::load_dir) or [`load_paths`](Self::load_paths) for that.
   pub fn load_str(
     &mut self,
     src: &str,
@@ -78,33 +140,187 @@ where
     self.blueprints.load_str(src, filepath)
   }
 
+  /// Load all the `.kdl` files in the directory and its subdirectories, sorted
+  /// by path.
+  pub fn load_dir(
+    &mut self,
+    path: impl AsRef<Path>,
+  ) -> Result<(), BlueprintLoadError> {
+    self.blueprints.load_dir(path.as_ref())
+  }
+
+  /// Load each file (or directory) in order.
+  pub fn load_paths<P: AsRef<Path>>(
+    &mut self,
+    paths: impl IntoIterator<Item = P>,
+  ) -> Result<(), BlueprintLoadError> {
+    self.blueprints.load_paths(paths)
+  }
+
+  /// Start loading blueprints into a new layer, like `"base"` and then
+  /// `"mymod"`.
+  ///
+  /// Later layers merge into or clobber the earlier ones the same way later
+  /// files do; the layer's name shows up in error messages and
+  /// [`blueprint_origins`](Self::blueprint_origins).
+  pub fn push_layer(&mut self, name: &str) {
+    self.blueprints.push_layer(name)
+  }
+
+  /// Every file (and layer) that defined or merged into the blueprint, in
+  /// load order.
+  pub fn blueprint_origins(&self, name: &str) -> Option<&[BlueprintOrigin]> {
+    self.blueprints.origins(name)
+  }
+
+  /// The metadata on the blueprint with the given name, if there is one.
+  pub fn blueprint_meta(&self, name: &str) -> Option<&BlueprintMeta> {
+    self.blueprints.meta(name)
+  }
+
+  /// The names of all the blueprints whose metadata matches, sorted.
+  ///
+  /// Abstract blueprints are included; filter them out with
+  /// [`BlueprintMeta::is_abstract`] if you're going to instantiate them.
+  pub fn blueprints_where(
+    &self,
+    pred: impl FnMut(&BlueprintMeta) -> bool,
+  ) -> Vec<&str> {
+    self.blueprints.names_where(pred)
+  }
+
+  /// Whether the blueprint splices in the ancestor, directly or through
+  /// other splices.
+  pub fn inherits_from(&self, name: &str, ancestor: &str) -> bool {
+    self.blueprints.inherits_from(name, ancestor)
+  }
+
+  /// Reload a file loaded earlier, replacing all the blueprints it added
+  /// but still letting files loaded after it override it.
+  ///
+  /// If the new version has errors, the old version is kept. If more than one
+  /// file was loaded with this name, only the last one is replaced.
+  pub fn reload_str(
+    &mut self,
+    src: &str,
+    filepath: &str,
+  ) -> Result<(), BlueprintParseError> {
+    self.blueprints.reload_str(src, filepath)
+  }
+
+  /// Reload the file with this name that was loaded into the given layer,
+  /// or before any layer was pushed if it's `None`.
+  pub fn reload_str_in_layer(
+    &mut self,
+    src: &str,
+    filepath: &str,
+    layer: Option<&str>,
+  ) -> Result<(), BlueprintParseError> {
+    self.blueprints.reload_str_in_layer(src, filepath, layer)
+  }
+
+  /// Read the file from disc again and [reload](Self::reload_str) it.
+  ///
+  /// See [`watch::BlueprintWatcher`] to do this whenever files change.
+  pub fn reload_file(
+    &mut self,
+    path: impl AsRef<Path>,
+  ) -> Result<(), BlueprintLoadError> {
+    self.blueprints.reload_file(path.as_ref())
+  }
+
+  /// All the files loaded from disc, in load order.
+  pub fn loaded_paths(&self) -> impl Iterator<Item = &Path> {
+    self.blueprints.paths()
+  }
+
   /// Instantiate an entity from a blueprint, adding all the components in that blueprint
   /// to the builder.
   ///
   /// Note that the builder doesn't have to be empty! For example, you might want to add a component for
   /// its position before filling it with other information.
+  ///
+  /// It doesn't even have to be a new entity; pass a builder from [`World::edit`](crate::world::World::edit)
+  /// to apply a blueprint to an entity that already exists.
   pub fn instantiate_to_builder<'a, 'w>(
     &self,
     name: &str,
+    builder: EntityBuilder<'a, 'w>,
+    ctx: &Ctx,
+  ) -> Result<EntityBuilder<'a, 'w>, InstantiationError> {
+    self.instantiate_to_builder_with(name, &[], builder, ctx)
+  }
+
+  /// Like [`instantiate_to_builder`](Self::instantiate_to_builder), but pass
+  /// values for the blueprint's parameters.
+  pub fn instantiate_to_builder_with<'a, 'w>(
+    &self,
+    name: &str,
+    params: &[(&str, KdlValue)],
+    mut builder: EntityBuilder<'a, 'w>,
+    ctx: &Ctx,
+  ) -> Result<EntityBuilder<'a, 'w>, InstantiationError> {
+    if self
+      .blueprints
+      .meta(name)
+      .is_some_and(BlueprintMeta::is_abstract)
+    {
+      return Err(InstantiationError::Abstract(name.into()));
+    }
+    let print = {
+      // Don't hold the lock while assembling, in case a factory instantiates
+      // something too
+      let rng = self.rng.lock().unwrap();
+      self.blueprints.lookup_with_rng(name, params, &rng)?
+    };
+
+    for comp in print.components {
+      builder = self.assemble_node(builder, &comp, ctx)?;
+    }
+
+    Ok(builder)
+  }
+
+  /// Add one component node to the builder.
+  fn assemble_node<'a, 'w>(
+    &self,
     mut builder: EntityBuilder<'a, 'w>,
+    comp: &RenderedComponent,
     ctx: &Ctx,
   ) -> Result<EntityBuilder<'a, 'w>, InstantiationError> {
-    let print = self.blueprints.lookup(name)?;
-
-    for node in print.components {
-      let name = node.name().value();
-      let factory = self
-        .factories
-        .get(name)
-        .ok_or_else(|| InstantiationError::NoAssembler(name.into()))?;
-      builder = factory
-        .assemble(builder, &node, ctx)
-        .map_err(|err| InstantiationError::AssemblerError(name.into(), err))?
+    let node = &comp.node;
+    let name = node.name().value();
+    let assembler_error = |err| {
+      InstantiationError::AssemblerError(
+        name.into(),
+        err,
+        comp.location.clone(),
+      )
+    };
+    if let Some(factory) = self.factories.get(name) {
+      return factory
+        .assemble(builder, node, ctx)
+        .map_err(assembler_error);
     }
 
+    // Fall back on the component with that friendly name
+    let deser = ComponentVtables::try_by_friendly_name(name)
+      .and_then(|vtable| vtable.deser)
+      .ok_or_else(|| {
+        InstantiationError::NoAssembler(name.into(), comp.location.clone())
+      })?;
+    let comp = component_from_node(node, deser).map_err(assembler_error)?;
+    builder.insert_raw(comp);
     Ok(builder)
   }
 
+  /// Whether there's a factory or serializable component with that name.
+  fn can_assemble(&self, name: &str) -> bool {
+    self.factories.contains_key(name)
+      || ComponentVtables::try_by_friendly_name(name)
+        .is_some_and(|vtable| vtable.deser.is_some())
+  }
+
   /// Convenience method to just return the entity off the builder instead of returning it.
   pub fn instantiate<'a, 'w>(
     &self,
@@ -114,15 +330,194 @@ where
   ) -> Result<Entity, InstantiationError> {
     Ok(self.instantiate_to_builder(name, builder, ctx)?.build())
   }
+
+  /// Instantiate an entity, passing values for the blueprint's parameters,
+  /// like `&[("level", 3.into())]`.
+  pub fn instantiate_with<'a, 'w>(
+    &self,
+    name: &str,
+    params: &[(&str, KdlValue)],
+    builder: EntityBuilder<'a, 'w>,
+    ctx: &Ctx,
+  ) -> Result<Entity, InstantiationError> {
+    Ok(
+      self
+        .instantiate_to_builder_with(name, params, builder, ctx)?
+        .build(),
+    )
+  }
+
+  /// Write a live entity back out as a blueprint, like for saving a prefab.
+  ///
+  /// Components are written the same way as [`World::to_kdl`], under their
+  /// friendly names, so they can be loaded back without registering
+  /// anything (unless a custom factory is registered under that name).
+  ///
+  /// If `base` is given, the blueprint splices it in first, then has a
+  /// `(remove)` node for each component the base has but the entity doesn't,
+  /// and then only lists the components that are different from the base's.
+  /// The splice doesn't pass any arguments, so the base is compared with its
+  /// parameters' defaults. Bases with parameters that have to be passed, or
+  /// with `(choose)` or `(maybe)` that could go more than one way, can't be
+  /// compared against and give an error.
+  ///
+  /// The blueprint is named `entity-<index>-<generation>`;
+  /// use [`KdlNode::set_name`] to call it something better.
+  pub fn export_blueprint(
+    &self,
+    world: &World,
+    entity: Entity,
+    base: Option<&str>,
+  ) -> Result<KdlNode, ExportError> {
+    let mut components = entity_to_kdl(world, entity)?;
+
+    let mut elements = Vec::new();
+    if let Some(base) = base {
+      if let Some(raw) = self.blueprints.get_raw(base) {
+        let required = raw
+          .params
+          .iter()
+          .filter(|param| param.default.is_null())
+          .map(|param| param.name.clone())
+          .collect::<Vec<_>>();
+        if !required.is_empty() {
+          return Err(ExportError::RequiredParams(base.into(), required));
+        }
+      }
+      let mut rolls = self.blueprints.lookup_every_roll(base, 2)?;
+      if rolls.len() > 1 {
+        return Err(ExportError::RandomBase(base.into()));
+      }
+      let print = rolls.remove(0);
+
+      let mut splice = KdlNode::new(base);
+      splice.set_ty("splice");
+      elements.push(splice);
+
+      let mut removed = Vec::<&str>::new();
+      for theirs in print.components.iter() {
+        let name = theirs.node.name().value();
+        let ours = components.iter().any(|it| it.name().value() == name);
+        if !ours && !removed.contains(&name) {
+          removed.push(name);
+          let mut remove = KdlNode::new(name);
+          remove.set_ty("remove");
+          elements.push(remove);
+        }
+      }
+
+      // Later nodes clobber earlier ones, so compare against the last one
+      let mut kept = Vec::new();
+      for node in components {
+        let theirs = print
+          .components
+          .iter()
+          .rev()
+          .find(|it| it.node.name().value() == node.name().value());
+        let same = match theirs {
+          Some(theirs) => same_component(&node, &theirs.node)?,
+          None => false,
+        };
+        if !same {
+          kept.push(node);
+        }
+      }
+      components = kept;
+    }
+    elements.extend(components);
+
+    let (idx, gen) = entity.decompose();
+    let mut out = KdlNode::new(format!("entity-{}-{}", idx, gen));
+    *out.ensure_children().nodes_mut() = elements;
+    Ok(out)
+  }
 }
 
 /// Things that can go wrong when instantiating an entity.
+///
+/// As a [`Diagnostic`], problems with a component point at its node, and at
+/// each `(splice)` that brought it in.
 #[derive(Debug, Error)]
 pub enum InstantiationError {
   #[error("while looking up the blueprint: {0}")]
   BlueprintLookupError(#[from] BlueprintLookupError),
-  #[error("there was no assembler registered for a component named {0:?}")]
-  NoAssembler(SmolStr),
+  #[error("the blueprint {0} is abstract, so it can only be spliced in")]
+  Abstract(SmolStr),
+  #[error("there was no assembler registered for a component named {0:?}, and no serializable component has that friendly name")]
+  NoAssembler(SmolStr, ComponentLocation),
   #[error("the assembler for {0:?} gave an error: {1}")]
-  AssemblerError(SmolStr, eyre::Error),
+  AssemblerError(SmolStr, eyre::Error, ComponentLocation),
+}
+
+impl InstantiationError {
+  /// Where the component with the problem came from, if it was a problem with
+  /// a component.
+  pub fn location(&self) -> Option<&ComponentLocation> {
+    match self {
+      InstantiationError::BlueprintLookupError(_)
+      | InstantiationError::Abstract(_) => None,
+      InstantiationError::NoAssembler(_, at)
+      | InstantiationError::AssemblerError(_, _, at) => Some(at),
+    }
+  }
+}
+
+impl Diagnostic for InstantiationError {
+  fn source_code(&self) -> Option<&dyn SourceCode> {
+    match self {
+      InstantiationError::BlueprintLookupError(err) => err.source_code(),
+      _ => self.location().map(|at| &*at.src as &dyn SourceCode),
+    }
+  }
+
+  fn labels(&self) -> Option<Box<dyn Iterator<Item = LabeledSpan> + '_>> {
+    let label = match self {
+      InstantiationError::BlueprintLookupError(err) => return err.labels(),
+      InstantiationError::Abstract(_) => return None,
+      InstantiationError::NoAssembler(..) => "no assembler for this",
+      InstantiationError::AssemblerError(..) => "while assembling this",
+    };
+    let at = self.location()?;
+    Some(Box::new(std::iter::once(LabeledSpan::new_with_span(
+      Some(label.to_owned()),
+      at.span,
+    ))))
+  }
+
+  fn related<'a>(
+    &'a self,
+  ) -> Option<Box<dyn Iterator<Item = &'a dyn Diagnostic> + 'a>> {
+    if let InstantiationError::BlueprintLookupError(err) = self {
+      return err.related();
+    }
+    // Innermost splice first
+    let at = self.location()?;
+    Some(Box::new(
+      at.splices.iter().rev().map(|it| it as &dyn Diagnostic),
+    ))
+  }
+
+  fn help<'a>(&'a self) -> Option<Box<dyn Display + 'a>> {
+    match self {
+      InstantiationError::BlueprintLookupError(err) => err.help(),
+      _ => None,
+    }
+  }
+}
+
+/// Things that can go wrong when exporting an entity as a blueprint.
+#[derive(Debug, Error)]
+pub enum ExportError {
+  #[error("while looking up the base blueprint: {0}")]
+  BlueprintLookupError(#[from] BlueprintLookupError),
+  #[error("while writing the components: {0}")]
+  KdlSerdeError(#[from] KdlSerdeError),
+  #[error(
+    "the base blueprint {0} has parameters that have to be passed: {1:?}"
+  )]
+  RequiredParams(SmolStr, Vec<SmolStr>),
+  #[error(
+    "the base blueprint {0} is random, so there's nothing to compare against"
+  )]
+  RandomBase(SmolStr),
 }
@@ -0,0 +1,312 @@
+//! The tiny expression language used to fill in blueprint parameters.
+//!
+//! Strings like `"{level * 10}"` are templates. Expressions can use
+//! parameters, numbers, strings, parentheses, unary `-`, and `+ - * / %`.
+//! `+` also joins strings together.
+
+use ahash::AHashMap;
+use kdl::KdlValue;
+use smol_str::SmolStr;
+
+/// Parameter names to their values.
+pub(crate) type Env = AHashMap<SmolStr, KdlValue>;
+
+/// Whether the string has anything that [`render`] would touch.
+pub(crate) fn is_template(s: &str) -> bool {
+  s.contains(['{', '}'])
+}
+
+/// Fill in all the `{expr}`s in the string.
+///
+/// If the whole string is a single `{expr}`, the result keeps its type, so
+/// `"{level * 10}"` turns into a number. Otherwise the results are pasted into
+/// the string. `{{` and `}}` are literal braces.
+pub(crate) fn render(template: &str, env: &Env) -> Result<KdlValue, String> {
+  if let Some(inner) = template
+    .strip_prefix('{')
+    .and_then(|it| it.strip_suffix('}'))
+  {
+    if !is_template(inner) {
+      return eval(inner, env);
+    }
+  }
+
+  let mut out = String::new();
+  let mut rest = template;
+  while let Some(idx) = rest.find(['{', '}']) {
+    out.push_str(&rest[..idx]);
+    let brace = &rest[idx..idx + 1];
+    let after = &rest[idx + 1..];
+    if after.starts_with(brace) {
+      out.push_str(brace);
+      rest = &after[1..];
+    } else if brace == "}" {
+      return Err("unmatched `}`; write `}}` for a literal brace".to_owned());
+    } else {
+      let end = after
+        .find('}')
+        .ok_or("unclosed `{`; write `{{` for a literal brace")?;
+      match eval(&after[..end], env)? {
+        KdlValue::String(s) | KdlValue::RawString(s) => out.push_str(&s),
+        other => out.push_str(&other.to_string()),
+      }
+      rest = &after[end + 1..];
+    }
+  }
+  out.push_str(rest);
+  Ok(KdlValue::String(out))
+}
+
+/// Evaluate a single expression.
+fn eval(src: &str, env: &Env) -> Result<KdlValue, String> {
+  let tokens = tokenize(src)?;
+  let mut parser = Parser {
+    tokens: &tokens,
+    pos: 0,
+    env,
+  };
+  let out = parser.expr()?;
+  match parser.tokens.get(parser.pos) {
+    None => Ok(out.into_kdl()),
+    Some(tok) => Err(format!("unexpected {} in `{}`", tok, src.trim())),
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+  Ident(SmolStr),
+  Int(i64),
+  Float(f64),
+  Str(String),
+  Op(char),
+}
+
+impl std::fmt::Display for Token {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Token::Ident(name) => write!(f, "`{}`", name),
+      Token::Int(it) => write!(f, "`{}`", it),
+      Token::Float(it) => write!(f, "`{}`", it),
+      Token::Str(it) => write!(f, "{:?}", it),
+      Token::Op(it) => write!(f, "`{}`", it),
+    }
+  }
+}
+
+fn tokenize(src: &str) -> Result<Vec<Token>, String> {
+  let chars = src.chars().collect::<Vec<_>>();
+  let mut out = Vec::new();
+  let mut i = 0;
+  while i < chars.len() {
+    let c = chars[i];
+    if c.is_whitespace() {
+      i += 1;
+    } else if c.is_ascii_digit() {
+      let start = i;
+      while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+        i += 1;
+      }
+      let num = chars[start..i].iter().collect::<String>();
+      let tok = if num.contains('.') {
+        num.parse().map(Token::Float).ok()
+      } else {
+        num.parse().map(Token::Int).ok()
+      };
+      out.push(tok.ok_or_else(|| format!("bad number `{}`", num))?);
+    } else if c.is_alphabetic() || c == '_' {
+      // KDL names are usually kebab-case, so a dash followed by a letter
+      // continues the name. Put spaces around `-` to subtract parameters.
+      let start = i;
+      while i < chars.len()
+        && (chars[i].is_alphanumeric()
+          || chars[i] == '_'
+          || (chars[i] == '-'
+            && chars.get(i + 1).is_some_and(|c| c.is_alphabetic())))
+      {
+        i += 1;
+      }
+      out.push(Token::Ident(
+        chars[start..i].iter().collect::<String>().into(),
+      ));
+    } else if c == '\'' {
+      // Single quotes, because double quotes would need escaping in KDL
+      let start = i + 1;
+      let end = chars[start..]
+        .iter()
+        .position(|c| *c == '\'')
+        .ok_or("unclosed string")?;
+      out.push(Token::Str(chars[start..start + end].iter().collect()));
+      i = start + end + 1;
+    } else if "+-*/%()".contains(c) {
+      out.push(Token::Op(c));
+      i += 1;
+    } else {
+      return Err(format!("unexpected character `{}`", c));
+    }
+  }
+  Ok(out)
+}
+
+#[derive(Debug, Clone)]
+enum Val {
+  Int(i64),
+  Float(f64),
+  Str(String),
+  Bool(bool),
+  Null,
+}
+
+impl Val {
+  fn from_kdl(value: &KdlValue) -> Self {
+    if let Some(it) = value.as_i64() {
+      Val::Int(it)
+    } else if let Some(it) = value.as_f64() {
+      Val::Float(it)
+    } else if let Some(it) = value.as_string() {
+      Val::Str(it.to_owned())
+    } else if let Some(it) = value.as_bool() {
+      Val::Bool(it)
+    } else {
+      Val::Null
+    }
+  }
+
+  fn into_kdl(self) -> KdlValue {
+    match self {
+      Val::Int(it) => KdlValue::Base10(it),
+      Val::Float(it) => KdlValue::Base10Float(it),
+      Val::Str(it) => KdlValue::String(it),
+      Val::Bool(it) => KdlValue::Bool(it),
+      Val::Null => KdlValue::Null,
+    }
+  }
+
+  fn type_name(&self) -> &'static str {
+    match self {
+      Val::Int(_) => "an integer",
+      Val::Float(_) => "a float",
+      Val::Str(_) => "a string",
+      Val::Bool(_) => "a boolean",
+      Val::Null => "null",
+    }
+  }
+}
+
+struct Parser<'a> {
+  tokens: &'a [Token],
+  pos: usize,
+  env: &'a Env,
+}
+
+impl<'a> Parser<'a> {
+  fn eat(&mut self, ops: &str) -> Option<char> {
+    match self.tokens.get(self.pos) {
+      Some(Token::Op(op)) if ops.contains(*op) => {
+        self.pos += 1;
+        Some(*op)
+      }
+      _ => None,
+    }
+  }
+
+  fn expr(&mut self) -> Result<Val, String> {
+    let mut lhs = self.term()?;
+    while let Some(op) = self.eat("+-") {
+      let rhs = self.term()?;
+      lhs = binop(op, lhs, rhs)?;
+    }
+    Ok(lhs)
+  }
+
+  fn term(&mut self) -> Result<Val, String> {
+    let mut lhs = self.unary()?;
+    while let Some(op) = self.eat("*/%") {
+      let rhs = self.unary()?;
+      lhs = binop(op, lhs, rhs)?;
+    }
+    Ok(lhs)
+  }
+
+  fn unary(&mut self) -> Result<Val, String> {
+    if self.eat("-").is_some() {
+      return match self.unary()? {
+        Val::Int(it) => it
+          .checked_neg()
+          .map(Val::Int)
+          .ok_or_else(|| format!("`-{}` overflowed", it)),
+        Val::Float(it) => Ok(Val::Float(-it)),
+        other => Err(format!("cannot negate {}", other.type_name())),
+      };
+    }
+    self.atom()
+  }
+
+  fn atom(&mut self) -> Result<Val, String> {
+    let tok = self
+      .tokens
+      .get(self.pos)
+      .ok_or("expected a value but the expression ended")?;
+    self.pos += 1;
+    match tok {
+      Token::Int(it) => Ok(Val::Int(*it)),
+      Token::Float(it) => Ok(Val::Float(*it)),
+      Token::Str(it) => Ok(Val::Str(it.clone())),
+      Token::Ident(name) => match self.env.get(name) {
+        Some(value) => Ok(Val::from_kdl(value)),
+        None => Err(format!("no parameter named `{}`", name)),
+      },
+      Token::Op('(') => {
+        let inner = self.expr()?;
+        self.eat(")").ok_or("unclosed `(`")?;
+        Ok(inner)
+      }
+      Token::Op(_) => Err(format!("unexpected {}", tok)),
+    }
+  }
+}
+
+fn binop(op: char, lhs: Val, rhs: Val) -> Result<Val, String> {
+  let out = match (lhs, rhs) {
+    (Val::Int(a), Val::Int(b)) => {
+      let out = match op {
+        '+' => a.checked_add(b),
+        '-' => a.checked_sub(b),
+        '*' => a.checked_mul(b),
+        '/' => a.checked_div(b),
+        _ => a.checked_rem(b),
+      };
+      Val::Int(out.ok_or_else(|| {
+        format!("`{} {} {}` overflowed or divided by zero", a, op, b)
+      })?)
+    }
+    (Val::Str(a), Val::Str(b)) if op == '+' => Val::Str(a + &b),
+    (a @ (Val::Int(_) | Val::Float(_)), b @ (Val::Int(_) | Val::Float(_))) => {
+      let a = as_float(&a);
+      let b = as_float(&b);
+      Val::Float(match op {
+        '+' => a + b,
+        '-' => a - b,
+        '*' => a * b,
+        '/' => a / b,
+        _ => a % b,
+      })
+    }
+    (a, b) => {
+      return Err(format!(
+        "cannot use `{}` on {} and {}",
+        op,
+        a.type_name(),
+        b.type_name()
+      ))
+    }
+  };
+  Ok(out)
+}
+
+fn as_float(val: &Val) -> f64 {
+  match val {
+    Val::Int(it) => *it as f64,
+    Val::Float(it) => *it,
+    _ => unreachable!(),
+  }
+}
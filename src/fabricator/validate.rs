@@ -0,0 +1,203 @@
+//! Checking a whole blueprint library for problems up front.
+
+use std::sync::Arc;
+
+use miette::{Diagnostic, NamedSource, SourceSpan};
+use smol_str::SmolStr;
+use thiserror::Error;
+
+use crate::world::World;
+
+/// The most combinations of `(choose)` and `(maybe)` rolls that
+/// [`EntityFabricator::validate`] will try for one blueprint.
+pub const MAX_VALIDATED_ROLLS: usize = 1024;
+
+use super::{
+  blueprint::{BlueprintElement, BlueprintLookupError, RenderedComponent},
+  EntityFabricator, InstantiationError,
+};
+
+impl<Ctx> EntityFabricator<Ctx>
+where
+  Ctx: 'static,
+{
+  /// Check every blueprint for problems, instead of finding them one at a
+  /// time when instantiating.
+  ///
+  /// This finds `(splice)`s of blueprints that don't exist, component nodes
+  /// with no factory or serializable component to load them, inheritance
+  /// loops, bad parameters, and `(patch)`es with nothing to patch.
+  /// Blueprints with parameters that have no default are only checked where
+  /// they're spliced in.
+  ///
+  /// Blueprints are looked up once for every way their `(choose)` and
+  /// `(maybe)` elements could be rolled, so the results don't depend on
+  /// luck. Only the first [`MAX_VALIDATED_ROLLS`] combinations are tried,
+  /// though every option is always checked for missing blueprints and
+  /// assemblers.
+  ///
+  /// Use [`validate_with`](Self::validate_with) to also try loading every
+  /// component.
+  pub fn validate(&self) -> Vec<ValidationError> {
+    self.validate_inner(None)
+  }
+
+  /// Like [`validate`](Self::validate), but also run every component node
+  /// through its factory on a scratch world, to find problems in the nodes
+  /// themselves.
+  ///
+  /// Each component is loaded onto its own entity, so this won't catch
+  /// factories that fail because of the components before them.
+  pub fn validate_with(&self, ctx: &Ctx) -> Vec<ValidationError> {
+    self.validate_inner(Some(ctx))
+  }
+
+  fn validate_inner(&self, ctx: Option<&Ctx>) -> Vec<ValidationError> {
+    let mut raws = self.blueprints.iter_raw().collect::<Vec<_>>();
+    raws.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut out = Vec::new();
+    let mut scratch = World::new();
+    for raw in raws {
+      let error = |span, src: &Arc<NamedSource>, kind| ValidationError {
+        blueprint: raw.name.clone(),
+        span,
+        src: src.clone(),
+        kind,
+      };
+
+      self.check_elements(&raw.components, &mut |span, src, kind| {
+        out.push(error(span, src, kind))
+      });
+
+      // Blueprints that need parameters can't be rendered on their own
+      if raw.params.iter().any(|param| param.default.is_null()) {
+        continue;
+      }
+      let prints = match self
+        .blueprints
+        .lookup_every_roll(&raw.name, MAX_VALIDATED_ROLLS)
+      {
+        Ok(it) => it,
+        // Already reported for the blueprint with the bad splice
+        Err(BlueprintLookupError::InheriteeNotFound(..)) => continue,
+        Err(BlueprintLookupError::Param(err)) => {
+          let (span, src) = (err.span, err.src.clone());
+          let kind = ValidationErrorKind::Lookup(err.into());
+          out.push(error(span, &src, kind));
+          continue;
+        }
+        Err(err) => {
+          // Point at the splice or patch with the problem if there is one
+          let (span, src) = match &err {
+            BlueprintLookupError::InheritanceLoop(_, span, src)
+            | BlueprintLookupError::NothingToPatch { span, src, .. } => {
+              (*span, src.clone())
+            }
+            _ => (raw.span, raw.src.clone()),
+          };
+          let kind = ValidationErrorKind::Lookup(err);
+          out.push(error(span, &src, kind));
+          continue;
+        }
+      };
+
+      let Some(ctx) = ctx else {
+        continue;
+      };
+      // The same node shows up in lots of the rolls, so only check it once
+      // unless something patched it
+      let mut checked = Vec::<RenderedComponent>::new();
+      for comp in prints.into_iter().flat_map(|print| print.components) {
+        if checked.iter().any(|it| {
+          it.location.span == comp.location.span
+            && Arc::ptr_eq(&it.location.src, &comp.location.src)
+            && it.node == comp.node
+        }) {
+          continue;
+        }
+        checked.push(comp.clone());
+
+        // Missing factories were already reported
+        if !self.can_assemble(comp.node.name().value()) {
+          continue;
+        }
+        if let Err(InstantiationError::AssemblerError(name, err, at)) =
+          self.assemble_node(scratch.spawn(), &comp, ctx)
+        {
+          let kind = ValidationErrorKind::AssemblerError(name, err);
+          out.push(error(at.span, &at.src, kind));
+        }
+      }
+    }
+
+    out
+  }
+
+  /// Check for components with no assembler and splices of blueprints that
+  /// don't exist, including inside `(choose)` and `(maybe)`.
+  fn check_elements(
+    &self,
+    elements: &[BlueprintElement],
+    report: &mut impl FnMut(SourceSpan, &Arc<NamedSource>, ValidationErrorKind),
+  ) {
+    for element in elements {
+      match element {
+        BlueprintElement::Component { node, src } => {
+          let name = node.name().value();
+          if !self.can_assemble(name) {
+            report(
+              *node.span(),
+              src,
+              ValidationErrorKind::NoAssembler(name.into()),
+            );
+          }
+        }
+        BlueprintElement::Splice {
+          name, span, src, ..
+        } => {
+          if self.blueprints.get_raw(name).is_none() {
+            report(
+              *span,
+              src,
+              ValidationErrorKind::SpliceNotFound(name.clone()),
+            );
+          }
+        }
+        BlueprintElement::Choose { options, .. } => {
+          for (_, option) in options {
+            self.check_elements(std::slice::from_ref(option), report);
+          }
+        }
+        BlueprintElement::Maybe { elements, .. } => {
+          self.check_elements(elements, report);
+        }
+        BlueprintElement::Patch { .. } | BlueprintElement::Remove { .. } => {}
+      }
+    }
+  }
+}
+
+/// A problem found by [`EntityFabricator::validate`].
+#[derive(Debug, Error, Diagnostic)]
+#[error("in the blueprint {blueprint}: {kind}")]
+pub struct ValidationError {
+  pub blueprint: SmolStr,
+  #[label]
+  pub span: SourceSpan,
+  #[source_code]
+  pub src: Arc<NamedSource>,
+  pub kind: ValidationErrorKind,
+}
+
+#[derive(Debug, Error)]
+pub enum ValidationErrorKind {
+  #[error("there was no assembler registered for a component named {0:?}, and no serializable component has that friendly name")]
+  NoAssembler(SmolStr),
+  #[error("it tried to splice in the blueprint {0} but it was not found")]
+  SpliceNotFound(SmolStr),
+  #[error("{0}")]
+  Lookup(BlueprintLookupError),
+  #[error("the assembler for {0:?} gave an error: {1}")]
+  AssemblerError(SmolStr, eyre::Error),
+}
@@ -0,0 +1,70 @@
+//! Reload blueprints when their files change, for iterating on them while the
+//! game runs.
+
+use std::{
+  fs,
+  path::{Path, PathBuf},
+  time::SystemTime,
+};
+
+use super::{blueprint::BlueprintLoadError, EntityFabricator};
+
+/// Polls the modification times of all the files a fabricator loaded from
+/// disc, and reloads the ones that changed.
+///
+/// Files the fabricator loads after this is made are picked up on the next
+/// poll.
+///
+/// There's no background thread; call [`poll`](Self::poll) every so often,
+/// like once a second or when the game window regains focus.
+pub struct BlueprintWatcher {
+  files: Vec<(PathBuf, Option<SystemTime>)>,
+}
+
+impl BlueprintWatcher {
+  /// Watch every file the fabricator has loaded from disc.
+  pub fn new<Ctx: 'static>(fab: &EntityFabricator<Ctx>) -> Self {
+    let mut me = Self { files: Vec::new() };
+    me.find_new_files(fab);
+    me
+  }
+
+  fn find_new_files<Ctx: 'static>(&mut self, fab: &EntityFabricator<Ctx>) {
+    for path in fab.loaded_paths() {
+      if !self.files.iter().any(|(it, _)| it == path) {
+        self.files.push((path.to_owned(), modified(path)));
+      }
+    }
+  }
+
+  /// Reload every file that changed since the last poll, in load order.
+  ///
+  /// Returns each file that was reloaded and whether it worked. Files with
+  /// errors keep their last good version, and aren't retried until they
+  /// change again.
+  pub fn poll<Ctx: 'static>(
+    &mut self,
+    fab: &mut EntityFabricator<Ctx>,
+  ) -> Vec<(PathBuf, Result<(), BlueprintLoadError>)> {
+    self.find_new_files(fab);
+    let mut out = Vec::new();
+    for (path, last) in self.files.iter_mut() {
+      let now = modified(path);
+      if now != *last {
+        *last = now;
+        out.push((path.clone(), fab.reload_file(&*path)));
+      }
+    }
+    out
+  }
+
+  /// All the files being watched.
+  pub fn files(&self) -> impl Iterator<Item = &Path> {
+    self.files.iter().map(|(path, _)| path.as_path())
+  }
+}
+
+/// `None` if the file can't be read, like if it's been deleted.
+fn modified(path: &Path) -> Option<SystemTime> {
+  fs::metadata(path).and_then(|it| it.modified()).ok()
+}
@@ -9,6 +9,7 @@ pub mod entities;
 pub mod fabricator;
 pub mod messages;
 pub mod query;
+pub mod replication;
 pub mod resource;
 pub mod util;
 pub mod world;
@@ -100,6 +100,24 @@ impl<'w> ListenerWorldAccess<'w> {
     EntityBuilder::new_lazy(self, entity)
   }
 
+  /// Set up a builder to change the components on an existing entity once
+  /// [`World::finalize`] is called.
+  ///
+  /// See [`World::lazy_edit`].
+  pub fn lazy_edit<'a>(&'a self, entity: Entity) -> EntityBuilder<'a, 'w> {
+    EntityBuilder::new_lazy_edit(self, entity)
+  }
+
+  /// Set up a copy of the given entity to be spawned once [`World::finalize`] is called.
+  ///
+  /// See [`World::lazy_clone_entity`].
+  pub fn lazy_clone_entity<'a>(
+    &'a self,
+    entity: Entity,
+  ) -> EntityBuilder<'a, 'w> {
+    self.lazy_spawn().lazily_cloning(entity)
+  }
+
   /// Queue an entity to be despawned when [`World::finalize`] is called.
   pub fn lazy_despawn(&self, entity: Entity) {
     self.queue_update(LazyUpdate::DespawnEntity(entity));
@@ -0,0 +1,452 @@
+//! Mirror a host world into replica worlds, like for co-op.
+//!
+//! Register the components and resources that should be sent with
+//! [`ComponentRegisterer::replicated`](crate::component::ComponentRegisterer::replicated)
+//! and [`ResourceRegisterer::replicated`](crate::resource::ResourceRegisterer::replicated).
+//! Then, every so often, call [`Replicator::send`] on the host with anything
+//! that's [`Write`], and [`ReplicaApplier::receive`] on the replica with the
+//! matching [`Read`].
+//!
+//! Only entities with at least one replicated component are sent over. They
+//! get new IDs in the replica world, so components and resources storing
+//! entities should be registered with `register_map_entities`.
+//!
+//! Packets are encoded with bincode, so the host and the replica need to agree
+//! on the layout of everything replicated. Each update is sent as one
+//! length-prefixed frame, so if one can't be applied, the replica can still
+//! pick up at the next one.
+
+use std::{
+  collections::BTreeMap,
+  io::{Read, Write},
+};
+
+use ahash::AHashMap;
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::{
+  entities::EntityMap,
+  prelude::{Component, Entity, EntityLiveness, Resource, World},
+  vtablesathome::{
+    ComponentVtable, ComponentVtables, DeserializeFn, ResourceVtable,
+    ResourceVtables, SerializeFn,
+  },
+  TypeIdWrapper,
+};
+
+/// One change to send to a replica.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Packet {
+  /// A new entity, by its ID in the host world.
+  Spawn(Entity),
+  /// An entity is gone, or doesn't have any replicated components anymore.
+  Despawn(Entity),
+  /// A component was added to an entity or changed, and now has this data.
+  Component {
+    entity: Entity,
+    name: String,
+    data: Vec<u8>,
+  },
+  /// A component was removed from an entity.
+  RemoveComponent { entity: Entity, name: String },
+  /// A resource was added or changed, and now has this data.
+  Resource { name: String, data: Vec<u8> },
+  /// A resource was removed.
+  RemoveResource { name: String },
+}
+
+/// Keeps track of what a replica already knows about a host world, and sends
+/// it what changed.
+///
+/// Use one of these for each replica.
+#[derive(Default)]
+pub struct Replicator {
+  /// Host entities the replica has, to the components it has on them.
+  sent: AHashMap<Entity, AHashMap<TypeIdWrapper, SentComponent>>,
+  resources: BTreeMap<TypeIdWrapper, Vec<u8>>,
+}
+
+struct SentComponent {
+  /// The change tick of the component when it was sent.
+  tick: u64,
+  data: Vec<u8>,
+}
+
+impl Replicator {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Work out the packets to bring the replica up to date with the world.
+  ///
+  /// Spawns come first, so components storing entities can find them.
+  /// Components that haven't been mutably borrowed since the last update aren't
+  /// even serialized again.
+  ///
+  /// Panics if a replicated component or resource can't be serialized.
+  pub fn update(&mut self, world: &World) -> Vec<Packet> {
+    let (packets, pending) = self.prepare(world);
+    self.commit(pending);
+    packets
+  }
+
+  /// Write the packets to bring the replica up to date with the world,
+  /// as one frame: the length of the update as a little-endian `u64`,
+  /// followed by the packets.
+  ///
+  /// If the frame can't be written, the replica is assumed to have missed it,
+  /// so everything in it is sent again next time.
+  ///
+  /// Panics if a replicated component or resource can't be serialized.
+  pub fn send(
+    &mut self,
+    world: &World,
+    mut writer: impl Write,
+  ) -> Result<(), ReplicationError> {
+    let (packets, pending) = self.prepare(world);
+    let frame = options()
+      .serialize(&packets)
+      .map_err(ReplicationError::Io)?;
+    writer
+      .write_all(&(frame.len() as u64).to_le_bytes())
+      .and_then(|()| writer.write_all(&frame))
+      .and_then(|()| writer.flush())
+      .map_err(|err| ReplicationError::Io(err.into()))?;
+    self.commit(pending);
+    Ok(())
+  }
+
+  /// Work out the packets for an update, without remembering that they were
+  /// sent yet.
+  fn prepare(&self, world: &World) -> (Vec<Packet>, PendingUpdate) {
+    let mut entities = world.entities().collect::<Vec<_>>();
+    entities.sort();
+
+    let mut spawns = Vec::new();
+    let mut packets = Vec::new();
+    let mut pending = PendingUpdate::default();
+    let mut alive = Vec::with_capacity(entities.len());
+    for e in entities {
+      let assoc = world.entities.get(e);
+      let mut comps = assoc
+        .iter()
+        .filter_map(|(tid, entry)| {
+          let vtable = ComponentVtables::by_tid(tid);
+          replicated_component(vtable).map(|ser| (vtable, ser, entry))
+        })
+        .peekable();
+      if comps.peek().is_none() {
+        continue;
+      }
+      alive.push(e);
+
+      let sent = self.sent.get(&e);
+      if sent.is_none() {
+        spawns.push(Packet::Spawn(e));
+      }
+      let mut kept = Vec::new();
+      for (vtable, ser, entry) in comps {
+        kept.push(vtable.tid);
+        let tick = entry.changed();
+        let old = sent.and_then(|sent| sent.get(&vtable.tid));
+        if old.is_some_and(|old| old.tick == tick) {
+          continue;
+        }
+        let data = {
+          let lock = entry.read().unwrap();
+          encode(ser(&**lock), vtable.friendly_name)
+        };
+        if !old.is_some_and(|old| old.data == data) {
+          packets.push(Packet::Component {
+            entity: e,
+            name: vtable.friendly_name.to_owned(),
+            data: data.clone(),
+          });
+        }
+        pending.components.push((
+          e,
+          vtable.tid,
+          Some(SentComponent { tick, data }),
+        ));
+      }
+      for &tid in sent.into_iter().flat_map(|sent| sent.keys()) {
+        if !kept.contains(&tid) {
+          packets.push(Packet::RemoveComponent {
+            entity: e,
+            name: ComponentVtables::by_tid(tid).friendly_name.to_owned(),
+          });
+          pending.components.push((e, tid, None));
+        }
+      }
+    }
+
+    let mut despawned = self
+      .sent
+      .keys()
+      .filter(|e| alive.binary_search(e).is_err())
+      .copied()
+      .collect::<Vec<_>>();
+    despawned.sort();
+    for &e in despawned.iter() {
+      packets.push(Packet::Despawn(e));
+    }
+    pending.despawned = despawned;
+
+    for (tid, res) in world.resources.iter() {
+      let vtable = ResourceVtables::by_tid(tid);
+      let Some(ser) = replicated_resource(vtable) else {
+        continue;
+      };
+      let data = {
+        let lock = res.read().unwrap();
+        encode(ser(&**lock), vtable.friendly_name)
+      };
+      if self.resources.get(&tid) != Some(&data) {
+        packets.push(Packet::Resource {
+          name: vtable.friendly_name.to_owned(),
+          data: data.clone(),
+        });
+      }
+      pending.resources.insert(tid, data);
+    }
+    for tid in self.resources.keys() {
+      if !pending.resources.contains_key(tid) {
+        packets.push(Packet::RemoveResource {
+          name: ResourceVtables::by_tid(*tid).friendly_name.to_owned(),
+        });
+      }
+    }
+
+    spawns.extend(packets);
+    (spawns, pending)
+  }
+
+  /// Remember that the replica got an update.
+  fn commit(&mut self, pending: PendingUpdate) {
+    for (e, tid, comp) in pending.components {
+      match comp {
+        Some(comp) => {
+          self.sent.entry(e).or_default().insert(tid, comp);
+        }
+        None => {
+          if let Some(sent) = self.sent.get_mut(&e) {
+            sent.remove(&tid);
+          }
+        }
+      }
+    }
+    for e in pending.despawned {
+      self.sent.remove(&e);
+    }
+    self.resources = pending.resources;
+  }
+}
+
+/// How a [`Replicator`] changes once an update is sent.
+#[derive(Default)]
+struct PendingUpdate {
+  /// Components the replica now has, or doesn't have anymore if `None`.
+  components: Vec<(Entity, TypeIdWrapper, Option<SentComponent>)>,
+  despawned: Vec<Entity>,
+  /// Every replicated resource the replica now has.
+  resources: BTreeMap<TypeIdWrapper, Vec<u8>>,
+}
+
+/// Applies packets from a [`Replicator`] to a replica world.
+///
+/// Entities from the host are spawned as new entities in the replica, and this
+/// keeps track of which is which. Use one of these for each replica world.
+#[derive(Default)]
+pub struct ReplicaApplier {
+  /// Made the first time it's needed, when there's a world to get a dead
+  /// entity from.
+  map: Option<EntityMap>,
+}
+
+impl ReplicaApplier {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Get what a host entity is in the replica world, if it's been spawned.
+  pub fn local(&self, host: Entity) -> Option<Entity> {
+    self.map.as_ref()?.get(host)
+  }
+
+  /// Read one update from a [`Replicator::send`] and apply it to the world.
+  ///
+  /// The whole update is read before any of it is applied. If a packet can't
+  /// be applied, the packets before it stay applied and the rest are skipped,
+  /// and the next call starts at the next update.
+  ///
+  /// This doesn't [finalize](World::finalize) the world, so anything the
+  /// callbacks did lazily waits for the caller to finalize it.
+  ///
+  /// This blocks if the reader does.
+  pub fn receive(
+    &mut self,
+    world: &mut World,
+    mut reader: impl Read,
+  ) -> Result<(), ReplicationError> {
+    let mut len = [0; 8];
+    reader
+      .read_exact(&mut len)
+      .map_err(|err| ReplicationError::Io(err.into()))?;
+    let len = u64::from_le_bytes(len);
+    let mut frame = Vec::new();
+    reader
+      .take(len)
+      .read_to_end(&mut frame)
+      .map_err(|err| ReplicationError::Io(err.into()))?;
+    if frame.len() as u64 != len {
+      return Err(ReplicationError::Io(
+        std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into(),
+      ));
+    }
+
+    let packets = options()
+      .deserialize::<Vec<Packet>>(&frame)
+      .map_err(ReplicationError::Io)?;
+    packets
+      .into_iter()
+      .try_for_each(|packet| self.apply(world, packet))
+  }
+
+  /// Apply one packet to the world.
+  ///
+  /// Components are put on entities by [editing](World::edit) them, so
+  /// creation and removal callbacks are run like normal.
+  pub fn apply(
+    &mut self,
+    world: &mut World,
+    packet: Packet,
+  ) -> Result<(), ReplicationError> {
+    let map = self
+      .map
+      .get_or_insert_with(|| EntityMap::new(world.entities.spawn_dead()));
+    match packet {
+      Packet::Spawn(host) => {
+        let local = world.spawn_empty();
+        map.insert(host, local);
+      }
+      Packet::Despawn(host) => {
+        let local = map
+          .remove(host)
+          .ok_or(ReplicationError::UnknownEntity(host))?;
+        // The replica might have gotten rid of it itself
+        if world.entities.liveness(local) == EntityLiveness::Alive {
+          world.despawn(local);
+        }
+      }
+      Packet::Component { entity, name, data } => {
+        let local = local_entity(map, world, entity)?;
+        let vtable = ComponentVtables::try_by_friendly_name(&name)
+          .filter(|vtable| replicated_component(vtable).is_some())
+          .ok_or_else(|| ReplicationError::UnknownComponent(name.clone()))?;
+        let mut comp = decode(vtable.deser.unwrap(), &data, &name)?;
+        if let Some(map_entities) = vtable.map_entities {
+          map_entities(&mut *comp, map);
+        }
+        let mut editor = world.edit(local);
+        editor.insert_raw(comp);
+        editor.build();
+      }
+      Packet::RemoveComponent { entity, name } => {
+        let local = local_entity(map, world, entity)?;
+        let vtable = ComponentVtables::try_by_friendly_name(&name)
+          .filter(|vtable| replicated_component(vtable).is_some())
+          .ok_or(ReplicationError::UnknownComponent(name))?;
+        let mut editor = world.edit(local);
+        editor.remove_raw(vtable.tid);
+        editor.build();
+      }
+      Packet::Resource { name, data } => {
+        let vtable = ResourceVtables::try_by_friendly_name(&name)
+          .filter(|vtable| replicated_resource(vtable).is_some())
+          .ok_or_else(|| ReplicationError::UnknownResource(name.clone()))?;
+        let mut res = decode(vtable.deser.unwrap(), &data, &name)?;
+        if let Some(map_entities) = vtable.map_entities {
+          map_entities(&mut *res, map);
+        }
+        world.resources.insert_raw(res);
+      }
+      Packet::RemoveResource { name } => {
+        let vtable = ResourceVtables::try_by_friendly_name(&name)
+          .filter(|vtable| replicated_resource(vtable).is_some())
+          .ok_or(ReplicationError::UnknownResource(name))?;
+        world.resources.remove_raw(vtable.tid);
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Something going wrong while sending or applying packets.
+#[derive(Debug, Error)]
+pub enum ReplicationError {
+  #[error("could not read or write a packet: {0}")]
+  Io(bincode::Error),
+  #[error("got a packet for host entity {0:x}, which isn't in the replica")]
+  UnknownEntity(Entity),
+  #[error("the component `{0}` isn't registered as replicated")]
+  UnknownComponent(String),
+  #[error("the resource `{0}` isn't registered as replicated")]
+  UnknownResource(String),
+  #[error("could not deserialize `{name}`: {message}")]
+  Deserialize { name: String, message: String },
+}
+
+fn options() -> impl Options {
+  bincode::DefaultOptions::new()
+}
+
+fn local_entity(
+  map: &EntityMap,
+  world: &World,
+  host: Entity,
+) -> Result<Entity, ReplicationError> {
+  map
+    .get(host)
+    .filter(|local| world.entities.liveness(*local) == EntityLiveness::Alive)
+    .ok_or(ReplicationError::UnknownEntity(host))
+}
+
+fn replicated_component(
+  vtable: &ComponentVtable,
+) -> Option<SerializeFn<dyn Component>> {
+  vtable
+    .ser
+    .filter(|_| vtable.replicated && vtable.deser.is_some())
+}
+
+fn replicated_resource(
+  vtable: &ResourceVtable,
+) -> Option<SerializeFn<dyn Resource>> {
+  vtable
+    .ser
+    .filter(|_| vtable.replicated && vtable.deser.is_some())
+}
+
+fn encode(ser: &dyn erased_serde::Serialize, friendly_name: &str) -> Vec<u8> {
+  options().serialize(ser).unwrap_or_else(|err| {
+    panic!(
+      "could not serialize {} to replicate it: {}",
+      friendly_name, err
+    )
+  })
+}
+
+fn decode<T: ?Sized>(
+  deser: DeserializeFn<T>,
+  data: &[u8],
+  friendly_name: &str,
+) -> Result<Box<T>, ReplicationError> {
+  let mut de = bincode::Deserializer::from_slice(data, options());
+  let mut erased = <dyn erased_serde::Deserializer>::erase(&mut de);
+  deser(&mut erased).map_err(|err| ReplicationError::Deserialize {
+    name: friendly_name.to_owned(),
+    message: err.to_string(),
+  })
+}
@@ -9,7 +9,10 @@ use downcast::{downcast, Any};
 use serde::{de::DeserializeOwned, Serialize};
 
 use crate::{
-  vtablesathome::{self, DeserializeFn, ResourceVtable},
+  entities::{EntityMap, MapEntities},
+  vtablesathome::{
+    self, DeserializeFn, MapEntitiesFn, ResourceVtable, SerializeFn,
+  },
   TypeIdWrapper,
 };
 
@@ -19,7 +22,9 @@ use crate::{
 /// This is handy for things you need across many entities, like position caches, assets, settings, save data ...
 /// anything that wouldn't make sense to have more than one of.
 ///
-pub trait Resource: Any + erased_serde::Serialize {
+/// Like components, resources are saved with the world, so they have to be `Serialize + DeserializeOwned`,
+/// unless they're registered as transient with `manually_register_resource!(MyResource, transient)`.
+pub trait Resource: Any {
   fn register(builder: ResourceRegisterer<Self>) -> ResourceRegisterer<Self>
   where
     Self: Sized,
@@ -36,7 +41,7 @@ pub struct ResourceRegisterer<R> {
 
 impl<R> ResourceRegisterer<R>
 where
-  R: Resource + Serialize + DeserializeOwned,
+  R: Resource,
 {
   pub fn set_friendly_name(mut self, name: &'static str) -> Self {
     if let Some(ono) = self.inner.friendly_name.replace(name) {
@@ -50,23 +55,100 @@ where
     self
   }
 
-  #[doc(hidden)]
-  pub fn into_vtable(self) -> ResourceVtable {
-    let friendly_name = self
-      .inner
-      .friendly_name
-      .unwrap_or_else(vtablesathome::default_friendly_type_name::<R>);
+  /// Never save this resource with the world.
+  ///
+  /// Resources that can't be saved at all, because they don't implement
+  /// `Serialize` or `Deserialize`, have to be registered with
+  /// `manually_register_resource!(MyResource, transient)` instead, which
+  /// calls this.
+  pub fn transient(mut self) -> Self {
+    self.inner.transient = true;
+    self
+  }
 
+  /// Never save this resource with the world, and insert its `Default` into
+  /// worlds when they're loaded.
+  ///
+  /// This is handy for caches that get rebuilt by create callbacks.
+  /// Like with [`transient`](Self::transient), if it isn't serializable the
+  /// registration macro has to be told it's transient too.
+  pub fn transient_default(mut self) -> Self
+  where
+    R: Default,
+  {
+    self.inner.transient = true;
+    self.inner.rebuild = Some(|| Box::<R>::default());
+    self
+  }
+
+  /// Rewrite the entities stored in this resource with its [`MapEntities`] impl
+  /// when it's moved to another world with
+  /// [`World::merge_from`](crate::world::World::merge_from), or saved with
+  /// [`World::serialize_compacted`](crate::world::World::serialize_compacted).
+  pub fn register_map_entities(mut self) -> Self
+  where
+    R: MapEntities,
+  {
+    let map_entities: MapEntitiesFn<dyn Resource> =
+      |res: &mut dyn Resource, map: &EntityMap| {
+        // SAFETY: this will only ever be called with a resource of the right concrete type
+        let concrete_res: &mut R =
+          unsafe { res.downcast_mut().unwrap_unchecked() };
+        concrete_res.map_entities(map);
+      };
+    self.inner.map_entities = Some(map_entities);
+    self
+  }
+
+  /// Send this resource to replicas of the world, with a
+  /// [`Replicator`](crate::replication::Replicator).
+  pub fn replicated(mut self) -> Self
+  where
+    R: Serialize + DeserializeOwned,
+  {
+    self.inner.replicated = true;
+    self
+  }
+
+  /// Save this resource with the world. This is automatically called by the
+  /// registration macros if the resource is serializable.
+  #[doc(hidden)]
+  pub fn register_serde(mut self) -> Self
+  where
+    R: Serialize + DeserializeOwned,
+  {
+    let ser = |res: &dyn Resource| -> &dyn erased_serde::Serialize {
+      // SAFETY: this will only ever be called with a resource of the right concrete type
+      let concrete_res: &R = unsafe { res.downcast_ref().unwrap_unchecked() };
+      concrete_res
+    } as SerializeFn<dyn Resource>;
     let deser = |deser: &mut dyn erased_serde::Deserializer| -> erased_serde::Result<Box<dyn Resource>> {
       let this = R::deserialize(deser)?;
       Ok(Box::new(this) as _)
     }
       as DeserializeFn<dyn Resource>;
 
+    self.inner.ser = Some(ser);
+    self.inner.deser = Some(deser);
+    self
+  }
+
+  #[doc(hidden)]
+  pub fn into_vtable(self) -> ResourceVtable {
+    let friendly_name = self
+      .inner
+      .friendly_name
+      .unwrap_or_else(vtablesathome::default_friendly_type_name::<R>);
+
     ResourceVtable {
       tid: TypeIdWrapper::of::<R>(),
       friendly_name,
-      deser,
+      ser: self.inner.ser,
+      deser: self.inner.deser,
+      transient: self.inner.transient,
+      rebuild: self.inner.rebuild,
+      map_entities: self.inner.map_entities,
+      replicated: self.inner.replicated,
     }
   }
 }
@@ -76,15 +158,30 @@ pub mod __private {
   use std::marker::PhantomData;
 
   use super::{Resource, ResourceRegisterer};
+  use crate::vtablesathome::{
+    DefaultFn, DeserializeFn, MapEntitiesFn, SerializeFn,
+  };
 
   pub struct ResourceRegistererErased {
     pub(crate) friendly_name: Option<&'static str>,
+    pub(crate) ser: Option<SerializeFn<dyn Resource>>,
+    pub(crate) deser: Option<DeserializeFn<dyn Resource>>,
+    pub(crate) transient: bool,
+    pub(crate) rebuild: Option<DefaultFn<dyn Resource>>,
+    pub(crate) map_entities: Option<MapEntitiesFn<dyn Resource>>,
+    pub(crate) replicated: bool,
   }
 
   impl ResourceRegistererErased {
     pub(crate) fn new() -> Self {
       Self {
         friendly_name: None,
+        ser: None,
+        deser: None,
+        transient: false,
+        rebuild: None,
+        map_entities: None,
+        replicated: false,
       }
     }
 
@@ -100,10 +197,11 @@ pub use __private::*;
 
 /// Longhand resource register macro. You can call this as
 /// `manually_register_resource(MyResource)` if you're allergic to
-/// attribute macros for some reason.
+/// attribute macros for some reason, or
+/// `manually_register_resource(MyResource, transient)` for transient ones.
 #[macro_export]
 macro_rules! manually_register_resource {
-  ($res_ty:ty) => {
+  ($res_ty:ty $(, $transient:ident)?) => {
     $crate::__private::paste! {
       #[doc(hidden)]
       #[allow(non_snake_case)]
@@ -116,6 +214,7 @@ macro_rules! manually_register_resource {
         (regi: $crate::__private::ResourceRegistererErased)
         -> $crate::__private::ResourceVtable {
         let wrapped = regi.wrap();
+        let wrapped = $crate::__register_serde!(wrapped $(, $transient)?);
         <$res_ty as $crate::resource::Resource>::register(wrapped).into_vtable()
       }
     }
@@ -0,0 +1,84 @@
+use generational_arena::Arena;
+use serde::{Serialize, Serializer};
+
+use crate::{
+  entities::EntityMap,
+  prelude::{Entity, World},
+};
+
+use super::{
+  entity::EntitiesSerWrapper, resource::ResourcesSerWrapper,
+  version::SchemaVersionSer, WorldLayout, WorldSerWrapper,
+};
+
+impl World {
+  /// Save a world with the given layout, renumbering the living entities
+  /// densely from zero.
+  ///
+  /// Over a long game, despawned entities leave free slots behind in the
+  /// allocator and the generation counters keep climbing, all of which gets
+  /// saved with the world. This throws all of that out of the save.
+  ///
+  /// The entities stored in components and resources are renumbered too,
+  /// with the [`MapEntities`](crate::entities::MapEntities) impls they
+  /// registered with `register_map_entities`. They're copied first, so the
+  /// world itself isn't changed and the entities you're holding onto outside
+  /// of it stay good; use [`World::compaction_map`] to find out what they are
+  /// in the save. Entities that aren't alive become an entity that will be
+  /// dead in the loaded world.
+  ///
+  /// Panics if a component that maps entities can't be cloned, like
+  /// [`World::clone_entity`].
+  pub fn serialize_compacted<S>(
+    &self,
+    serializer: S,
+    layout: WorldLayout,
+  ) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    let (map, allocator) = self.compaction();
+
+    let wrapper = WorldSerWrapper {
+      version: SchemaVersionSer,
+      layout,
+      allocator: &allocator,
+      entities: EntitiesSerWrapper::new(self, layout).mapped(&map),
+      resources: ResourcesSerWrapper::new(self).mapped(&map),
+    };
+    wrapper.serialize(serializer)
+  }
+
+  /// Get what each living entity becomes in a save written with
+  /// [`World::serialize_compacted`], as long as the world doesn't change
+  /// in between.
+  pub fn compaction_map(&self) -> EntityMap {
+    self.compaction().0
+  }
+
+  /// The living entities are renumbered in order, all with generation 1, so
+  /// the entity at index 0 with generation 0 is never alive.
+  ///
+  /// Saved allocators don't remember their generation, only the generations
+  /// of the entities in them, so a slot freed in the save wouldn't stay dead.
+  fn compaction(&self) -> (EntityMap, Arena<()>) {
+    let mut olds = self.entities().collect::<Vec<_>>();
+    olds.sort();
+
+    let mut allocator = Arena::with_capacity(olds.len());
+    let dead = allocator.insert(());
+    allocator.remove(dead);
+    let dead = if olds.is_empty() {
+      // Nothing in the save has a generation, so pick one it'll never get to
+      Entity::recompose(0, u64::MAX)
+    } else {
+      Entity(dead)
+    };
+
+    let mut map = EntityMap::new(dead);
+    for old in olds {
+      map.insert(old, Entity(allocator.insert(())));
+    }
+    (map, allocator)
+  }
+}
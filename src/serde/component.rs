@@ -1,10 +1,19 @@
-use serde::{de::Visitor, ser::SerializeMap, Deserialize, Serialize};
+use serde::{
+  de::{IgnoredAny, Visitor},
+  ser::SerializeMap,
+  Deserialize, Serialize,
+};
 
 use crate::{
   prelude::Component, vtablesathome::ComponentVtables, ToTypeIdWrapper,
 };
 
-use super::{ApplyDeserFn, ErasedSerWrapper};
+use super::{
+  load::{deserialize_read_ahead, loading_leniently, report_broken},
+  unknown::handle_unknown,
+  version::loading_version,
+  ApplyDeserFn, ErasedSerWrapper, LoadError, Value,
+};
 
 /// Wrap components in this to serialize them,
 /// then get them back by deserializing them into a ComponentDeWrapper.
@@ -25,21 +34,72 @@ impl<'w> Serialize for ComponentSerWrapper<'w> {
   {
     let vtable = ComponentVtables::by_tid((*self.component).type_id_wrapper());
     let mut map = serializer.serialize_map(Some(1))?;
-    map.serialize_entry(
-      vtable.friendly_name,
-      &ErasedSerWrapper::new(self.component),
-    )?;
+    if vtable.transient {
+      // Just a placeholder so it can be rebuilt
+      map.serialize_entry(vtable.friendly_name, &())?;
+    } else {
+      let ser = vtable.ser.ok_or_else(|| {
+        <S::Error as serde::ser::Error>::custom(format!(
+          "component {} is not serializable; register it as transient",
+          vtable.friendly_name
+        ))
+      })?;
+      map.serialize_entry(
+        vtable.friendly_name,
+        &ErasedSerWrapper::new(ser(self.component)),
+      )?;
+    }
     map.end()
   }
 }
 
+/// Deep-copy a component, with its registered `Clone` impl if it has one,
+/// or by round-tripping it through serde if not.
+///
+/// Panics if the component can't be round-tripped.
+pub(crate) fn clone_component(component: &dyn Component) -> Box<dyn Component> {
+  let vtable = ComponentVtables::by_tid((*component).type_id_wrapper());
+  if let Some(clone) = vtable.clone {
+    return clone(component);
+  }
+
+  let (Some(ser), Some(deser)) = (vtable.ser, vtable.deser) else {
+    panic!(
+      "could not clone component {}, because it's not serializable and didn't register a clone",
+      vtable.friendly_name
+    )
+  };
+  let value = serde_value::to_value(ErasedSerWrapper::new(ser(component)))
+    .unwrap_or_else(|err| {
+      panic!(
+        "could not serialize component {} to clone it: {}",
+        vtable.friendly_name, err
+      )
+    });
+  let mut erased = <dyn erased_serde::Deserializer>::erase(value);
+  deser(&mut erased).unwrap_or_else(|err| {
+    panic!(
+      "could not deserialize component {} to clone it: {}",
+      vtable.friendly_name, err
+    )
+  })
+}
+
 // ===================
 // === DESERIALIZE ===
 // ===================
 
 /// Deserialize one component from `{ friendly-name: { data... }}`
-pub(super) struct ComponentDeWrapper {
-  pub inner: Box<dyn Component>,
+pub(super) enum ComponentDeWrapper {
+  Known(Box<dyn Component>),
+  /// The friendly name wasn't registered, and the data is being preserved.
+  Unknown(String, Value),
+  /// The friendly name wasn't registered, and the data was thrown out.
+  Skipped(String),
+  /// The component is transient, and doesn't get rebuilt.
+  Transient,
+  /// The component failed to load in lenient mode, and was reported.
+  Broken,
 }
 
 impl<'de> Deserialize<'de> for ComponentDeWrapper {
@@ -47,8 +107,7 @@ impl<'de> Deserialize<'de> for ComponentDeWrapper {
   where
     D: serde::Deserializer<'de>,
   {
-    let component = deserializer.deserialize_map(ComponentDeVisitor)?;
-    Ok(ComponentDeWrapper { inner: component })
+    deserializer.deserialize_map(ComponentDeVisitor)
   }
 }
 
@@ -56,7 +115,7 @@ impl<'de> Deserialize<'de> for ComponentDeWrapper {
 struct ComponentDeVisitor;
 
 impl<'de> Visitor<'de> for ComponentDeVisitor {
-  type Value = Box<dyn Component>;
+  type Value = ComponentDeWrapper;
 
   fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
     formatter.write_str(
@@ -73,14 +132,68 @@ impl<'de> Visitor<'de> for ComponentDeVisitor {
         "requires exactly one key/value pair",
       )
     })?;
-    let vtable = ComponentVtables::by_friendly_name(&friendly_name);
-    let component = map.next_value_seed(ApplyDeserFn {
-      deser: vtable.deser,
-    })?;
+    let Some(vtable) = ComponentVtables::try_by_friendly_name(&friendly_name)
+    else {
+      return Ok(
+        match handle_unknown("component", &friendly_name, &mut map)? {
+          Some(data) => ComponentDeWrapper::Unknown(friendly_name, data),
+          None => ComponentDeWrapper::Skipped(friendly_name),
+        },
+      );
+    };
+    if vtable.transient {
+      map.next_value::<IgnoredAny>()?;
+      return Ok(match vtable.rebuild {
+        Some(rebuild) => ComponentDeWrapper::Known(rebuild()),
+        None => ComponentDeWrapper::Transient,
+      });
+    }
+    let place = LoadError::new("").in_component(&friendly_name);
+    let Some(deser) = vtable.deser else {
+      report_broken(LoadError {
+        message: "not deserializable; register it as transient".to_owned(),
+        ..place
+      })?;
+      map.next_value::<IgnoredAny>()?;
+      return Ok(ComponentDeWrapper::Broken);
+    };
+
+    let outdated =
+      loading_version().filter(|version| vtable.is_outdated(*version));
+    let component = match outdated {
+      Some(version) => {
+        let old: Value = map.next_value()?;
+        match vtable.migrate(version, old) {
+          Ok(data) => {
+            deserialize_read_ahead(ApplyDeserFn { deser, place }, data)?
+          }
+          Err(err) => {
+            report_broken(LoadError {
+              message: format!("when migrating: {}", err),
+              ..place
+            })?;
+            None
+          }
+        }
+      }
+      None => {
+        let seed = ApplyDeserFn { deser, place };
+        if loading_leniently() {
+          // Read it all in first, so it can be skipped if it's broken
+          let data: Value = map.next_value()?;
+          deserialize_read_ahead(seed, data)?
+        } else {
+          map.next_value_seed(seed)?
+        }
+      }
+    };
 
     // typetag just ignores if there's more than one k/v here, so that's
     // what i'll do i guess
 
-    Ok(component)
+    Ok(match component {
+      Some(it) => ComponentDeWrapper::Known(it),
+      None => ComponentDeWrapper::Broken,
+    })
   }
 }
@@ -5,20 +5,34 @@
 The design is more-or-less stolen from [Hecs' row serialization](https://docs.rs/hecs/0.9.0/hecs/serialize/row/trait.SerializeContext.html).
 */
 
+use std::marker::PhantomData;
+
 use ahash::AHashMap;
 use serde::{
-  de::{MapAccess, SeqAccess, Visitor},
-  ser::{SerializeMap, SerializeSeq},
+  de::{IgnoredAny, MapAccess, SeqAccess, Visitor},
+  ser::{Error as _, SerializeMap, SerializeSeq},
   Deserialize, Deserializer, Serialize, Serializer,
 };
 
 use crate::{
   builder::EntityBuilderComponentTracker,
+  entities::EntityMap,
   prelude::{Entity, World},
+  vtablesathome::ComponentVtables,
   world::EntityAssoc,
+  TypeIdWrapper,
 };
 
-use super::component::{ComponentDeWrapper, ComponentSerWrapper};
+use super::{
+  component::{clone_component, ComponentDeWrapper, ComponentSerWrapper},
+  layout::loading_layout,
+  load::{
+    deserialize_read_ahead, loading_entity, loading_leniently, report_broken,
+    set_loading_entity, with_load_context, LoadingEntityReset,
+  },
+  unknown::UnknownSerWrapper,
+  LoadError, UnknownComponents, Value, WorldLayout,
+};
 
 // =====================
 // === SERIALIZATION ===
@@ -27,11 +41,46 @@ use super::component::{ComponentDeWrapper, ComponentSerWrapper};
 /// We pretend to Serde that this and [`EntitiesDeWrapper`] are the same thing.
 pub(crate) struct EntitiesSerWrapper<'w> {
   world: &'w World,
+  entities: Vec<Entity>,
+  layout: WorldLayout,
+  /// What to renumber the entities to as they're written, if anything.
+  map: Option<&'w EntityMap>,
 }
 
 impl<'w> EntitiesSerWrapper<'w> {
-  pub(crate) fn new(world: &'w World) -> Self {
-    Self { world }
+  pub(crate) fn new(world: &'w World, layout: WorldLayout) -> Self {
+    Self {
+      world,
+      entities: world.entities().collect(),
+      layout,
+      map: None,
+    }
+  }
+
+  /// Only serialize some of the entities in the world.
+  pub(crate) fn subset(
+    world: &'w World,
+    entities: Vec<Entity>,
+    layout: WorldLayout,
+  ) -> Self {
+    Self {
+      world,
+      entities,
+      layout,
+      map: None,
+    }
+  }
+
+  /// Write every entity as what it maps to, including the ones stored in
+  /// components that registered a [`MapEntities`](crate::entities::MapEntities)
+  /// impl.
+  pub(crate) fn mapped(mut self, map: &'w EntityMap) -> Self {
+    self.map = Some(map);
+    self
+  }
+
+  fn id(&self, entity: Entity) -> Entity {
+    self.map.map_or(entity, |map| map.map(entity))
   }
 }
 
@@ -40,24 +89,50 @@ impl<'w> Serialize for EntitiesSerWrapper<'w> {
   where
     S: Serializer,
   {
-    let mut map = serializer.serialize_map(Some(self.world.entities.len()))?;
-    for entity in self.world.entities() {
-      map.serialize_key(&entity)?;
-      let wrapper = &EntitySerWrapper::new(self.world, entity);
-      map.serialize_value(wrapper)?;
+    match self.layout {
+      WorldLayout::EntityMap => {
+        let mut map = serializer.serialize_map(Some(self.entities.len()))?;
+        for &entity in self.entities.iter() {
+          map.serialize_key(&self.id(entity))?;
+          let wrapper = &EntitySerWrapper::new(self.world, entity, self.map);
+          map.serialize_value(wrapper)?;
+        }
+        map.end()
+      }
+      WorldLayout::EntityList => {
+        let mut seq = serializer.serialize_seq(Some(self.entities.len()))?;
+        for &entity in self.entities.iter() {
+          seq.serialize_element(&EntityEntrySer {
+            id: self.id(entity),
+            components: EntitySerWrapper::new(self.world, entity, self.map),
+          })?;
+        }
+        seq.end()
+      }
     }
-    map.end()
   }
 }
 
-struct EntitySerWrapper<'w> {
+/// One entity in the [`WorldLayout::EntityList`] layout.
+#[derive(Serialize)]
+struct EntityEntrySer<'w> {
+  id: Entity,
+  components: EntitySerWrapper<'w>,
+}
+
+pub(super) struct EntitySerWrapper<'w> {
   pub world: &'w World,
   pub entity: Entity,
+  pub map: Option<&'w EntityMap>,
 }
 
 impl<'w> EntitySerWrapper<'w> {
-  pub fn new(world: &'w World, entity: Entity) -> Self {
-    Self { world, entity }
+  pub fn new(
+    world: &'w World,
+    entity: Entity,
+    map: Option<&'w EntityMap>,
+  ) -> Self {
+    Self { world, entity, map }
   }
 }
 
@@ -66,13 +141,48 @@ impl<'w> Serialize for EntitySerWrapper<'w> {
   where
     S: Serializer,
   {
-    let components = self.world.entities.get(self.entity);
-    let mut seq = serializer.serialize_seq(Some(components.len()))?;
+    let Some(components) = self.world.entities.try_get(self.entity) else {
+      return Err(S::Error::custom(format!(
+        "entity {:x} isn't alive",
+        self.entity
+      )));
+    };
+    let unknown_tid = TypeIdWrapper::of::<UnknownComponents>();
 
-    for (_tid, assoc) in components.iter() {
+    // Unknown components get flattened back out into their own entries
+    let unknown = components.components().get(&unknown_tid).map(|comp| {
+      let lock = comp.read().unwrap();
+      lock.downcast_ref::<UnknownComponents>().unwrap().0.clone()
+    });
+    // Transient components are left out entirely, unless they have a placeholder
+    let saved = components
+      .iter()
+      .filter(|(tid, _)| {
+        let vtable = ComponentVtables::by_tid(*tid);
+        *tid != unknown_tid && (!vtable.transient || vtable.rebuild.is_some())
+      })
+      .collect::<Vec<_>>();
+    let len = saved.len() + unknown.as_ref().map_or(0, Vec::len);
+    let mut seq = serializer.serialize_seq(Some(len))?;
+
+    for (tid, assoc) in saved {
       let inner = assoc.read().unwrap();
-      let wrapper = ComponentSerWrapper::new(&**inner);
-      seq.serialize_element(&wrapper)?;
+      let vtable = ComponentVtables::by_tid(tid);
+      match (self.map, vtable.map_entities) {
+        (Some(map), Some(map_entities)) if !vtable.transient => {
+          // Rewrite a copy, so the world itself isn't touched
+          let mut copy = clone_component(&**inner);
+          map_entities(&mut *copy, map);
+          seq.serialize_element(&ComponentSerWrapper::new(&*copy))?;
+        }
+        _ => seq.serialize_element(&ComponentSerWrapper::new(&**inner))?,
+      }
+    }
+    for (friendly_name, data) in unknown.iter().flatten() {
+      seq.serialize_element(&UnknownSerWrapper {
+        friendly_name,
+        data,
+      })?;
     }
 
     seq.end()
@@ -93,7 +203,20 @@ impl<'de> Deserialize<'de> for EntitiesDeWrapper {
   where
     D: Deserializer<'de>,
   {
-    let entities = deserializer.deserialize_map(EntitiesDeVisitor)?;
+    // Formats that know what they're holding can tell which layout it is,
+    // others have to be told.
+    let entities = if deserializer.is_human_readable() {
+      deserializer.deserialize_any(EntitiesDeVisitor)?
+    } else {
+      match loading_layout() {
+        WorldLayout::EntityMap => {
+          deserializer.deserialize_map(EntitiesDeVisitor)?
+        }
+        WorldLayout::EntityList => {
+          deserializer.deserialize_seq(EntitiesDeVisitor)?
+        }
+      }
+    };
     Ok(Self { entities })
   }
 }
@@ -106,7 +229,7 @@ impl<'de> Visitor<'de> for EntitiesDeVisitor {
   fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
     write!(
       formatter,
-      "a map of entities to sequences of externally-tagged components"
+      "a map of entities to sequences of externally-tagged components, or a sequence of entity entries"
     )
   }
 
@@ -115,22 +238,197 @@ impl<'de> Visitor<'de> for EntitiesDeVisitor {
     A: MapAccess<'de>,
   {
     let mut out = AHashMap::new();
+    let _reset = LoadingEntityReset;
+
+    if loading_leniently() {
+      // Read each entity in first, so it can be skipped if it's broken
+      while let Some(key) = map.next_key::<Value>()? {
+        let data: Value = map.next_value()?;
+        let entity = match deserialize_read_ahead::<_, A::Error>(
+          PhantomData::<LoadingEntityId>,
+          key,
+        ) {
+          Ok(LoadingEntityId(entity)) => entity,
+          Err(err) => {
+            report_broken(LoadError::new(err))?;
+            continue;
+          }
+        };
+        match deserialize_read_ahead::<_, A::Error>(
+          PhantomData::<EntityDeWrapper>,
+          data,
+        ) {
+          Ok(components) => insert_entity(&mut out, entity, components),
+          Err(err) => report_broken(LoadError::new(err))?,
+        }
+        set_loading_entity(None);
+      }
+    } else {
+      while let Some(LoadingEntityId(entity)) = map.next_key()? {
+        let components: EntityDeWrapper = map.next_value()?;
+        insert_entity(&mut out, entity, components);
+        set_loading_entity(None);
+      }
+    }
+
+    Ok(out)
+  }
 
-    while let Some(entity) = map.next_key()? {
-      // force
-      let _: Entity = entity;
-      let components: EntityDeWrapper = map.next_value()?;
+  fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+  where
+    A: SeqAccess<'de>,
+  {
+    let mut out = AHashMap::new();
+    let _reset = LoadingEntityReset;
 
-      // how ergonomic
-      out.insert(entity, EntityAssoc::new(components.components.components));
+    if loading_leniently() {
+      // Read each entity in first, so it can be skipped if it's broken
+      while let Some(entry) = seq.next_element::<Value>()? {
+        match deserialize_read_ahead::<_, A::Error>(
+          PhantomData::<EntityEntryDe>,
+          entry,
+        ) {
+          Ok(EntityEntryDe { id, components }) => {
+            insert_entity(&mut out, id.0, components)
+          }
+          Err(err) => report_broken(LoadError::new(err))?,
+        }
+        set_loading_entity(None);
+      }
+    } else {
+      while let Some(entry) = seq.next_element()? {
+        let EntityEntryDe { id, components } = entry;
+        insert_entity(&mut out, id.0, components);
+        set_loading_entity(None);
+      }
     }
 
     Ok(out)
   }
 }
 
+fn insert_entity(
+  out: &mut AHashMap<Entity, EntityAssoc>,
+  entity: Entity,
+  components: EntityDeWrapper,
+) {
+  if !components.skipped.is_empty() {
+    with_load_context(|ctx| {
+      if let Some(ctx) = ctx {
+        ctx
+          .report
+          .skipped_components
+          .extend(components.skipped.into_iter().map(|name| (entity, name)));
+      }
+    });
+  }
+
+  // how ergonomic
+  out.insert(entity, EntityAssoc::new(components.components.components));
+}
+
+/// One entity in the [`WorldLayout::EntityList`] layout.
+struct EntityEntryDe {
+  id: LoadingEntityId,
+  components: EntityDeWrapper,
+}
+
+impl<'de> Deserialize<'de> for EntityEntryDe {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    deserializer.deserialize_struct(
+      "EntityEntryDe",
+      &["id", "components"],
+      EntityEntryDeVisitor,
+    )
+  }
+}
+
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "lowercase")]
+enum EntityEntryField {
+  Id,
+  Components,
+  #[serde(other)]
+  Other,
+}
+
+struct EntityEntryDeVisitor;
+
+impl<'de> Visitor<'de> for EntityEntryDeVisitor {
+  type Value = EntityEntryDe;
+
+  fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(formatter, "an entity entry with an id and components")
+  }
+
+  fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+  where
+    A: SeqAccess<'de>,
+  {
+    let id = seq
+      .next_element()?
+      .ok_or_else(|| serde::de::Error::invalid_length(0, &self))?;
+    let components = seq
+      .next_element()?
+      .ok_or_else(|| serde::de::Error::invalid_length(1, &self))?;
+    Ok(EntityEntryDe { id, components })
+  }
+
+  fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+  where
+    A: MapAccess<'de>,
+  {
+    let mut id = None;
+    let mut components = None;
+    // If the components come first, hold onto them until the ID is known,
+    // so errors in them can say which entity they're in
+    let mut early_components = None;
+    while let Some(key) = map.next_key()? {
+      match key {
+        EntityEntryField::Id => id = Some(map.next_value::<LoadingEntityId>()?),
+        EntityEntryField::Components if id.is_some() => {
+          components = Some(map.next_value::<EntityDeWrapper>()?)
+        }
+        EntityEntryField::Components => {
+          early_components = Some(map.next_value::<Value>()?)
+        }
+        EntityEntryField::Other => {
+          map.next_value::<IgnoredAny>()?;
+        }
+      }
+    }
+
+    let id = id.ok_or_else(|| serde::de::Error::missing_field("id"))?;
+    if let Some(early) = early_components {
+      components = Some(deserialize_read_ahead(PhantomData, early)?);
+    }
+    let components = components
+      .ok_or_else(|| serde::de::Error::missing_field("components"))?;
+    Ok(EntityEntryDe { id, components })
+  }
+}
+
+/// Remembers the entity it loaded, so errors in its components can say where they are.
+struct LoadingEntityId(Entity);
+
+impl<'de> Deserialize<'de> for LoadingEntityId {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    let entity = Entity::deserialize(deserializer)?;
+    set_loading_entity(Some(entity));
+    Ok(Self(entity))
+  }
+}
+
 struct EntityDeWrapper {
   components: EntityBuilderComponentTracker,
+  /// Friendly names of unknown components that were dropped.
+  skipped: Vec<String>,
 }
 
 impl<'de> Deserialize<'de> for EntityDeWrapper {
@@ -138,18 +436,21 @@ impl<'de> Deserialize<'de> for EntityDeWrapper {
   where
     D: Deserializer<'de>,
   {
-    let components = deserializer.deserialize_seq(EntityDeVisitor)?;
-    Ok(EntityDeWrapper { components })
+    deserializer.deserialize_seq(EntityDeVisitor)
   }
 }
 
 struct EntityDeVisitor;
 
 impl<'de> Visitor<'de> for EntityDeVisitor {
-  type Value = EntityBuilderComponentTracker;
+  type Value = EntityDeWrapper;
 
   fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-    write!(formatter, "a sequence of externally tagged components")
+    write!(formatter, "a sequence of externally tagged components")?;
+    if let Some(entity) = loading_entity() {
+      write!(formatter, " for entity {:x}", entity)?;
+    }
+    Ok(())
   }
 
   fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
@@ -157,12 +458,25 @@ impl<'de> Visitor<'de> for EntityDeVisitor {
     A: SeqAccess<'de>,
   {
     let mut tracker = EntityBuilderComponentTracker::new();
+    let mut unknown = Vec::new();
+    let mut skipped = Vec::new();
     while let Some(next) = seq.next_element()? {
-      // force type
-      let _: ComponentDeWrapper = next;
-      tracker.insert_raw(next.inner);
+      match next {
+        ComponentDeWrapper::Known(comp) => {
+          tracker.insert_raw(comp);
+        }
+        ComponentDeWrapper::Unknown(name, data) => unknown.push((name, data)),
+        ComponentDeWrapper::Skipped(name) => skipped.push(name),
+        ComponentDeWrapper::Transient | ComponentDeWrapper::Broken => {}
+      }
+    }
+    if !unknown.is_empty() {
+      tracker.insert_raw(Box::new(UnknownComponents(unknown)));
     }
 
-    Ok(tracker)
+    Ok(EntityDeWrapper {
+      components: tracker,
+      skipped,
+    })
   }
 }
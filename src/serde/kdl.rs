@@ -0,0 +1,1246 @@
+/*!
+Reading and writing worlds and components as KDL, laid out like blueprints.
+
+Components are written as nodes named after their friendly name, the same way
+they're written in blueprints:
+
+- Structs and maps are written as properties, or child nodes for
+  fields that aren't simple values.
+- Newtypes and other single values are written as one argument.
+- Sequences of simple values are written as arguments, and other sequences are
+  written as child nodes named `-`.
+- Unit variants are written as strings, and variants holding a single value
+  as that value annotated with the variant's name, like `(other)"gloom"`.
+  The fabricator can read both of those. Other variants are written as a child
+  node named after the variant.
+
+Struct field names are written in `kebab-case` and read back in `snake_case`,
+the same way the [fabricator](crate::fabricator) reads blueprints. Map keys
+and friendly names are always kept as-is.
+
+Components that are [migrated](crate::component::ComponentRegisterer::migrate_from)
+get the old data with its field names as they were written, in `kebab-case`.
+
+A whole world is written like this:
+
+```text
+version 0
+layout "EntityList"
+allocator { ... }
+entities {
+    entity 0 0 {
+        position 1 2
+        has-hp start-hp=10
+    }
+}
+resources {
+    turn 12
+}
+```
+*/
+
+use std::collections::BTreeMap;
+
+use kdl::{KdlDocument, KdlEntry, KdlNode, KdlValue};
+use serde::{
+  de::{
+    value::{MapDeserializer, SeqDeserializer},
+    DeserializeSeed, EnumAccess, IntoDeserializer, VariantAccess, Visitor,
+  },
+  forward_to_deserialize_any,
+  ser::{
+    SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant,
+    SerializeTuple, SerializeTupleStruct, SerializeTupleVariant,
+  },
+  Deserialize, Deserializer, Serialize, Serializer,
+};
+use serde_value::Value;
+use thiserror::Error;
+
+use crate::prelude::{Component, Entity, World};
+
+use super::{
+  component::{ComponentDeWrapper, ComponentSerWrapper},
+  entity::EntitySerWrapper,
+  load::{with_load_context, LoadContextGuard},
+  LoadOptions, LoadReport, WorldLayout,
+};
+
+/// Problems when converting between KDL and worlds or components.
+#[derive(Debug, Error)]
+pub enum KdlSerdeError {
+  #[error("{0}")]
+  Custom(String),
+  #[error(
+    "can't write a map key that isn't a string, number, or bool as KDL: {0:?}"
+  )]
+  BadKey(Value),
+  #[error("can't write a number that doesn't fit in an i64 as KDL: {0}")]
+  TooBig(u64),
+  #[error("node {0:?} had arguments along with properties or children")]
+  MixedNode(String),
+  #[error("node {0:?} had children named `-` along with other children")]
+  MixedChildren(String),
+  #[error("expected a world, but {0}")]
+  BadWorld(String),
+  #[error("the component {0:?} isn't registered")]
+  UnknownComponent(String),
+  #[error("the component {0:?} is transient, so it can't be loaded")]
+  TransientComponent(String),
+}
+
+impl serde::de::Error for KdlSerdeError {
+  fn custom<T: std::fmt::Display>(msg: T) -> Self {
+    KdlSerdeError::Custom(msg.to_string())
+  }
+}
+
+impl serde::ser::Error for KdlSerdeError {
+  fn custom<T: std::fmt::Display>(msg: T) -> Self {
+    KdlSerdeError::Custom(msg.to_string())
+  }
+}
+
+impl ComponentSerWrapper<'_> {
+  /// Write the component as a KDL node, named after its friendly name.
+  pub fn to_kdl(&self) -> Result<KdlNode, KdlSerdeError> {
+    let KdlTree::Map(map) = self.serialize(KdlTreeSerializer)? else {
+      unreachable!("components are always serialized as a map");
+    };
+    let (name, data) = map.into_iter().next().unwrap();
+    Ok(tree_to_node(&name, &data))
+  }
+}
+
+/// Read a component out of a KDL node written by [`ComponentSerWrapper::to_kdl`]
+/// (or written by hand, like in a blueprint), using the node's name as its
+/// friendly name.
+pub fn component_from_kdl(
+  node: &KdlNode,
+) -> Result<Box<dyn Component>, KdlSerdeError> {
+  let name = node.name().value();
+  let value = Value::Map(BTreeMap::from([(
+    Value::String(name.to_owned()),
+    node_to_value(node)?,
+  )]));
+  match ComponentDeWrapper::deserialize(KdlValueDeserializer(value))? {
+    ComponentDeWrapper::Known(comp) => Ok(comp),
+    ComponentDeWrapper::Unknown(name, _)
+    | ComponentDeWrapper::Skipped(name) => {
+      Err(KdlSerdeError::UnknownComponent(name))
+    }
+    ComponentDeWrapper::Transient => {
+      Err(KdlSerdeError::TransientComponent(name.to_owned()))
+    }
+    ComponentDeWrapper::Broken => {
+      unreachable!("components are only dropped when loading a world")
+    }
+  }
+}
+
+/// Write all of an entity's components as nodes, the same way they're saved
+/// in a world.
+pub(crate) fn entity_to_kdl(
+  world: &World,
+  entity: Entity,
+) -> Result<Vec<KdlNode>, KdlSerdeError> {
+  let KdlTree::Seq(components) =
+    EntitySerWrapper::new(world, entity, None).serialize(KdlTreeSerializer)?
+  else {
+    unreachable!("components are always a seq");
+  };
+  Ok(components_to_nodes(components))
+}
+
+/// Check if two component nodes would load the same component.
+///
+/// If `theirs` can be loaded as a component, it's compared the way
+/// [`ComponentSerWrapper::to_kdl`] would write it, so formatting and defaulted
+/// fields don't matter. Otherwise, the nodes are compared as-is.
+pub(crate) fn same_component(
+  ours: &KdlNode,
+  theirs: &KdlNode,
+) -> Result<bool, KdlSerdeError> {
+  if ours.name().value() != theirs.name().value() {
+    return Ok(false);
+  }
+  let theirs = match component_from_kdl(theirs) {
+    Ok(comp) => ComponentSerWrapper::new(&*comp).to_kdl()?,
+    Err(_) => theirs.clone(),
+  };
+  Ok(node_to_value(ours)? == node_to_value(&theirs)?)
+}
+
+impl World {
+  /// Write the whole world as a KDL document.
+  pub fn to_kdl(&self) -> Result<KdlDocument, KdlSerdeError> {
+    let tree = WorldListLayout(self).serialize(KdlTreeSerializer)?;
+    let mut doc = world_tree_to_doc(tree);
+    doc.fmt();
+    Ok(doc)
+  }
+
+  /// Load a world from a KDL document written by [`World::to_kdl`].
+  pub fn from_kdl(doc: &KdlDocument) -> Result<World, KdlSerdeError> {
+    let (world, _report) = World::from_kdl_with(doc, LoadOptions::default())?;
+    Ok(world)
+  }
+
+  /// Load a world from a KDL document, with options for what to do with the problems in it.
+  ///
+  /// See [`World::deserialize_with`].
+  pub fn from_kdl_with(
+    doc: &KdlDocument,
+    options: LoadOptions,
+  ) -> Result<(World, LoadReport), KdlSerdeError> {
+    let fields = doc_to_world_fields(doc)?;
+    let guard = LoadContextGuard::new(options);
+    with_load_context(|ctx| {
+      if let Some(ctx) = ctx {
+        ctx.from_kdl = true;
+      }
+    });
+    let world = super::deserialize_world(KdlValueDeserializer::map(fields))?;
+    Ok((world, guard.finish()))
+  }
+}
+
+/// KDL can't have entities as keys, so always use the list layout.
+struct WorldListLayout<'w>(&'w World);
+
+impl Serialize for WorldListLayout<'_> {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    self.0.serialize_with(serializer, WorldLayout::EntityList)
+  }
+}
+
+// ====================
+// === TREE TO KDL ===
+// ====================
+
+/// What a value looks like on its way to KDL.
+///
+/// This is like a [`Value`], but it keeps fields in the order they were
+/// written, and remembers which things are enum variants. Struct fields are
+/// already in `kebab-case`.
+enum KdlTree {
+  Unit,
+  Scalar(KdlValue),
+  Seq(Vec<KdlTree>),
+  Map(Vec<(String, KdlTree)>),
+  /// Unit variants are just strings, so this is only for the others.
+  Variant(&'static str, Box<KdlTree>),
+}
+
+fn world_tree_to_doc(tree: KdlTree) -> KdlDocument {
+  let KdlTree::Map(world) = tree else {
+    unreachable!("worlds are always serialized as a map");
+  };
+
+  let mut doc = KdlDocument::new();
+  for (key, tree) in world {
+    let node = match (key.as_str(), tree) {
+      ("entities", KdlTree::Seq(entities)) => {
+        let mut node = KdlNode::new("entities");
+        let kids = node.ensure_children();
+        for entity in entities {
+          kids.nodes_mut().push(entity_tree_to_node(entity));
+        }
+        node
+      }
+      ("resources", KdlTree::Map(resources)) => {
+        let mut node = KdlNode::new("resources");
+        let kids = node.ensure_children();
+        for (name, data) in resources {
+          kids.nodes_mut().push(tree_to_node(&name, &data));
+        }
+        node
+      }
+      (_, tree) => tree_to_node(&key, &tree),
+    };
+    doc.nodes_mut().push(node);
+  }
+  doc
+}
+
+/// Write `{ id: [idx, gen], components: [...] }` as `entity idx gen { ... }`
+fn entity_tree_to_node(tree: KdlTree) -> KdlNode {
+  let KdlTree::Map(entry) = tree else {
+    unreachable!("entity entries are always a map");
+  };
+  let mut id = None;
+  let mut components = None;
+  for (key, tree) in entry {
+    match (key.as_str(), tree) {
+      ("id", tree) => id = Some(tree),
+      ("components", KdlTree::Seq(tree)) => components = Some(tree),
+      _ => unreachable!("entity entries only have an ID and components"),
+    }
+  }
+
+  let mut node = tree_to_node("entity", &id.unwrap());
+  *node.ensure_children().nodes_mut() =
+    components_to_nodes(components.unwrap());
+  node
+}
+
+/// Write `[{ friendly-name: data }, ...]` as nodes.
+fn components_to_nodes(components: Vec<KdlTree>) -> Vec<KdlNode> {
+  let mut out = Vec::new();
+  for comp in components {
+    let KdlTree::Map(comp) = comp else {
+      unreachable!("components are always serialized as a map");
+    };
+    for (name, data) in comp {
+      out.push(tree_to_node(&name, &data));
+    }
+  }
+  out
+}
+
+/// Write a value as a node with the given name.
+fn tree_to_node(name: &str, tree: &KdlTree) -> KdlNode {
+  let mut node = KdlNode::new(name);
+  fill_node(&mut node, tree);
+  node
+}
+
+fn fill_node(node: &mut KdlNode, tree: &KdlTree) {
+  match tree {
+    KdlTree::Unit => {}
+    KdlTree::Seq(items) => {
+      let scalars =
+        items.iter().map(tree_to_scalar).collect::<Option<Vec<_>>>();
+      match scalars {
+        Some(scalars) => {
+          for scalar in scalars {
+            node.push(scalar_to_entry(None, scalar));
+          }
+        }
+        None => {
+          let kids = node.ensure_children();
+          for item in items {
+            kids.nodes_mut().push(tree_to_node("-", item));
+          }
+        }
+      }
+    }
+    KdlTree::Map(map) => {
+      for (key, tree) in map {
+        match tree_to_scalar(tree) {
+          Some(scalar) => node.push(scalar_to_entry(Some(key.clone()), scalar)),
+          None => {
+            let kid = tree_to_node(key, tree);
+            node.ensure_children().nodes_mut().push(kid);
+          }
+        }
+      }
+    }
+    KdlTree::Scalar(_) | KdlTree::Variant(..) => match tree_to_scalar(tree) {
+      Some(scalar) => node.push(scalar_to_entry(None, scalar)),
+      None => {
+        let KdlTree::Variant(variant, inner) = tree else {
+          unreachable!("scalars can always be written as scalars");
+        };
+        let kid = tree_to_node(variant, inner);
+        node.ensure_children().nodes_mut().push(kid);
+      }
+    },
+  }
+}
+
+/// Returns `None` if the value can't be written as a single KDL value.
+///
+/// Otherwise, returns the value and the name of the enum variant it's in,
+/// if there is one.
+fn tree_to_scalar(tree: &KdlTree) -> Option<(Option<&'static str>, KdlValue)> {
+  match tree {
+    KdlTree::Unit => Some((None, KdlValue::Null)),
+    KdlTree::Scalar(scalar) => Some((None, scalar.clone())),
+    KdlTree::Variant(variant, inner) => match tree_to_scalar(inner)? {
+      (None, scalar) => Some((Some(variant), scalar)),
+      // There's only room for one annotation
+      (Some(_), _) => None,
+    },
+    KdlTree::Seq(_) | KdlTree::Map(_) => None,
+  }
+}
+
+fn scalar_to_entry(
+  key: Option<String>,
+  (variant, scalar): (Option<&'static str>, KdlValue),
+) -> KdlEntry {
+  let mut entry = match key {
+    Some(key) => KdlEntry::new_prop(key, scalar),
+    None => KdlEntry::new(scalar),
+  };
+  if let Some(variant) = variant {
+    entry.set_ty(variant);
+  }
+  entry
+}
+
+fn key_to_string(key: &Value) -> Result<String, KdlSerdeError> {
+  match key {
+    Value::String(s) => Ok(s.clone()),
+    Value::Char(c) => Ok(c.to_string()),
+    Value::Bool(b) => Ok(b.to_string()),
+    Value::U8(x) => Ok(x.to_string()),
+    Value::U16(x) => Ok(x.to_string()),
+    Value::U32(x) => Ok(x.to_string()),
+    Value::U64(x) => Ok(x.to_string()),
+    Value::I8(x) => Ok(x.to_string()),
+    Value::I16(x) => Ok(x.to_string()),
+    Value::I32(x) => Ok(x.to_string()),
+    Value::I64(x) => Ok(x.to_string()),
+    Value::F32(x) => Ok(x.to_string()),
+    Value::F64(x) => Ok(x.to_string()),
+    Value::Newtype(inner) => key_to_string(inner),
+    _ => Err(KdlSerdeError::BadKey(key.clone())),
+  }
+}
+
+// ==================
+// === SERIALIZER ===
+// ==================
+
+struct KdlTreeSerializer;
+
+impl Serializer for KdlTreeSerializer {
+  type Ok = KdlTree;
+  type Error = KdlSerdeError;
+
+  type SerializeSeq = KdlSeqSerializer;
+  type SerializeTuple = KdlSeqSerializer;
+  type SerializeTupleStruct = KdlSeqSerializer;
+  type SerializeTupleVariant = KdlSeqSerializer;
+  type SerializeMap = KdlMapSerializer;
+  type SerializeStruct = KdlMapSerializer;
+  type SerializeStructVariant = KdlMapSerializer;
+
+  fn serialize_bool(self, v: bool) -> Result<KdlTree, KdlSerdeError> {
+    Ok(KdlTree::Scalar(KdlValue::Bool(v)))
+  }
+
+  fn serialize_i8(self, v: i8) -> Result<KdlTree, KdlSerdeError> {
+    self.serialize_i64(v as i64)
+  }
+
+  fn serialize_i16(self, v: i16) -> Result<KdlTree, KdlSerdeError> {
+    self.serialize_i64(v as i64)
+  }
+
+  fn serialize_i32(self, v: i32) -> Result<KdlTree, KdlSerdeError> {
+    self.serialize_i64(v as i64)
+  }
+
+  fn serialize_i64(self, v: i64) -> Result<KdlTree, KdlSerdeError> {
+    Ok(KdlTree::Scalar(KdlValue::Base10(v)))
+  }
+
+  fn serialize_u8(self, v: u8) -> Result<KdlTree, KdlSerdeError> {
+    self.serialize_i64(v as i64)
+  }
+
+  fn serialize_u16(self, v: u16) -> Result<KdlTree, KdlSerdeError> {
+    self.serialize_i64(v as i64)
+  }
+
+  fn serialize_u32(self, v: u32) -> Result<KdlTree, KdlSerdeError> {
+    self.serialize_i64(v as i64)
+  }
+
+  fn serialize_u64(self, v: u64) -> Result<KdlTree, KdlSerdeError> {
+    let v = i64::try_from(v).map_err(|_| KdlSerdeError::TooBig(v))?;
+    self.serialize_i64(v)
+  }
+
+  fn serialize_f32(self, v: f32) -> Result<KdlTree, KdlSerdeError> {
+    self.serialize_f64(v as f64)
+  }
+
+  fn serialize_f64(self, v: f64) -> Result<KdlTree, KdlSerdeError> {
+    Ok(KdlTree::Scalar(KdlValue::Base10Float(v)))
+  }
+
+  fn serialize_char(self, v: char) -> Result<KdlTree, KdlSerdeError> {
+    self.serialize_str(&v.to_string())
+  }
+
+  fn serialize_str(self, v: &str) -> Result<KdlTree, KdlSerdeError> {
+    Ok(KdlTree::Scalar(KdlValue::String(v.to_owned())))
+  }
+
+  fn serialize_bytes(self, v: &[u8]) -> Result<KdlTree, KdlSerdeError> {
+    Ok(KdlTree::Seq(
+      v.iter()
+        .map(|byte| KdlTree::Scalar(KdlValue::Base10(*byte as i64)))
+        .collect(),
+    ))
+  }
+
+  fn serialize_none(self) -> Result<KdlTree, KdlSerdeError> {
+    Ok(KdlTree::Unit)
+  }
+
+  fn serialize_some<T>(self, value: &T) -> Result<KdlTree, KdlSerdeError>
+  where
+    T: ?Sized + Serialize,
+  {
+    value.serialize(self)
+  }
+
+  fn serialize_unit(self) -> Result<KdlTree, KdlSerdeError> {
+    Ok(KdlTree::Unit)
+  }
+
+  fn serialize_unit_struct(
+    self,
+    _name: &'static str,
+  ) -> Result<KdlTree, KdlSerdeError> {
+    Ok(KdlTree::Unit)
+  }
+
+  fn serialize_unit_variant(
+    self,
+    _name: &'static str,
+    _variant_index: u32,
+    variant: &'static str,
+  ) -> Result<KdlTree, KdlSerdeError> {
+    self.serialize_str(variant)
+  }
+
+  fn serialize_newtype_struct<T>(
+    self,
+    _name: &'static str,
+    value: &T,
+  ) -> Result<KdlTree, KdlSerdeError>
+  where
+    T: ?Sized + Serialize,
+  {
+    value.serialize(self)
+  }
+
+  fn serialize_newtype_variant<T>(
+    self,
+    _name: &'static str,
+    _variant_index: u32,
+    variant: &'static str,
+    value: &T,
+  ) -> Result<KdlTree, KdlSerdeError>
+  where
+    T: ?Sized + Serialize,
+  {
+    let inner = value.serialize(self)?;
+    Ok(KdlTree::Variant(variant, Box::new(inner)))
+  }
+
+  fn serialize_seq(
+    self,
+    len: Option<usize>,
+  ) -> Result<KdlSeqSerializer, KdlSerdeError> {
+    Ok(KdlSeqSerializer {
+      variant: None,
+      items: Vec::with_capacity(len.unwrap_or_default()),
+    })
+  }
+
+  fn serialize_tuple(
+    self,
+    len: usize,
+  ) -> Result<KdlSeqSerializer, KdlSerdeError> {
+    self.serialize_seq(Some(len))
+  }
+
+  fn serialize_tuple_struct(
+    self,
+    _name: &'static str,
+    len: usize,
+  ) -> Result<KdlSeqSerializer, KdlSerdeError> {
+    self.serialize_seq(Some(len))
+  }
+
+  fn serialize_tuple_variant(
+    self,
+    _name: &'static str,
+    _variant_index: u32,
+    variant: &'static str,
+    len: usize,
+  ) -> Result<KdlSeqSerializer, KdlSerdeError> {
+    Ok(KdlSeqSerializer {
+      variant: Some(variant),
+      items: Vec::with_capacity(len),
+    })
+  }
+
+  fn serialize_map(
+    self,
+    _len: Option<usize>,
+  ) -> Result<KdlMapSerializer, KdlSerdeError> {
+    Ok(KdlMapSerializer {
+      variant: None,
+      entries: Vec::new(),
+      key: None,
+    })
+  }
+
+  fn serialize_struct(
+    self,
+    _name: &'static str,
+    len: usize,
+  ) -> Result<KdlMapSerializer, KdlSerdeError> {
+    self.serialize_map(Some(len))
+  }
+
+  fn serialize_struct_variant(
+    self,
+    _name: &'static str,
+    _variant_index: u32,
+    variant: &'static str,
+    _len: usize,
+  ) -> Result<KdlMapSerializer, KdlSerdeError> {
+    Ok(KdlMapSerializer {
+      variant: Some(variant),
+      entries: Vec::new(),
+      key: None,
+    })
+  }
+}
+
+struct KdlSeqSerializer {
+  variant: Option<&'static str>,
+  items: Vec<KdlTree>,
+}
+
+impl KdlSeqSerializer {
+  fn finish(self) -> KdlTree {
+    let seq = KdlTree::Seq(self.items);
+    match self.variant {
+      Some(variant) => KdlTree::Variant(variant, Box::new(seq)),
+      None => seq,
+    }
+  }
+}
+
+impl SerializeSeq for KdlSeqSerializer {
+  type Ok = KdlTree;
+  type Error = KdlSerdeError;
+
+  fn serialize_element<T>(&mut self, value: &T) -> Result<(), KdlSerdeError>
+  where
+    T: ?Sized + Serialize,
+  {
+    self.items.push(value.serialize(KdlTreeSerializer)?);
+    Ok(())
+  }
+
+  fn end(self) -> Result<KdlTree, KdlSerdeError> {
+    Ok(self.finish())
+  }
+}
+
+macro_rules! forward_to_serialize_element {
+  ($($trait:ident :: $method:ident),* $(,)?) => {
+    $(
+      impl $trait for KdlSeqSerializer {
+        type Ok = KdlTree;
+        type Error = KdlSerdeError;
+
+        fn $method<T>(&mut self, value: &T) -> Result<(), KdlSerdeError>
+        where
+          T: ?Sized + Serialize,
+        {
+          SerializeSeq::serialize_element(self, value)
+        }
+
+        fn end(self) -> Result<KdlTree, KdlSerdeError> {
+          Ok(self.finish())
+        }
+      }
+    )*
+  };
+}
+
+forward_to_serialize_element! {
+  SerializeTuple::serialize_element,
+  SerializeTupleStruct::serialize_field,
+  SerializeTupleVariant::serialize_field,
+}
+
+struct KdlMapSerializer {
+  variant: Option<&'static str>,
+  entries: Vec<(String, KdlTree)>,
+  key: Option<String>,
+}
+
+impl KdlMapSerializer {
+  fn finish(self) -> KdlTree {
+    let map = KdlTree::Map(self.entries);
+    match self.variant {
+      Some(variant) => KdlTree::Variant(variant, Box::new(map)),
+      None => map,
+    }
+  }
+}
+
+impl SerializeMap for KdlMapSerializer {
+  type Ok = KdlTree;
+  type Error = KdlSerdeError;
+
+  fn serialize_key<T>(&mut self, key: &T) -> Result<(), KdlSerdeError>
+  where
+    T: ?Sized + Serialize,
+  {
+    let key = serde_value::to_value(key)
+      .map_err(|err| KdlSerdeError::Custom(err.to_string()))?;
+    self.key = Some(key_to_string(&key)?);
+    Ok(())
+  }
+
+  fn serialize_value<T>(&mut self, value: &T) -> Result<(), KdlSerdeError>
+  where
+    T: ?Sized + Serialize,
+  {
+    let key = self.key.take().expect("serialized a value without a key");
+    self
+      .entries
+      .push((key, value.serialize(KdlTreeSerializer)?));
+    Ok(())
+  }
+
+  fn end(self) -> Result<KdlTree, KdlSerdeError> {
+    Ok(self.finish())
+  }
+}
+
+impl SerializeStruct for KdlMapSerializer {
+  type Ok = KdlTree;
+  type Error = KdlSerdeError;
+
+  fn serialize_field<T>(
+    &mut self,
+    key: &'static str,
+    value: &T,
+  ) -> Result<(), KdlSerdeError>
+  where
+    T: ?Sized + Serialize,
+  {
+    let value = value.serialize(KdlTreeSerializer)?;
+    self.entries.push((key.replace('_', "-"), value));
+    Ok(())
+  }
+
+  fn end(self) -> Result<KdlTree, KdlSerdeError> {
+    Ok(self.finish())
+  }
+}
+
+impl SerializeStructVariant for KdlMapSerializer {
+  type Ok = KdlTree;
+  type Error = KdlSerdeError;
+
+  fn serialize_field<T>(
+    &mut self,
+    key: &'static str,
+    value: &T,
+  ) -> Result<(), KdlSerdeError>
+  where
+    T: ?Sized + Serialize,
+  {
+    SerializeStruct::serialize_field(self, key, value)
+  }
+
+  fn end(self) -> Result<KdlTree, KdlSerdeError> {
+    Ok(self.finish())
+  }
+}
+
+// =====================
+// === KDL TO VALUE ===
+// =====================
+
+/// The version comes first no matter where it is in the document, because the
+/// components can't be loaded until it's known.
+fn doc_to_world_fields(
+  doc: &KdlDocument,
+) -> Result<Vec<(Value, Value)>, KdlSerdeError> {
+  let mut world = Vec::new();
+  for node in doc.nodes() {
+    let key = node.name().value();
+    let kids = node.children().map(|kids| kids.nodes()).unwrap_or_default();
+    let value = match key {
+      "entities" => Value::Seq(
+        kids
+          .iter()
+          .map(entity_node_to_value)
+          .collect::<Result<_, _>>()?,
+      ),
+      "resources" => Value::Map(
+        kids
+          .iter()
+          .map(|kid| {
+            Ok((
+              Value::String(kid.name().value().to_owned()),
+              node_to_value(kid)?,
+            ))
+          })
+          .collect::<Result<_, KdlSerdeError>>()?,
+      ),
+      _ => node_to_value(node)?,
+    };
+    world.push((Value::String(key.to_owned()), value));
+  }
+  world.sort_by_key(|(key, _)| *key != Value::String("version".to_owned()));
+  Ok(world)
+}
+
+/// Read `entity idx gen { ... }` as `[[idx, gen], [...]]`
+fn entity_node_to_value(node: &KdlNode) -> Result<Value, KdlSerdeError> {
+  if node.name().value() != "entity" {
+    return Err(KdlSerdeError::BadWorld(format!(
+      "found a node named {:?} in the entities",
+      node.name().value()
+    )));
+  }
+
+  let id = node
+    .entries()
+    .iter()
+    .map(|entry| match entry.name() {
+      None => Ok(scalar_to_value(entry.value())),
+      Some(_) => Err(KdlSerdeError::BadWorld(
+        "entity nodes can only have their ID as arguments".to_owned(),
+      )),
+    })
+    .collect::<Result<_, _>>()?;
+  let components = node
+    .children()
+    .map(|kids| kids.nodes())
+    .unwrap_or_default()
+    .iter()
+    .map(|kid| {
+      Ok(Value::Map(BTreeMap::from([(
+        Value::String(kid.name().value().to_owned()),
+        node_to_value(kid)?,
+      )])))
+    })
+    .collect::<Result<_, KdlSerdeError>>()?;
+
+  // As a seq, so the ID comes first and the components can be read with it
+  Ok(Value::Seq(vec![Value::Seq(id), Value::Seq(components)]))
+}
+
+/// Read a node the same way blueprints are read.
+///
+/// Keys are kept as they're written, because only the deserializer knows
+/// which ones are struct fields.
+fn node_to_value(node: &KdlNode) -> Result<Value, KdlSerdeError> {
+  let name = node.name().value();
+  let (args, props): (Vec<_>, Vec<_>) = node
+    .entries()
+    .iter()
+    .partition(|entry| entry.name().is_none());
+  let kids = node.children().map(|kids| kids.nodes()).unwrap_or_default();
+
+  if props.is_empty() && kids.is_empty() {
+    return Ok(match args.as_slice() {
+      [] => Value::Unit,
+      [arg] => entry_to_value(arg),
+      args => Value::Seq(args.iter().map(|arg| entry_to_value(arg)).collect()),
+    });
+  }
+  if !args.is_empty() {
+    return Err(KdlSerdeError::MixedNode(name.to_owned()));
+  }
+
+  let dashes = kids.iter().filter(|kid| kid.name().value() == "-").count();
+  if dashes > 0 {
+    if dashes != kids.len() || !props.is_empty() {
+      return Err(KdlSerdeError::MixedChildren(name.to_owned()));
+    }
+    let items = kids.iter().map(node_to_value).collect::<Result<_, _>>()?;
+    return Ok(Value::Seq(items));
+  }
+
+  let mut map = BTreeMap::new();
+  for prop in props {
+    let key = prop.name().unwrap().value().to_owned();
+    map.insert(Value::String(key), entry_to_value(prop));
+  }
+  for kid in kids {
+    let key = kid.name().value().to_owned();
+    map.insert(Value::String(key), node_to_value(kid)?);
+  }
+  Ok(Value::Map(map))
+}
+
+/// Annotated values are read as enum variants, like `(other)"gloom"`.
+fn entry_to_value(entry: &KdlEntry) -> Value {
+  let value = scalar_to_value(entry.value());
+  match entry.ty() {
+    Some(variant) => Value::Map(BTreeMap::from([(
+      Value::String(variant.value().to_owned()),
+      value,
+    )])),
+    None => value,
+  }
+}
+
+fn scalar_to_value(scalar: &KdlValue) -> Value {
+  match scalar {
+    KdlValue::RawString(s) | KdlValue::String(s) => Value::String(s.clone()),
+    KdlValue::Base2(x)
+    | KdlValue::Base8(x)
+    | KdlValue::Base10(x)
+    | KdlValue::Base16(x) => Value::I64(*x),
+    KdlValue::Base10Float(x) => Value::F64(*x),
+    KdlValue::Bool(b) => Value::Bool(*b),
+    KdlValue::Null => Value::Unit,
+  }
+}
+
+// ====================
+// === DESERIALIZER ===
+// ====================
+
+/// KDL loses some information that serde needs, so this is more forgiving than
+/// deserializing a [`Value`] normally:
+///
+/// - a single value can be read as a sequence of one
+/// - nothing can be read as an empty sequence or map
+/// - strings can be read as numbers, because map keys are always strings
+struct KdlValueDeserializer(Value);
+
+impl<'de> IntoDeserializer<'de, KdlSerdeError> for KdlValueDeserializer {
+  type Deserializer = Self;
+
+  fn into_deserializer(self) -> Self::Deserializer {
+    self
+  }
+}
+
+impl KdlValueDeserializer {
+  fn seq(
+    items: Vec<Value>,
+  ) -> SeqDeserializer<impl Iterator<Item = KdlValueDeserializer>, KdlSerdeError>
+  {
+    SeqDeserializer::new(items.into_iter().map(KdlValueDeserializer))
+  }
+
+  fn map(
+    map: impl IntoIterator<Item = (Value, Value)>,
+  ) -> MapDeserializer<
+    'static,
+    impl Iterator<Item = (KdlValueDeserializer, KdlValueDeserializer)>,
+    KdlSerdeError,
+  > {
+    MapDeserializer::new(
+      map
+        .into_iter()
+        .map(|(k, v)| (KdlValueDeserializer(k), KdlValueDeserializer(v))),
+    )
+  }
+
+  /// Turn the `kebab-case` keys of a struct back into its `snake_case` field
+  /// names. Keys that already name a field are left alone.
+  fn snake_case_fields(self, fields: &[&str]) -> Self {
+    let Value::Map(map) = self.0 else {
+      return self;
+    };
+    let map = map
+      .into_iter()
+      .map(|(key, value)| match key {
+        Value::String(key) if !fields.contains(&key.as_str()) => {
+          let snake = key.replace('-', "_");
+          if fields.contains(&snake.as_str()) {
+            (Value::String(snake), value)
+          } else {
+            (Value::String(key), value)
+          }
+        }
+        key => (key, value),
+      })
+      .collect();
+    KdlValueDeserializer(Value::Map(map))
+  }
+}
+
+/// Load something that was read out of KDL into a [`Value`] ahead of time.
+pub(super) fn deserialize_value<'de, T>(
+  seed: T,
+  value: Value,
+) -> Result<T::Value, KdlSerdeError>
+where
+  T: DeserializeSeed<'de>,
+{
+  seed.deserialize(KdlValueDeserializer(value))
+}
+
+macro_rules! deserialize_number {
+  ($($method:ident => $visit:ident),* $(,)?) => {
+    $(
+      fn $method<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+      where
+        V: Visitor<'de>,
+      {
+        match self.0 {
+          Value::String(s) => match s.parse() {
+            Ok(it) => visitor.$visit(it),
+            Err(_) => KdlValueDeserializer(Value::String(s)).deserialize_any(visitor),
+          },
+          _ => self.deserialize_any(visitor),
+        }
+      }
+    )*
+  };
+}
+
+impl<'de> Deserializer<'de> for KdlValueDeserializer {
+  type Error = KdlSerdeError;
+
+  fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+  where
+    V: Visitor<'de>,
+  {
+    match self.0 {
+      Value::Bool(b) => visitor.visit_bool(b),
+      Value::U8(x) => visitor.visit_u8(x),
+      Value::U16(x) => visitor.visit_u16(x),
+      Value::U32(x) => visitor.visit_u32(x),
+      Value::U64(x) => visitor.visit_u64(x),
+      Value::I8(x) => visitor.visit_i8(x),
+      Value::I16(x) => visitor.visit_i16(x),
+      Value::I32(x) => visitor.visit_i32(x),
+      Value::I64(x) => visitor.visit_i64(x),
+      Value::F32(x) => visitor.visit_f32(x),
+      Value::F64(x) => visitor.visit_f64(x),
+      Value::Char(c) => visitor.visit_char(c),
+      Value::String(s) => visitor.visit_string(s),
+      Value::Unit => visitor.visit_unit(),
+      Value::Option(None) => visitor.visit_none(),
+      Value::Option(Some(inner)) => {
+        visitor.visit_some(KdlValueDeserializer(*inner))
+      }
+      Value::Newtype(inner) => {
+        visitor.visit_newtype_struct(KdlValueDeserializer(*inner))
+      }
+      Value::Seq(items) => {
+        let mut seq = Self::seq(items);
+        let out = visitor.visit_seq(&mut seq)?;
+        seq.end()?;
+        Ok(out)
+      }
+      Value::Map(map) => {
+        let mut map = Self::map(map);
+        let out = visitor.visit_map(&mut map)?;
+        map.end()?;
+        Ok(out)
+      }
+      Value::Bytes(bytes) => visitor.visit_byte_buf(bytes),
+    }
+  }
+
+  fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+  where
+    V: Visitor<'de>,
+  {
+    match self.0 {
+      Value::Unit | Value::Option(None) => visitor.visit_none(),
+      Value::Option(Some(inner)) => {
+        visitor.visit_some(KdlValueDeserializer(*inner))
+      }
+      other => visitor.visit_some(KdlValueDeserializer(other)),
+    }
+  }
+
+  fn deserialize_newtype_struct<V>(
+    self,
+    _name: &'static str,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error>
+  where
+    V: Visitor<'de>,
+  {
+    match self.0 {
+      Value::Newtype(inner) => {
+        visitor.visit_newtype_struct(KdlValueDeserializer(*inner))
+      }
+      other => visitor.visit_newtype_struct(KdlValueDeserializer(other)),
+    }
+  }
+
+  fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+  where
+    V: Visitor<'de>,
+  {
+    let items = match self.0 {
+      Value::Seq(items) => items,
+      Value::Unit => Vec::new(),
+      Value::Map(_) | Value::Bytes(_) => return self.deserialize_any(visitor),
+      single => vec![single],
+    };
+    let mut seq = Self::seq(items);
+    let out = visitor.visit_seq(&mut seq)?;
+    seq.end()?;
+    Ok(out)
+  }
+
+  fn deserialize_tuple<V>(
+    self,
+    _len: usize,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error>
+  where
+    V: Visitor<'de>,
+  {
+    self.deserialize_seq(visitor)
+  }
+
+  fn deserialize_tuple_struct<V>(
+    self,
+    _name: &'static str,
+    _len: usize,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error>
+  where
+    V: Visitor<'de>,
+  {
+    self.deserialize_seq(visitor)
+  }
+
+  fn deserialize_map<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+  where
+    V: Visitor<'de>,
+  {
+    match self.0 {
+      Value::Unit => {
+        let mut map = Self::map(BTreeMap::new());
+        visitor.visit_map(&mut map)
+      }
+      other => KdlValueDeserializer(other).deserialize_any(visitor),
+    }
+  }
+
+  fn deserialize_struct<V>(
+    self,
+    _name: &'static str,
+    fields: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Self::Error>
+  where
+    V: Visitor<'de>,
+  {
+    self.snake_case_fields(fields).deserialize_map(visitor)
+  }
+
+  fn deserialize_enum<V>(
+    self,
+    _name: &'static str,
+    _variants: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Self::Error>
+  where
+    V: Visitor<'de>,
+  {
+    match self.0 {
+      Value::String(variant) => visitor.visit_enum(KdlEnumAccess {
+        variant,
+        content: Value::Unit,
+      }),
+      Value::Map(map) if map.len() == 1 => {
+        let (variant, content) = map.into_iter().next().unwrap();
+        let Value::String(variant) = variant else {
+          return Err(KdlSerdeError::Custom(format!(
+            "expected an enum variant name, got {:?}",
+            variant
+          )));
+        };
+        visitor.visit_enum(KdlEnumAccess { variant, content })
+      }
+      other => Err(KdlSerdeError::Custom(format!(
+        "expected an enum variant, got {:?}",
+        other
+      ))),
+    }
+  }
+
+  deserialize_number! {
+    deserialize_u8 => visit_u8,
+    deserialize_u16 => visit_u16,
+    deserialize_u32 => visit_u32,
+    deserialize_u64 => visit_u64,
+    deserialize_i8 => visit_i8,
+    deserialize_i16 => visit_i16,
+    deserialize_i32 => visit_i32,
+    deserialize_i64 => visit_i64,
+    deserialize_f32 => visit_f32,
+    deserialize_f64 => visit_f64,
+  }
+
+  forward_to_deserialize_any! {
+    bool i128 u128 char str string bytes byte_buf unit unit_struct
+    identifier ignored_any
+  }
+}
+
+struct KdlEnumAccess {
+  variant: String,
+  content: Value,
+}
+
+impl<'de> EnumAccess<'de> for KdlEnumAccess {
+  type Error = KdlSerdeError;
+  type Variant = KdlValueDeserializer;
+
+  fn variant_seed<V>(
+    self,
+    seed: V,
+  ) -> Result<(V::Value, Self::Variant), Self::Error>
+  where
+    V: DeserializeSeed<'de>,
+  {
+    let variant =
+      seed.deserialize(KdlValueDeserializer(Value::String(self.variant)))?;
+    Ok((variant, KdlValueDeserializer(self.content)))
+  }
+}
+
+impl<'de> VariantAccess<'de> for KdlValueDeserializer {
+  type Error = KdlSerdeError;
+
+  fn unit_variant(self) -> Result<(), Self::Error> {
+    Ok(())
+  }
+
+  fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Self::Error>
+  where
+    T: DeserializeSeed<'de>,
+  {
+    seed.deserialize(self)
+  }
+
+  fn tuple_variant<V>(
+    self,
+    _len: usize,
+    visitor: V,
+  ) -> Result<V::Value, Self::Error>
+  where
+    V: Visitor<'de>,
+  {
+    self.deserialize_seq(visitor)
+  }
+
+  fn struct_variant<V>(
+    self,
+    fields: &'static [&'static str],
+    visitor: V,
+  ) -> Result<V::Value, Self::Error>
+  where
+    V: Visitor<'de>,
+  {
+    self.snake_case_fields(fields).deserialize_map(visitor)
+  }
+}
@@ -0,0 +1,80 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::prelude::World;
+
+use super::{
+  entity::EntitiesSerWrapper, load::with_load_context,
+  resource::ResourcesSerWrapper, version::SchemaVersionSer, WorldSerWrapper,
+};
+
+/// How the entities in a saved world are laid out.
+///
+/// Worlds can be loaded from either layout, no matter which one is the default.
+#[derive(
+  Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub enum WorldLayout {
+  /// A map of entities to their components.
+  ///
+  /// This needs a format that supports keys that aren't strings,
+  /// like Ron or bincode.
+  #[default]
+  EntityMap,
+  /// A sequence of `{ id: [index, generation], components: [...] }`.
+  ///
+  /// This works with JSON.
+  EntityList,
+}
+
+impl World {
+  /// Save a world with the given layout.
+  ///
+  /// Serializing a `World` normally is the same as calling this with the default layout.
+  pub fn serialize_with<S>(
+    &self,
+    serializer: S,
+    layout: WorldLayout,
+  ) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    let allocator = self.entities.allocator.try_read().unwrap();
+    let entities = EntitiesSerWrapper::new(self, layout);
+    let resources = ResourcesSerWrapper::new(self);
+
+    let wrapper = WorldSerWrapper {
+      version: SchemaVersionSer,
+      layout,
+      allocator: &allocator,
+      entities,
+      resources,
+    };
+    wrapper.serialize(serializer)
+  }
+}
+
+/// Reads the layout of the world being loaded, and remembers it in the
+/// load context for formats that can't tell on their own.
+#[derive(Default)]
+pub(super) struct WorldLayoutDe;
+
+impl<'de> Deserialize<'de> for WorldLayoutDe {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    let layout = WorldLayout::deserialize(deserializer)?;
+    with_load_context(|ctx| {
+      if let Some(ctx) = ctx {
+        ctx.layout = layout;
+      }
+    });
+    Ok(WorldLayoutDe)
+  }
+}
+
+/// Get the layout of the world currently being loaded.
+/// Outside of loading a world, it's the default.
+pub(super) fn loading_layout() -> WorldLayout {
+  with_load_context(|ctx| ctx.map(|ctx| ctx.layout)).unwrap_or_default()
+}
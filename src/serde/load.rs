@@ -0,0 +1,293 @@
+use std::{
+  cell::{Cell, RefCell},
+  fmt,
+};
+
+use serde::{de::DeserializeSeed, Deserializer};
+use serde_value::ValueDeserializer;
+
+use crate::prelude::{Entity, World};
+
+use super::{kdl, Value, WorldLayout};
+
+/// Options for loading a world with [`World::deserialize_with`].
+#[derive(Debug, Clone, Default)]
+pub struct LoadOptions {
+  /// What to do with components and resources whose friendly names aren't registered.
+  pub unknown: UnknownPolicy,
+  /// If true, entities, components, and resources that fail to load are
+  /// dropped and listed in the [`LoadReport`], instead of failing the whole load.
+  ///
+  /// Like [`UnknownPolicy::Preserve`], this only works with self-describing
+  /// formats, because everything has to be read into a
+  /// [`Value`](super::Value) first so the broken parts can be skipped over.
+  pub lenient: bool,
+  /// If true, the save is from before worlds recorded their schema version and
+  /// layout, so it's just the allocator, entities, and resources. It's
+  /// loaded as version 0.
+  ///
+  /// Self-describing formats can tell on their own, so this is only needed
+  /// for formats like bincode.
+  pub unversioned: bool,
+}
+
+/// What to do when loading a component or resource with a friendly name that isn't
+/// registered, like if a mod was removed or a component was deleted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnknownPolicy {
+  /// Fail to load the world. This is the default.
+  #[default]
+  Error,
+  /// Drop the unknown data, and list it in the [`LoadReport`].
+  Skip,
+  /// Keep the unknown data around as an opaque blob in
+  /// [`UnknownComponents`](super::UnknownComponents) or
+  /// [`UnknownResources`](super::UnknownResources),
+  /// which is written back out the next time the world is saved.
+  ///
+  /// This only works with self-describing formats. Also, the data is kept in
+  /// serde's loose data model, so formats that care about the difference
+  /// between structs and maps (like Ron) might not be able to read it back.
+  Preserve,
+}
+
+/// Everything that was dropped while loading a world.
+#[derive(Debug, Clone, Default)]
+pub struct LoadReport {
+  /// Entities and the friendly names of the components that were skipped on them.
+  pub skipped_components: Vec<(Entity, String)>,
+  /// Friendly names of the resources that were skipped.
+  pub skipped_resources: Vec<String>,
+  /// Everything that failed to load in [lenient](LoadOptions::lenient) mode.
+  pub broken: Vec<LoadError>,
+}
+
+impl LoadReport {
+  /// Return true if nothing was dropped.
+  pub fn is_empty(&self) -> bool {
+    self.skipped_components.is_empty()
+      && self.skipped_resources.is_empty()
+      && self.broken.is_empty()
+  }
+}
+
+/// Something that failed to load, and where it was.
+///
+/// When loading a world fails, the serde error's message is this
+/// struct's `Display`, like
+/// ``entity 3@0, component `has-hp`, field `start_hp`: invalid type: ...``.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoadError {
+  /// The entity being loaded, if there was one and its ID could be read.
+  pub entity: Option<Entity>,
+  /// Friendly name of the component being loaded.
+  pub component: Option<String>,
+  /// Friendly name of the resource being loaded.
+  pub resource: Option<String>,
+  /// Path to the field that failed inside the component or resource,
+  /// like `stats.hp` or `inventory[2]`.
+  pub field: Option<String>,
+  pub message: String,
+}
+
+impl LoadError {
+  /// An error about the entity currently being loaded, if any.
+  pub(super) fn new(message: impl fmt::Display) -> Self {
+    Self {
+      entity: loading_entity(),
+      component: None,
+      resource: None,
+      field: None,
+      message: message.to_string(),
+    }
+  }
+
+  pub(super) fn in_component(mut self, friendly_name: &str) -> Self {
+    self.component = Some(friendly_name.to_owned());
+    self
+  }
+
+  pub(super) fn in_resource(mut self, friendly_name: &str) -> Self {
+    self.entity = None;
+    self.resource = Some(friendly_name.to_owned());
+    self
+  }
+}
+
+impl fmt::Display for LoadError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let mut wrote_any = false;
+    let mut part = |f: &mut fmt::Formatter<'_>, args: fmt::Arguments| {
+      if wrote_any {
+        f.write_str(", ")?;
+      }
+      wrote_any = true;
+      f.write_fmt(args)
+    };
+    if let Some(entity) = self.entity {
+      part(f, format_args!("entity {:x}", entity))?;
+    }
+    if let Some(component) = &self.component {
+      part(f, format_args!("component `{}`", component))?;
+    }
+    if let Some(resource) = &self.resource {
+      part(f, format_args!("resource `{}`", resource))?;
+    }
+    if let Some(field) = &self.field {
+      part(f, format_args!("field `{}`", field))?;
+    }
+    if wrote_any {
+      f.write_str(": ")?;
+    }
+    f.write_str(&self.message)
+  }
+}
+
+impl std::error::Error for LoadError {}
+
+impl World {
+  /// Load a world, with options for what to do with the problems in it.
+  ///
+  /// Returns the world and a report of what had to be dropped to load it.
+  /// Deserializing a `World` normally is the same as calling this with the default options.
+  pub fn deserialize_with<'de, D>(
+    deserializer: D,
+    options: LoadOptions,
+  ) -> Result<(World, LoadReport), D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    let guard = LoadContextGuard::new(options);
+    let world = super::deserialize_world(deserializer)?;
+    Ok((world, guard.finish()))
+  }
+}
+
+/// State for the world currently being loaded, threaded down to the
+/// components and resources.
+pub(super) struct LoadContext {
+  /// Schema version the world was saved with.
+  pub version: u32,
+  pub layout: WorldLayout,
+  pub options: LoadOptions,
+  pub report: LoadReport,
+  /// The world is being read from KDL, so anything read ahead into a
+  /// [`Value`](super::Value) has to be read back the same way.
+  pub from_kdl: bool,
+}
+
+thread_local! {
+  /// `None` means things are being loaded outside of a world, and
+  /// are assumed to be up to date and well-formed.
+  static LOAD_CONTEXT: RefCell<Option<LoadContext>> = const { RefCell::new(None) };
+}
+
+thread_local! {
+  /// The entity whose components are being loaded right now, for errors.
+  ///
+  /// This is kept outside the load context so importing entities
+  /// gets nice errors too.
+  static LOADING_ENTITY: Cell<Option<Entity>> = const { Cell::new(None) };
+}
+
+/// Get the entity whose components are currently being loaded.
+pub(super) fn loading_entity() -> Option<Entity> {
+  LOADING_ENTITY.with(Cell::get)
+}
+
+pub(super) fn set_loading_entity(entity: Option<Entity>) {
+  LOADING_ENTITY.with(|it| it.set(entity));
+}
+
+/// Forgets the entity being loaded when it's dropped, even if loading it failed.
+pub(super) struct LoadingEntityReset;
+
+impl Drop for LoadingEntityReset {
+  fn drop(&mut self) {
+    set_loading_entity(None);
+  }
+}
+
+/// Check if broken things should be dropped instead of failing the load.
+pub(super) fn loading_leniently() -> bool {
+  with_load_context(|ctx| ctx.is_some_and(|ctx| ctx.options.lenient))
+}
+
+/// Load something that was read ahead into a [`Value`], the same way the
+/// format it was read from would have loaded it.
+pub(super) fn deserialize_read_ahead<'de, T, E>(
+  seed: T,
+  value: Value,
+) -> Result<T::Value, E>
+where
+  T: DeserializeSeed<'de>,
+  E: serde::de::Error,
+{
+  if with_load_context(|ctx| ctx.is_some_and(|ctx| ctx.from_kdl)) {
+    kdl::deserialize_value(seed, value).map_err(E::custom)
+  } else {
+    seed.deserialize(ValueDeserializer::<E>::new(value))
+  }
+}
+
+/// Handle something failing to load: in lenient mode, write it down in the
+/// report and carry on, and otherwise, fail.
+pub(super) fn report_broken<E: serde::de::Error>(
+  err: LoadError,
+) -> Result<(), E> {
+  with_load_context(|ctx| match ctx {
+    Some(ctx) if ctx.options.lenient => {
+      ctx.report.broken.push(err);
+      Ok(())
+    }
+    _ => Err(E::custom(err)),
+  })
+}
+
+/// Get something from the context of the world currently being loaded.
+pub(super) fn with_load_context<T>(
+  f: impl FnOnce(Option<&mut LoadContext>) -> T,
+) -> T {
+  LOAD_CONTEXT.with(|it| f(it.borrow_mut().as_mut()))
+}
+
+/// Sets up a load context until it's dropped, then puts back whatever was
+/// there before, so loads can happen inside other loads.
+pub(super) struct LoadContextGuard {
+  previous: Option<LoadContext>,
+  previous_entity: Option<Entity>,
+}
+
+impl LoadContextGuard {
+  /// Worlds saved before the version was recorded count as version 0.
+  pub(super) fn new(options: LoadOptions) -> Self {
+    let previous = LOAD_CONTEXT.with(|it| {
+      it.borrow_mut().replace(LoadContext {
+        version: 0,
+        layout: WorldLayout::default(),
+        options,
+        report: LoadReport::default(),
+        from_kdl: false,
+      })
+    });
+    let previous_entity = LOADING_ENTITY.with(|it| it.replace(None));
+    Self {
+      previous,
+      previous_entity,
+    }
+  }
+
+  pub(super) fn finish(self) -> LoadReport {
+    LOAD_CONTEXT
+      .with(|it| it.borrow_mut().take())
+      .map(|ctx| ctx.report)
+      .unwrap_or_default()
+  }
+}
+
+impl Drop for LoadContextGuard {
+  fn drop(&mut self) {
+    LOAD_CONTEXT.with(|it| *it.borrow_mut() = self.previous.take());
+    set_loading_entity(self.previous_entity);
+  }
+}
@@ -3,6 +3,7 @@ Serializing and deserializing worlds.
 
 Worlds are stored as:
 
+- the [schema version](schema_version) the world was saved with
 - a mapping of user-defined keys to resource data
 - the backing allocator for the entities
 - a mapping of entities to, a mapping of "friendly-name" keys to component data.
@@ -15,6 +16,8 @@ In pseudo-Ron, a serialized world will look something like this:
 
 ```text
 SerDeWorld(
+    version: 0,
+    layout: EntityMap,
     // The allocator (generational_arena) serializes itself;
     // this is what it happens to look like on the inside.
     // Frankly I'm not really sure what it's doing; the internals of that crate are
@@ -61,11 +64,31 @@ create a cache, like for (say) entity positions, then you
 shouldn't serialize whatever you're caching, or invalidate it before you
 load it.
 
+Components and resources registered as
+[transient](crate::component::ComponentRegisterer::transient) aren't saved at
+all, so they don't need to be serializable.
+
 ---
 
-Note that the entity serialization requires the ability to have keys
-that aren't strings. So, if you want to use a human-readable format,
-json won't work. But [Ron](https://crates.io/crates/ron) works great.
+Note that the default entity serialization requires the ability to have keys
+that aren't strings. So, if you want to use JSON, save the world with
+[`World::serialize_with`] and [`WorldLayout::EntityList`], which writes the
+entities as a list like this instead:
+
+```text
+"entities": [
+    {"id": [0, 0], "components": [{"position": [0.0, 1.0, 2.0]}, ...]},
+    ...
+]
+```
+
+Or, [Ron](https://crates.io/crates/ron) works great with either layout.
+
+Worlds can also be saved as KDL with [`World::to_kdl`] and loaded with
+[`World::from_kdl`]. Each component is written as a node the same way it
+would be written in a blueprint, so saved entities can be read (or
+hand-edited) like blueprints. See the [`kdl`](self::kdl) module for what it
+looks like.
 
 For something compact, remember that a lot of binary formats aren't amazingly
 compatible when the schema changes.
@@ -78,43 +101,113 @@ If you're worried about this leading to gigantic save files, gzipping it should
 probably help a lot.
 
 But, you can freely add *new* component types as you develop a game, and old saves should be compatible.
+If you remove a component or resource, old saves that have it will fail to load,
+unless you load them with [`World::deserialize_with`] and an [`UnknownPolicy`]
+to skip or preserve it.
+
+When something in a save is malformed, the error says which entity, component
+or resource, and field it was in (see [`LoadError`]). If you'd rather load
+everything that *isn't* broken, set [`LoadOptions::lenient`], and everything
+that was dropped is listed in the [`LoadReport`].
+
+If you need to change an existing component, register a
+[migration](crate::component::ComponentRegisterer::migrate_from) for it,
+which bumps the [schema version](schema_version), and old saves will be
+converted as they're loaded, through every migration since they were saved.
+Saves from before the schema version was recorded load as version 0; formats
+that aren't self-describing, like bincode, need
+[`LoadOptions::unversioned`] to load them, because their old layout can't be
+told apart from the new one. So bincode saves from palkia 0.16 and earlier
+don't load without it.
+
+---
+
+You can also serialize just some of the entities in a world with
+[`World::export_entities`], and load them into another world with
+[`World::import_entities`]. The imported entities get fresh IDs, so any
+component storing entities must implement
+[`MapEntities`](crate::entities::MapEntities) to have them rewritten.
+Exports record the schema version too, so old exports are migrated on import.
+Like saves, exports to JSON need [`World::export_entities_with`] and
+[`WorldLayout::EntityList`].
+
+The allocator is saved with all of its free slots and generations, so saves
+from long games can get bloated. Save with [`World::serialize_compacted`] to
+renumber the entities densely from zero in the save, which rewrites the
+entities stored in components and resources with their `MapEntities` impls,
+like importing does. The world itself is left alone;
+[`World::compaction_map`] says what each entity becomes in the save.
+
+---
+
+For undo, or rolling back a game, [`World::snapshot`] takes a copy of the
+world that [`World::restore`] can go back to. Components keep track of when
+they were last mutably borrowed, so snapshots only serialize the components
+that changed since the last one, and restoring only deserializes the ones that
+changed since the snapshot. Each snapshot also lists what was spawned,
+despawned, and changed since the one before it.
+
+To compare two worlds, [`World::diff`] makes a [`WorldPatch`] listing the
+entities, components, and resources that differ, which
+[`World::apply_patch`] can apply to another world. Patches are serializable
+themselves, so they can be sent over the network or checked in for golden
+tests.
 
 */
 
+mod compact;
 mod component;
 mod entity;
+pub mod kdl;
+mod layout;
+mod load;
+mod patch;
 mod resource;
+mod snapshot;
+mod subset;
+mod unknown;
+mod version;
+
+use std::marker::PhantomData;
 
 use generational_arena::Arena;
 
 use serde::{
-  de::DeserializeSeed, Deserialize, Deserializer, Serialize, Serializer,
+  de::{DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor},
+  Deserialize, Deserializer, Serialize, Serializer,
 };
+use serde_path_to_error::Track;
 
 use crate::{
   prelude::World, vtablesathome::DeserializeFn, world::storage::EntityStorage,
 };
 
+pub(crate) use self::component::clone_component;
+pub use self::{
+  component::ComponentSerWrapper,
+  layout::WorldLayout,
+  load::{LoadError, LoadOptions, LoadReport, UnknownPolicy},
+  patch::{Change, ComponentPatch, PatchError, ResourcePatch, WorldPatch},
+  snapshot::Snapshot,
+  subset::EntitiesExport,
+  unknown::{UnknownComponents, UnknownResources},
+  version::schema_version,
+};
 use self::{
   entity::{EntitiesDeWrapper, EntitiesSerWrapper},
+  layout::WorldLayoutDe,
+  load::{deserialize_read_ahead, report_broken, with_load_context},
   resource::{ResourcesDeWrapper, ResourcesSerWrapper},
+  version::{SchemaVersionDe, SchemaVersionSer},
 };
+pub use serde_value::Value;
 
 impl Serialize for World {
   fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
   where
     S: Serializer,
   {
-    let allocator = self.entities.allocator.try_read().unwrap();
-    let entities = EntitiesSerWrapper::new(self);
-    let resources = ResourcesSerWrapper::new(self);
-
-    let wrapper = WorldSerWrapper {
-      allocator: &*allocator,
-      entities,
-      resources,
-    };
-    wrapper.serialize(serializer)
+    self.serialize_with(serializer, WorldLayout::default())
   }
 }
 
@@ -123,36 +216,179 @@ impl<'de> Deserialize<'de> for World {
   where
     D: Deserializer<'de>,
   {
-    let wrapper = WorldDeWrapper::deserialize(deserializer)?;
-
-    let mut world = World::new();
-    // do i ... repeat, repeat myself?
-    world.resources = wrapper.resources.resources;
-    world.entities =
-      EntityStorage::new(wrapper.allocator, wrapper.entities.entities);
+    let (world, _report) =
+      World::deserialize_with(deserializer, LoadOptions::default())?;
+    Ok(world)
+  }
+}
 
-    for e in world.entities() {
-      world.run_creation_callbacks(e);
+/// Actually load the world, assuming the load context has been set up.
+fn deserialize_world<'de, D>(deserializer: D) -> Result<World, D::Error>
+where
+  D: Deserializer<'de>,
+{
+  let unversioned =
+    with_load_context(|ctx| ctx.is_some_and(|ctx| ctx.options.unversioned));
+  let wrapper = if unversioned {
+    let UnversionedWorldDeWrapper {
+      allocator,
+      entities,
+      resources,
+    } = UnversionedWorldDeWrapper::deserialize(deserializer)?;
+    WorldDeWrapper {
+      allocator,
+      entities,
+      resources,
     }
-    world.finalize();
-    Ok(world)
+  } else {
+    WorldDeWrapper::deserialize(deserializer)?
+  };
+
+  let mut world = World::new();
+  // do i ... repeat, repeat myself?
+  world.resources = wrapper.resources.resources;
+  world.entities =
+    EntityStorage::new(wrapper.allocator, wrapper.entities.entities);
+
+  for e in world.entities() {
+    world.run_creation_callbacks(e);
   }
+  world.finalize();
+  Ok(world)
 }
 
 #[derive(Serialize)]
 struct WorldSerWrapper<'w> {
+  version: SchemaVersionSer,
+  layout: WorldLayout,
   allocator: &'w Arena<()>,
   entities: EntitiesSerWrapper<'w>,
   resources: ResourcesSerWrapper<'w>,
 }
 
-#[derive(Deserialize)]
+/// The version and layout are put in the load context as they're read,
+/// so they aren't kept here.
 struct WorldDeWrapper {
   allocator: Arena<()>,
   entities: EntitiesDeWrapper,
   resources: ResourcesDeWrapper,
 }
 
+impl<'de> Deserialize<'de> for WorldDeWrapper {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    deserializer.deserialize_struct(
+      "WorldSerWrapper",
+      &["version", "layout", "allocator", "entities", "resources"],
+      WorldDeVisitor,
+    )
+  }
+}
+
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "lowercase")]
+enum WorldField {
+  Version,
+  Layout,
+  Allocator,
+  Entities,
+  Resources,
+  #[serde(other)]
+  Other,
+}
+
+struct WorldDeVisitor;
+
+impl<'de> Visitor<'de> for WorldDeVisitor {
+  type Value = WorldDeWrapper;
+
+  fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(formatter, "a saved world")
+  }
+
+  fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+  where
+    A: SeqAccess<'de>,
+  {
+    let missing = |idx| serde::de::Error::invalid_length(idx, &self);
+    seq
+      .next_element::<SchemaVersionDe>()?
+      .ok_or_else(|| missing(0))?;
+    seq
+      .next_element::<WorldLayoutDe>()?
+      .ok_or_else(|| missing(1))?;
+    let allocator = seq.next_element()?.ok_or_else(|| missing(2))?;
+    let entities = seq.next_element()?.ok_or_else(|| missing(3))?;
+    let resources = seq.next_element()?.ok_or_else(|| missing(4))?;
+    Ok(WorldDeWrapper {
+      allocator,
+      entities,
+      resources,
+    })
+  }
+
+  fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+  where
+    A: MapAccess<'de>,
+  {
+    let mut read_version = false;
+    let mut allocator = None;
+    let mut entities = None;
+    let mut resources = None;
+    // If the entities come first, hold onto them until the version is known,
+    // so their components can be migrated. Saves from before the version was
+    // recorded don't have one, and count as version 0.
+    let mut early_entities = None;
+    while let Some(key) = map.next_key()? {
+      match key {
+        WorldField::Version => {
+          map.next_value::<SchemaVersionDe>()?;
+          read_version = true;
+        }
+        WorldField::Layout => {
+          map.next_value::<WorldLayoutDe>()?;
+        }
+        WorldField::Allocator => allocator = Some(map.next_value()?),
+        WorldField::Entities if read_version => {
+          entities = Some(map.next_value()?)
+        }
+        WorldField::Entities => {
+          early_entities = Some(map.next_value::<Value>()?)
+        }
+        WorldField::Resources => resources = Some(map.next_value()?),
+        WorldField::Other => {
+          map.next_value::<IgnoredAny>()?;
+        }
+      }
+    }
+
+    if let Some(early) = early_entities {
+      entities = Some(deserialize_read_ahead(PhantomData, early)?);
+    }
+    Ok(WorldDeWrapper {
+      allocator: allocator
+        .ok_or_else(|| serde::de::Error::missing_field("allocator"))?,
+      entities: entities
+        .ok_or_else(|| serde::de::Error::missing_field("entities"))?,
+      resources: resources
+        .ok_or_else(|| serde::de::Error::missing_field("resources"))?,
+    })
+  }
+}
+
+/// How worlds were saved before the schema version and layout were recorded.
+///
+/// Self-describing formats can load these with [`WorldDeWrapper`], but
+/// other formats have to be told with [`LoadOptions::unversioned`].
+#[derive(Deserialize)]
+struct UnversionedWorldDeWrapper {
+  allocator: Arena<()>,
+  entities: EntitiesDeWrapper,
+  resources: ResourcesDeWrapper,
+}
+
 struct ErasedSerWrapper<'a, T: ?Sized> {
   inner: &'a T,
 }
@@ -175,27 +411,59 @@ where
   }
 }
 
+/// Load a component or resource that was kept around as a [`Value`], like in
+/// a snapshot or a patch.
+fn from_value<T: ?Sized>(
+  deser: DeserializeFn<T>,
+  data: &Value,
+) -> erased_serde::Result<Box<T>> {
+  let mut erased = <dyn erased_serde::Deserializer>::erase(data.clone());
+  deser(&mut erased)
+}
+
 /// Deserializer that applies the deser fn to an erased deserializer.
 /// Used for component/resource deserialization.
 ///
+/// If it fails, the error is filled in with which field it failed at and
+/// [reported](report_broken), and in lenient mode this returns `None`.
+///
 /// Thanks typetag for notes here.
 struct ApplyDeserFn<T: ?Sized> {
   deser: DeserializeFn<T>,
+  /// What's being loaded, to fill in errors with.
+  place: LoadError,
 }
 
 impl<'de, T> DeserializeSeed<'de> for ApplyDeserFn<T>
 where
   T: ?Sized,
 {
-  type Value = Box<T>;
+  type Value = Option<Box<T>>;
 
   fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
   where
     D: Deserializer<'de>,
   {
-    // it's automatically maintainable and readable because it's written
-    // in crab language
-    let mut erased = <dyn erased_serde::Deserializer>::erase(deserializer);
-    (self.deser)(&mut erased).map_err(serde::de::Error::custom)
+    let mut track = Track::new();
+    let res = {
+      let tracked =
+        serde_path_to_error::Deserializer::new(deserializer, &mut track);
+      // it's automatically maintainable and readable because it's written
+      // in crab language
+      let mut erased = <dyn erased_serde::Deserializer>::erase(tracked);
+      (self.deser)(&mut erased)
+    };
+    match res {
+      Ok(it) => Ok(Some(it)),
+      Err(err) => {
+        let path = track.path();
+        report_broken(LoadError {
+          field: path.iter().next().is_some().then(|| path.to_string()),
+          message: err.to_string(),
+          ..self.place
+        })?;
+        Ok(None)
+      }
+    }
   }
 }
@@ -0,0 +1,389 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use generational_arena::Arena;
+use serde::{Deserialize, Serialize};
+use serde_value::Value;
+use thiserror::Error;
+
+use crate::{
+  prelude::{Component, Entity, EntityLiveness, World},
+  resource::Resource,
+  vtablesathome::{
+    ComponentVtable, ComponentVtables, DeserializeFn, ResourceVtable,
+    ResourceVtables, SerializeFn,
+  },
+  world::storage::EntityAssoc,
+  ToTypeIdWrapper, TypeIdWrapper,
+};
+
+use super::{from_value, snapshot::to_value};
+
+impl World {
+  /// Find the differences between this world and another one, as a patch that
+  /// turns this world into the other when [applied](World::apply_patch).
+  ///
+  /// Components are compared by their serialized data, so this only makes
+  /// sense between worlds that share entities, like a world and a copy of it
+  /// loaded from a save. Like with serde, transient components and resources
+  /// are ignored.
+  ///
+  /// Lazy updates that haven't been [finalized](World::finalize) aren't seen.
+  ///
+  /// Panics if a component or resource can't be serialized.
+  pub fn diff(&self, other: &World) -> WorldPatch {
+    let mut ours = self.entities().collect::<Vec<_>>();
+    ours.sort();
+    let mut theirs = other.entities().collect::<Vec<_>>();
+    theirs.sort();
+
+    let mut patch = WorldPatch::default();
+    for &e in ours.iter() {
+      if theirs.binary_search(&e).is_err() {
+        patch.removed_entities.push(e);
+      }
+    }
+    for &e in theirs.iter() {
+      let their_assoc = other.entities.get(e);
+      let our_assoc = if ours.binary_search(&e).is_ok() {
+        Some(self.entities.get(e))
+      } else {
+        patch.added_entities.push(e);
+        None
+      };
+
+      for (tid, entry) in their_assoc.iter() {
+        let vtable = ComponentVtables::by_tid(tid);
+        let Some(ser) = saved_component(vtable) else {
+          continue;
+        };
+        let theirs = {
+          let lock = entry.read().unwrap();
+          to_value(ser(&**lock), "component", vtable.friendly_name)
+        };
+        let ours = our_assoc.and_then(|assoc| {
+          let entry = assoc.components().get(&tid)?;
+          let lock = entry.read().unwrap();
+          Some(to_value(ser(&**lock), "component", vtable.friendly_name))
+        });
+        let change = match ours {
+          None => Change::Added(theirs),
+          Some(ours) if ours != theirs => Change::Changed(theirs),
+          Some(_) => continue,
+        };
+        patch.components.push(ComponentPatch {
+          entity: e,
+          name: vtable.friendly_name.to_owned(),
+          change,
+        });
+      }
+      for (tid, _) in our_assoc.iter().flat_map(|assoc| assoc.iter()) {
+        let vtable = ComponentVtables::by_tid(tid);
+        if saved_component(vtable).is_some()
+          && !their_assoc.components().contains_key(&tid)
+        {
+          patch.components.push(ComponentPatch {
+            entity: e,
+            name: vtable.friendly_name.to_owned(),
+            change: Change::Removed,
+          });
+        }
+      }
+    }
+
+    let tids = self
+      .resources
+      .iter()
+      .chain(other.resources.iter())
+      .map(|(tid, _)| tid)
+      .collect::<BTreeSet<_>>();
+    for tid in tids {
+      let vtable = ResourceVtables::by_tid(tid);
+      let Some(ser) = saved_resource(vtable) else {
+        continue;
+      };
+      let value_in = |world: &World| {
+        let res = world.resources.get_raw(tid)?;
+        let lock = res.read().unwrap();
+        Some(to_value(ser(&**lock), "resource", vtable.friendly_name))
+      };
+      let change = match (value_in(self), value_in(other)) {
+        (None, Some(theirs)) => Change::Added(theirs),
+        (Some(_), None) => Change::Removed,
+        (Some(ours), Some(theirs)) if ours != theirs => Change::Changed(theirs),
+        _ => continue,
+      };
+      patch.resources.push(ResourcePatch {
+        name: vtable.friendly_name.to_owned(),
+        change,
+      });
+    }
+    patch.resources.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let allocator = |world: &World| {
+      let alloc = world.entities.allocator.read().unwrap();
+      serde_value::to_value(&*alloc).unwrap()
+    };
+    if allocator(self) != allocator(other) {
+      patch.allocator = Some(other.entities.allocator.read().unwrap().clone());
+    }
+
+    patch
+  }
+
+  /// Apply a patch from [`World::diff`] to this world.
+  ///
+  /// Removed entities are thrown out without running any callbacks, like
+  /// [`World::despawn`]. Otherwise, callbacks are run the same as if each
+  /// entity had been [edited](World::edit): creation callbacks for new
+  /// component types, and removal callbacks for removed ones.
+  ///
+  /// Everything in the patch is checked before anything is changed, so if this
+  /// returns an error the world is left as it was.
+  ///
+  /// This doesn't finalize the world, so lazy updates that were already queued
+  /// stay queued, and so does anything the callbacks do lazily. Patches that
+  /// spawn or despawn entities replace the allocator, which would pull the
+  /// rug out from under queued spawns, so those fail with
+  /// [`PatchError::Unfinalized`] until the world is
+  /// [finalized](World::finalize).
+  pub fn apply_patch(&mut self, patch: &WorldPatch) -> Result<(), PatchError> {
+    // Check and load everything up front
+    if patch.allocator.is_some() && self.has_lazy_updates() {
+      return Err(PatchError::Unfinalized);
+    }
+    for &e in patch.removed_entities.iter() {
+      if self.entities.liveness(e) != EntityLiveness::Alive {
+        return Err(PatchError::NotAlive(e));
+      }
+    }
+    for &e in patch.added_entities.iter() {
+      if self.entities.liveness(e) == EntityLiveness::Alive {
+        return Err(PatchError::AlreadyAlive(e));
+      }
+      if !patch
+        .allocator
+        .as_ref()
+        .is_some_and(|alloc| alloc.contains(e.0))
+      {
+        return Err(PatchError::NotAllocated(e));
+      }
+    }
+    if let Some(alloc) = &patch.allocator {
+      for e in self.entities.iter() {
+        if !alloc.contains(e.0) && !patch.removed_entities.contains(&e) {
+          return Err(PatchError::AllocatorMismatch(e));
+        }
+      }
+    }
+
+    let mut components = Vec::with_capacity(patch.components.len());
+    for comp in patch.components.iter() {
+      let alive = self.entities.liveness(comp.entity) == EntityLiveness::Alive
+        && !patch.removed_entities.contains(&comp.entity);
+      if !alive && !patch.added_entities.contains(&comp.entity) {
+        return Err(PatchError::NotAlive(comp.entity));
+      }
+      let vtable = ComponentVtables::try_by_friendly_name(&comp.name)
+        .filter(|vtable| saved_component(vtable).is_some())
+        .ok_or_else(|| PatchError::UnknownComponent(comp.name.clone()))?;
+      let loaded = match &comp.change {
+        Change::Added(data) | Change::Changed(data) => {
+          Some(load_value(vtable.deser.unwrap(), data, &comp.name)?)
+        }
+        Change::Removed => None,
+      };
+      components.push((comp.entity, vtable.tid, loaded));
+    }
+
+    let mut resources = Vec::with_capacity(patch.resources.len());
+    for res in patch.resources.iter() {
+      let vtable = ResourceVtables::try_by_friendly_name(&res.name)
+        .filter(|vtable| saved_resource(vtable).is_some())
+        .ok_or_else(|| PatchError::UnknownResource(res.name.clone()))?;
+      let loaded: Option<Box<dyn Resource>> = match &res.change {
+        Change::Added(data) | Change::Changed(data) => {
+          Some(load_value(vtable.deser.unwrap(), data, &res.name)?)
+        }
+        Change::Removed => None,
+      };
+      resources.push((vtable.tid, loaded));
+    }
+
+    // Now actually change things
+    for &e in patch.removed_entities.iter() {
+      if patch.allocator.is_some() {
+        self.entities.take(e);
+      } else {
+        self.entities.despawn(e);
+      }
+    }
+    if let Some(alloc) = &patch.allocator {
+      *self.entities.allocator.get_mut().unwrap() = alloc.clone();
+    }
+    for &e in patch.added_entities.iter() {
+      self.entities.finish_spawn(e, EntityAssoc::empty());
+    }
+
+    // Entities to the component types they started with, and what was removed
+    let mut edited = BTreeMap::<
+      Entity,
+      (BTreeSet<TypeIdWrapper>, Vec<Box<dyn Component>>),
+    >::new();
+    for (e, tid, loaded) in components {
+      let assoc = self.entities.get_mut(e);
+      let (_, removed) = edited.entry(e).or_insert_with(|| {
+        (assoc.iter().map(|(tid, _)| tid).collect(), Vec::new())
+      });
+      match loaded {
+        Some(comp) => {
+          assoc.insert_raw(comp);
+        }
+        None => removed.extend(assoc.remove(tid)),
+      }
+    }
+
+    for (tid, loaded) in resources {
+      match loaded {
+        Some(res) => {
+          self.resources.insert_raw(res);
+        }
+        None => {
+          self.resources.remove_raw(tid);
+        }
+      }
+    }
+
+    // Only run callbacks once everything is in, so they can see each other
+    for (e, (original, removed)) in edited {
+      let assoc = self.entities.get(e);
+      let removed = removed
+        .into_iter()
+        .filter(|comp| {
+          !assoc.components().contains_key(&(**comp).type_id_wrapper())
+        })
+        .collect::<Vec<_>>();
+      self.run_removal_callback(e, EntityAssoc::new(removed));
+      self.run_creation_callbacks_filtered(e, |tid| !original.contains(&tid));
+    }
+
+    Ok(())
+  }
+}
+
+/// The differences between two worlds. Get one with [`World::diff`], and
+/// apply it with [`World::apply_patch`].
+///
+/// This is serializable, so it can be sent somewhere else or checked into a
+/// golden test. Components and resources are stored by friendly name, the same
+/// as in a saved world.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WorldPatch {
+  /// Entities alive in the new world but not the old one. Their components
+  /// are listed in [`components`](Self::components).
+  #[serde(default)]
+  pub added_entities: Vec<Entity>,
+  /// Entities alive in the old world but not the new one.
+  #[serde(default)]
+  pub removed_entities: Vec<Entity>,
+  /// Components added, removed, or changed, in order of entity.
+  #[serde(default)]
+  pub components: Vec<ComponentPatch>,
+  /// Resources added, removed, or changed, in order of friendly name.
+  #[serde(default)]
+  pub resources: Vec<ResourcePatch>,
+  /// The new world's allocator, if it's any different, so spawned entities
+  /// get the same IDs.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  allocator: Option<Arena<()>>,
+}
+
+impl WorldPatch {
+  /// Whether the worlds were the same.
+  pub fn is_empty(&self) -> bool {
+    self.added_entities.is_empty()
+      && self.removed_entities.is_empty()
+      && self.components.is_empty()
+      && self.resources.is_empty()
+      && self.allocator.is_none()
+  }
+}
+
+/// A change to one component in a [`WorldPatch`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ComponentPatch {
+  pub entity: Entity,
+  /// The friendly name of the component.
+  pub name: String,
+  pub change: Change,
+}
+
+/// A change to one resource in a [`WorldPatch`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ResourcePatch {
+  /// The friendly name of the resource.
+  pub name: String,
+  pub change: Change,
+}
+
+/// What happened to a component or resource.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Change {
+  /// It's new, with this data.
+  Added(Value),
+  /// It's gone.
+  Removed,
+  /// It's different, and now has this data.
+  Changed(Value),
+}
+
+/// Something wrong with a patch passed to [`World::apply_patch`], usually
+/// because it was made from a different world.
+#[derive(Debug, Error)]
+pub enum PatchError {
+  #[error("the patch changes entity {0:x}, which isn't alive")]
+  NotAlive(Entity),
+  #[error("the patch adds entity {0:x}, which is already alive")]
+  AlreadyAlive(Entity),
+  #[error("the patch adds entity {0:x}, but doesn't allocate it")]
+  NotAllocated(Entity),
+  #[error("entity {0:x} is alive, but wouldn't be allocated after the patch")]
+  AllocatorMismatch(Entity),
+  #[error("the patch replaces the allocator, but the world isn't finalized")]
+  Unfinalized,
+  #[error("the component `{0}` isn't registered, or isn't serializable")]
+  UnknownComponent(String),
+  #[error("the resource `{0}` isn't registered, or isn't serializable")]
+  UnknownResource(String),
+  #[error("could not deserialize `{name}`: {message}")]
+  Deserialize { name: String, message: String },
+}
+
+/// Get the serializer for a component, if it gets saved at all.
+fn saved_component(
+  vtable: &ComponentVtable,
+) -> Option<SerializeFn<dyn Component>> {
+  vtable
+    .ser
+    .filter(|_| !vtable.transient && vtable.deser.is_some())
+}
+
+/// Get the serializer for a resource, if it gets saved at all.
+fn saved_resource(
+  vtable: &ResourceVtable,
+) -> Option<SerializeFn<dyn Resource>> {
+  vtable
+    .ser
+    .filter(|_| !vtable.transient && vtable.deser.is_some())
+}
+
+/// Load a component or resource from a patch.
+fn load_value<T: ?Sized>(
+  deser: DeserializeFn<T>,
+  data: &Value,
+  friendly_name: &str,
+) -> Result<Box<T>, PatchError> {
+  from_value(deser, data).map_err(|err| PatchError::Deserialize {
+    name: friendly_name.to_owned(),
+    message: err.to_string(),
+  })
+}
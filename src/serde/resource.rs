@@ -1,14 +1,22 @@
 use serde::{
-  de::{MapAccess, Visitor},
+  de::{IgnoredAny, MapAccess, Visitor},
   ser::SerializeMap,
   Deserialize, Serialize, Serializer,
 };
 
 use crate::{
-  prelude::World, vtablesathome::ResourceVtables, world::storage::ResourceMap,
+  entities::EntityMap, prelude::World, vtablesathome::ResourceVtables,
+  world::storage::ResourceMap, TypeIdWrapper,
 };
 
-use super::{ApplyDeserFn, ErasedSerWrapper};
+use super::{
+  from_value,
+  load::{
+    deserialize_read_ahead, loading_leniently, report_broken, with_load_context,
+  },
+  unknown::handle_unknown,
+  ApplyDeserFn, ErasedSerWrapper, LoadError, UnknownResources, Value,
+};
 
 // =====================
 // === SERIALIZATION ===
@@ -19,11 +27,20 @@ use super::{ApplyDeserFn, ErasedSerWrapper};
 /// We send this to Serde and pretend it's the whole map.
 pub(super) struct ResourcesSerWrapper<'w> {
   pub world: &'w World,
+  /// What to renumber the entities in the resources to, if anything.
+  pub map: Option<&'w EntityMap>,
 }
 
 impl<'w> ResourcesSerWrapper<'w> {
   pub(super) fn new(world: &'w World) -> Self {
-    Self { world }
+    Self { world, map: None }
+  }
+
+  /// Rewrite the entities in resources that registered a
+  /// [`MapEntities`](crate::entities::MapEntities) impl as they're written.
+  pub(super) fn mapped(mut self, map: &'w EntityMap) -> Self {
+    self.map = Some(map);
+    self
   }
 }
 
@@ -32,16 +49,59 @@ impl<'w> Serialize for ResourcesSerWrapper<'w> {
   where
     S: Serializer,
   {
-    let len = self.world.resources.len();
+    // Unknown resources get flattened back out into their own entries
+    let unknown = self
+      .world
+      .resources
+      .read::<UnknownResources>()
+      .ok()
+      .map(|unknown| unknown.0.clone());
+    // Transient resources are left out entirely
+    let saved = self
+      .world
+      .resources
+      .iter()
+      .filter(|(tid, _)| {
+        *tid != TypeIdWrapper::of::<UnknownResources>()
+          && !ResourceVtables::by_tid(*tid).transient
+      })
+      .collect::<Vec<_>>();
+    let len = saved.len() + unknown.as_ref().map_or(0, Vec::len);
 
     let mut map = serializer.serialize_map(Some(len))?;
 
-    for (tid, res) in self.world.resources.iter() {
+    for (tid, res) in saved {
       let vtable = ResourceVtables::by_tid(tid);
+      let ser = vtable.ser.ok_or_else(|| {
+        <S::Error as serde::ser::Error>::custom(format!(
+          "resource {} is not serializable; register it as transient",
+          vtable.friendly_name
+        ))
+      })?;
       let lock = res.read().unwrap();
 
       map.serialize_key(vtable.friendly_name)?;
-      map.serialize_value(&ErasedSerWrapper::new(&**lock))?;
+      match (self.map, vtable.map_entities, vtable.deser) {
+        (Some(entity_map), Some(map_entities), Some(deser)) => {
+          // Rewrite a copy, so the world itself isn't touched
+          let err = |err: &dyn std::fmt::Display| {
+            <S::Error as serde::ser::Error>::custom(format!(
+              "could not copy resource {}: {}",
+              vtable.friendly_name, err
+            ))
+          };
+          let value =
+            serde_value::to_value(ErasedSerWrapper::new(ser(&**lock)))
+              .map_err(|e| err(&e))?;
+          let mut copy = from_value(deser, &value).map_err(|e| err(&e))?;
+          map_entities(&mut *copy, entity_map);
+          map.serialize_value(&ErasedSerWrapper::new(ser(&*copy)))?;
+        }
+        _ => map.serialize_value(&ErasedSerWrapper::new(ser(&**lock)))?,
+      }
+    }
+    for (friendly_name, data) in unknown.iter().flatten() {
+      map.serialize_entry(friendly_name, data)?;
     }
     map.end()
   }
@@ -79,14 +139,56 @@ impl<'de> Visitor<'de> for ResourcesDeVisitor {
     A: MapAccess<'de>,
   {
     let mut out = ResourceMap::new();
+    let mut unknown = Vec::new();
     while let Some(key) = map.next_key()? {
       // force type
       let _: String = key;
-      let vtable = ResourceVtables::by_friendly_name(&key);
-      let res = map.next_value_seed(ApplyDeserFn {
-        deser: vtable.deser,
-      })?;
-      out.insert_raw(res);
+      let Some(vtable) = ResourceVtables::try_by_friendly_name(&key) else {
+        match handle_unknown("resource", &key, &mut map)? {
+          Some(data) => unknown.push((key, data)),
+          None => with_load_context(|ctx| {
+            if let Some(ctx) = ctx {
+              ctx.report.skipped_resources.push(key);
+            }
+          }),
+        }
+        continue;
+      };
+      if vtable.transient {
+        // It'll be rebuilt after loading if it can be
+        map.next_value::<IgnoredAny>()?;
+        continue;
+      }
+      let place = LoadError::new("").in_resource(&key);
+      let Some(deser) = vtable.deser else {
+        report_broken(LoadError {
+          message: "not deserializable; register it as transient".to_owned(),
+          ..place
+        })?;
+        map.next_value::<IgnoredAny>()?;
+        continue;
+      };
+      let seed = ApplyDeserFn { deser, place };
+      let res = if loading_leniently() {
+        // Read it all in first, so it can be skipped if it's broken
+        let data: Value = map.next_value()?;
+        deserialize_read_ahead(seed, data)?
+      } else {
+        map.next_value_seed(seed)?
+      };
+      if let Some(res) = res {
+        out.insert_raw(res);
+      }
+    }
+    if !unknown.is_empty() {
+      out.insert(UnknownResources(unknown));
+    }
+    for vtable in ResourceVtables::iter() {
+      if let Some(rebuild) = vtable.rebuild {
+        if !out.contains_tid(vtable.tid) {
+          out.insert_raw(rebuild());
+        }
+      }
     }
 
     Ok(out)
@@ -0,0 +1,358 @@
+use std::{
+  collections::{BTreeMap, BTreeSet},
+  sync::Arc,
+};
+
+use ahash::AHashMap;
+use generational_arena::Arena;
+use serde_value::Value;
+
+use crate::{
+  prelude::{Entity, World},
+  vtablesathome::{ComponentVtables, DeserializeFn, ResourceVtables},
+  world::storage::{ComponentEntry, EntityAssoc, EntityStorage},
+  TypeIdWrapper,
+};
+
+use super::{from_value, ErasedSerWrapper};
+
+impl World {
+  /// Take a snapshot of the world, to roll back to later with
+  /// [`World::restore`].
+  ///
+  /// This is much cheaper than serializing the whole world. Components that
+  /// haven't been mutably borrowed since the last snapshot (or restore) reuse
+  /// the data from that one, instead of being serialized all over again.
+  /// Resources don't keep track of when they change, so they're serialized
+  /// every time.
+  ///
+  /// The snapshot also records what changed since the last one; see
+  /// [`Snapshot::spawned`] and friends.
+  ///
+  /// Like with serde, transient components and resources aren't saved.
+  /// Lazy updates that haven't been [finalized](World::finalize) aren't seen,
+  /// and are left queued.
+  ///
+  /// Panics if a component or resource can't be serialized.
+  pub fn snapshot(&mut self) -> Snapshot {
+    let last = self.last_snapshot.take();
+    let last = last.as_ref().map(|it| &*it.inner);
+
+    let mut entities = AHashMap::with_capacity(self.entities.len());
+    let mut spawned = Vec::new();
+    let mut changed_components = Vec::new();
+    for e in self.entities.iter() {
+      let assoc = self.entities.get(e);
+      let old = last.and_then(|last| last.entities.get(&e));
+      match old {
+        None => spawned.push(e),
+        Some(old) => {
+          // Note the components that got taken off
+          for comp in old {
+            if !assoc.components().contains_key(&comp.tid) {
+              changed_components
+                .push((e, ComponentVtables::by_tid(comp.tid).friendly_name));
+            }
+          }
+        }
+      }
+
+      let comps = assoc
+        .iter()
+        .map(|(tid, entry)| {
+          let tick = entry.changed();
+          let reused = old.and_then(|old| {
+            old.iter().find(|comp| comp.tid == tid && comp.tick == tick)
+          });
+          let data = match reused {
+            Some(comp) => comp.data.clone(),
+            None => {
+              if old.is_some() {
+                changed_components
+                  .push((e, ComponentVtables::by_tid(tid).friendly_name));
+              }
+              snapshot_component(tid, entry)
+            }
+          };
+          SnapshotComponent { tid, tick, data }
+        })
+        .collect();
+      entities.insert(e, comps);
+    }
+    let despawned = last
+      .map(|last| {
+        last
+          .entities
+          .keys()
+          .filter(|e| !entities.contains_key(e))
+          .copied()
+          .collect()
+      })
+      .unwrap_or_default();
+
+    let mut resources = BTreeMap::new();
+    let mut changed_resources = Vec::new();
+    for (tid, res) in self.resources.iter() {
+      let vtable = ResourceVtables::by_tid(tid);
+      let saved = !vtable.transient && vtable.deser.is_some();
+      let Some(ser) = vtable.ser.filter(|_| saved) else {
+        continue;
+      };
+      let lock = res.read().unwrap();
+      let value = to_value(ser(&**lock), "resource", vtable.friendly_name);
+      let old = last.and_then(|last| last.resources.get(&tid));
+      let data = match old {
+        Some(old) if **old == value => old.clone(),
+        _ => {
+          changed_resources.push(vtable.friendly_name);
+          Arc::new(value)
+        }
+      };
+      resources.insert(tid, data);
+    }
+    if let Some(last) = last {
+      for tid in last.resources.keys() {
+        if !resources.contains_key(tid) {
+          changed_resources.push(ResourceVtables::by_tid(*tid).friendly_name);
+        }
+      }
+    }
+
+    // Entities that are only lazily spawned so far aren't in the snapshot
+    let mut allocator = self.entities.allocator.get_mut().unwrap().clone();
+    allocator.retain(|idx, _| entities.contains_key(&Entity(idx)));
+
+    let snapshot = Snapshot {
+      inner: Arc::new(SnapshotInner {
+        allocator,
+        entities,
+        resources,
+        spawned,
+        despawned,
+        changed_components,
+        changed_resources,
+      }),
+    };
+    self.last_snapshot = Some(snapshot.clone());
+    snapshot
+  }
+
+  /// Roll the world back to a snapshot taken with [`World::snapshot`].
+  ///
+  /// Components that haven't changed since the snapshot are left alone, and
+  /// the rest are deserialized from it. Entities spawned since then are
+  /// thrown out without running any callbacks, like [`World::despawn`].
+  /// Otherwise, callbacks are run the same as if each entity had been
+  /// [edited](World::edit) back: creation callbacks for component types that
+  /// come back, and removal callbacks for component types that go away.
+  ///
+  /// Transient components are kept if their entity still has them, or rebuilt
+  /// if they were registered with a placeholder. Resources are overwritten if
+  /// they're different, and ones inserted since the snapshot are removed
+  /// (unless they're transient).
+  ///
+  /// Lazy updates that were still queued are thrown out, since they came from
+  /// the state being rolled back. Anything the callbacks do lazily waits for
+  /// the next [`World::finalize`]; this doesn't finalize the world itself.
+  ///
+  /// A snapshot can be restored as many times as you like.
+  ///
+  /// Panics if a component or resource can't be deserialized.
+  pub fn restore(&mut self, snapshot: &Snapshot) {
+    self.discard_lazy_updates();
+    let inner = &*snapshot.inner;
+
+    // Anything left in here after this was spawned after the snapshot
+    let mut olds = std::mem::take(&mut self.entities).into_assocs();
+    let mut assocs = AHashMap::with_capacity(inner.entities.len());
+    let mut spawned = Vec::new();
+    let mut edited = Vec::new();
+    for (&e, comps) in inner.entities.iter() {
+      let mut old = olds.remove(&e);
+      let original = old
+        .iter()
+        .flat_map(|old| old.iter().map(|(tid, _)| tid))
+        .collect::<BTreeSet<_>>();
+
+      let entries = comps
+        .iter()
+        .filter_map(|comp| {
+          let vtable = ComponentVtables::by_tid(comp.tid);
+          let existing =
+            old.as_mut().and_then(|old| old.remove_entry(comp.tid));
+          let entry = match (&comp.data, existing) {
+            (_, Some(entry)) if entry.changed() == comp.tick => entry,
+            (Some(data), _) => {
+              let deser = vtable.deser.unwrap();
+              let component = restore_value(deser, data, vtable.friendly_name);
+              ComponentEntry::with_tick(component, comp.tick)
+            }
+            (None, Some(entry)) => entry,
+            (None, None) => ComponentEntry::new((vtable.rebuild?)()),
+          };
+          Some((comp.tid, entry))
+        })
+        .collect::<Vec<_>>();
+      assocs.insert(e, EntityAssoc::from_entries(entries));
+      match old {
+        Some(old) => edited.push((e, original, old)),
+        None => spawned.push(e),
+      }
+    }
+    self.entities = EntityStorage::new(inner.allocator.clone(), assocs);
+
+    let stale = self
+      .resources
+      .iter()
+      .map(|(tid, _)| tid)
+      .filter(|tid| {
+        let vtable = ResourceVtables::by_tid(*tid);
+        !vtable.transient
+          && vtable.ser.is_some()
+          && vtable.deser.is_some()
+          && !inner.resources.contains_key(tid)
+      })
+      .collect::<Vec<_>>();
+    for tid in stale {
+      self.resources.remove_raw(tid);
+    }
+    for (&tid, data) in inner.resources.iter() {
+      let vtable = ResourceVtables::by_tid(tid);
+      if let Some(res) = self.resources.get_raw(tid) {
+        let lock = res.read().unwrap();
+        let ser = vtable.ser.unwrap();
+        if to_value(ser(&**lock), "resource", vtable.friendly_name) == **data {
+          continue;
+        }
+      }
+      let deser = vtable.deser.unwrap();
+      let res = restore_value(deser, data, vtable.friendly_name);
+      self.resources.insert_raw(res);
+    }
+
+    // Only run callbacks once everything is back, so they can see each other
+    for e in spawned {
+      self.run_creation_callbacks(e);
+    }
+    for (e, original, removed) in edited {
+      self.run_removal_callback(e, removed);
+      self.run_creation_callbacks_filtered(e, |tid| !original.contains(&tid));
+    }
+    self.last_snapshot = Some(snapshot.clone());
+  }
+}
+
+/// A copy of a world at some point in time. Make one with [`World::snapshot`],
+/// and go back to it with [`World::restore`].
+///
+/// The data inside is shared, so snapshots are cheap to clone, and snapshots
+/// taken one after another share the data of components that didn't change.
+///
+/// Each snapshot also records what changed since the snapshot before it. If
+/// there wasn't one, everything counts as spawned and all the resources count
+/// as changed.
+#[derive(Clone)]
+pub struct Snapshot {
+  inner: Arc<SnapshotInner>,
+}
+
+impl Snapshot {
+  /// How many entities were alive.
+  pub fn len(&self) -> usize {
+    self.inner.entities.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.inner.entities.is_empty()
+  }
+
+  /// Whether the entity was alive.
+  pub fn contains(&self, entity: Entity) -> bool {
+    self.inner.entities.contains_key(&entity)
+  }
+
+  /// Entities spawned since the last snapshot.
+  pub fn spawned(&self) -> &[Entity] {
+    &self.inner.spawned
+  }
+
+  /// Entities despawned since the last snapshot.
+  pub fn despawned(&self) -> &[Entity] {
+    &self.inner.despawned
+  }
+
+  /// Components added, removed, or mutably borrowed since the last snapshot,
+  /// by the friendly name of the component.
+  ///
+  /// This leaves out the components on newly spawned entities.
+  pub fn changed_components(&self) -> &[(Entity, &'static str)] {
+    &self.inner.changed_components
+  }
+
+  /// Resources added, removed, or changed since the last snapshot, by
+  /// friendly name.
+  pub fn changed_resources(&self) -> &[&'static str] {
+    &self.inner.changed_resources
+  }
+}
+
+struct SnapshotInner {
+  allocator: Arena<()>,
+  entities: AHashMap<Entity, Vec<SnapshotComponent>>,
+  resources: BTreeMap<TypeIdWrapper, Arc<Value>>,
+
+  spawned: Vec<Entity>,
+  despawned: Vec<Entity>,
+  changed_components: Vec<(Entity, &'static str)>,
+  changed_resources: Vec<&'static str>,
+}
+
+struct SnapshotComponent {
+  tid: TypeIdWrapper,
+  /// The change tick of the component when it was saved.
+  tick: u64,
+  /// `None` if the component is transient or can't be serialized.
+  data: Option<Arc<Value>>,
+}
+
+fn snapshot_component(
+  tid: TypeIdWrapper,
+  entry: &ComponentEntry,
+) -> Option<Arc<Value>> {
+  let vtable = ComponentVtables::by_tid(tid);
+  if vtable.transient || vtable.deser.is_none() {
+    return None;
+  }
+  let ser = vtable.ser?;
+  let lock = entry.read().unwrap();
+  Some(Arc::new(to_value(
+    ser(&**lock),
+    "component",
+    vtable.friendly_name,
+  )))
+}
+
+/// Serialize a component or resource to a value, panicking if it can't be.
+pub(super) fn to_value(
+  ser: &dyn erased_serde::Serialize,
+  kind: &str,
+  friendly_name: &str,
+) -> Value {
+  serde_value::to_value(ErasedSerWrapper::new(ser)).unwrap_or_else(|err| {
+    panic!("could not serialize {} {}: {}", kind, friendly_name, err)
+  })
+}
+
+/// Load a component or resource from a snapshot, panicking if it can't be.
+fn restore_value<T: ?Sized>(
+  deser: DeserializeFn<T>,
+  data: &Value,
+  friendly_name: &str,
+) -> Box<T> {
+  from_value(deser, data).unwrap_or_else(|err| {
+    panic!(
+      "could not deserialize {} from a snapshot: {}",
+      friendly_name, err
+    )
+  })
+}
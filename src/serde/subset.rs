@@ -0,0 +1,179 @@
+use std::marker::PhantomData;
+
+use serde::{
+  de::{IgnoredAny, MapAccess, SeqAccess, Visitor},
+  Deserialize, Deserializer, Serialize,
+};
+
+use crate::{
+  entities::EntityMap,
+  prelude::{Entity, World},
+};
+
+use super::{
+  entity::{EntitiesDeWrapper, EntitiesSerWrapper},
+  layout::WorldLayoutDe,
+  load::{deserialize_read_ahead, LoadContextGuard},
+  version::{SchemaVersionDe, SchemaVersionSer},
+  LoadOptions, Value, WorldLayout,
+};
+
+impl World {
+  /// Get something that serializes just the given entities, to be loaded into
+  /// another world with [`World::import_entities`].
+  ///
+  /// Unlike serializing the whole world, this doesn't include the allocator or
+  /// any resources. It does include the [schema version](super::schema_version),
+  /// so exported entities get migrated when they're imported, same as saves.
+  ///
+  /// The entities are laid out with the default [`WorldLayout`], same as
+  /// serializing a world. Serializing the export fails if any of the entities
+  /// aren't alive.
+  pub fn export_entities(
+    &self,
+    entities: impl IntoIterator<Item = Entity>,
+  ) -> EntitiesExport<'_> {
+    self.export_entities_with(entities, WorldLayout::default())
+  }
+
+  /// Export some entities with the given layout, like
+  /// [`World::serialize_with`]. Use [`WorldLayout::EntityList`] for JSON.
+  pub fn export_entities_with(
+    &self,
+    entities: impl IntoIterator<Item = Entity>,
+    layout: WorldLayout,
+  ) -> EntitiesExport<'_> {
+    EntitiesExport {
+      version: SchemaVersionSer,
+      layout,
+      entities: EntitiesSerWrapper::subset(
+        self,
+        entities.into_iter().collect(),
+        layout,
+      ),
+    }
+  }
+
+  /// Load entities serialized with [`World::export_entities`] into this world.
+  ///
+  /// Each entity gets a fresh ID, and the entities stored inside components are
+  /// rewritten to match with [`MapEntities`](crate::entities::MapEntities).
+  /// Entities that weren't exported turn into dead entities.
+  /// Then, creation callbacks are run for everything. Anything they do lazily
+  /// waits for the next [`World::finalize`] along with everything else
+  /// already queued; this doesn't finalize the world itself.
+  ///
+  /// Returns the mapping from the exported entities to the new ones.
+  pub fn import_entities<'de, D>(
+    &mut self,
+    deserializer: D,
+  ) -> Result<EntityMap, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    let guard = LoadContextGuard::new(LoadOptions::default());
+    let entities = EntitiesImportDe::deserialize(deserializer)?.entities;
+    drop(guard);
+    Ok(self.insert_remapped(entities.entities))
+  }
+}
+
+/// Serializes some of the entities in a world. Get one with [`World::export_entities`].
+#[derive(Serialize)]
+pub struct EntitiesExport<'w> {
+  version: SchemaVersionSer,
+  layout: WorldLayout,
+  entities: EntitiesSerWrapper<'w>,
+}
+
+/// The version and layout are put in the load context as they're read,
+/// so they aren't kept here.
+struct EntitiesImportDe {
+  entities: EntitiesDeWrapper,
+}
+
+impl<'de> Deserialize<'de> for EntitiesImportDe {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    deserializer.deserialize_struct(
+      "EntitiesExport",
+      &["version", "layout", "entities"],
+      EntitiesImportVisitor,
+    )
+  }
+}
+
+#[derive(Deserialize)]
+#[serde(field_identifier, rename_all = "lowercase")]
+enum ExportField {
+  Version,
+  Layout,
+  Entities,
+  #[serde(other)]
+  Other,
+}
+
+struct EntitiesImportVisitor;
+
+impl<'de> Visitor<'de> for EntitiesImportVisitor {
+  type Value = EntitiesImportDe;
+
+  fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(formatter, "exported entities")
+  }
+
+  fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+  where
+    A: SeqAccess<'de>,
+  {
+    let missing = |idx| serde::de::Error::invalid_length(idx, &self);
+    seq
+      .next_element::<SchemaVersionDe>()?
+      .ok_or_else(|| missing(0))?;
+    seq
+      .next_element::<WorldLayoutDe>()?
+      .ok_or_else(|| missing(1))?;
+    let entities = seq.next_element()?.ok_or_else(|| missing(2))?;
+    Ok(EntitiesImportDe { entities })
+  }
+
+  fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+  where
+    A: MapAccess<'de>,
+  {
+    // Like loading a world, hold onto the entities until the version is known
+    let mut read_version = false;
+    let mut entities = None;
+    let mut early_entities = None;
+    while let Some(key) = map.next_key()? {
+      match key {
+        ExportField::Version => {
+          map.next_value::<SchemaVersionDe>()?;
+          read_version = true;
+        }
+        ExportField::Layout => {
+          map.next_value::<WorldLayoutDe>()?;
+        }
+        ExportField::Entities if read_version => {
+          entities = Some(map.next_value()?)
+        }
+        ExportField::Entities => {
+          early_entities = Some(map.next_value::<Value>()?)
+        }
+        ExportField::Other => {
+          map.next_value::<IgnoredAny>()?;
+        }
+      }
+    }
+
+    if let Some(early) = early_entities {
+      entities = Some(deserialize_read_ahead(PhantomData, early)?);
+    }
+    Ok(EntitiesImportDe {
+      entities: entities
+        .ok_or_else(|| serde::de::Error::missing_field("entities"))?,
+    })
+  }
+}
@@ -0,0 +1,116 @@
+use serde::{
+  de::IgnoredAny, ser::SerializeMap, Deserialize, Serialize, Serializer,
+};
+
+use crate::{
+  __private::{ComponentRegistererErased, ResourceRegistererErased},
+  component::{Component, ComponentRegisterer},
+  resource::{Resource, ResourceRegisterer},
+  vtablesathome::{ComponentVtable, ResourceVtable},
+};
+
+use super::{
+  load::{with_load_context, UnknownPolicy},
+  Value,
+};
+
+/// Components on an entity that weren't recognized when loading a world with
+/// [`UnknownPolicy::Preserve`], as `(friendly name, data)` pairs.
+///
+/// When the world is saved again, these are written back out as if they were
+/// normal components.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UnknownComponents(pub Vec<(String, Value)>);
+
+impl Component for UnknownComponents {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder
+      .set_friendly_name("palkia:unknown-components")
+      .register_clone()
+  }
+}
+
+#[linkme::distributed_slice(crate::__private::COMPONENT_REGISTRATORS)]
+fn register_unknown_components(
+  regi: ComponentRegistererErased,
+) -> ComponentVtable {
+  UnknownComponents::register(regi.wrap())
+    .register_serde()
+    .into_vtable()
+}
+
+/// Resources that weren't recognized when loading a world with
+/// [`UnknownPolicy::Preserve`], as `(friendly name, data)` pairs.
+///
+/// When the world is saved again, these are written back out as if they were
+/// normal resources.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct UnknownResources(pub Vec<(String, Value)>);
+
+impl Resource for UnknownResources {
+  fn register(builder: ResourceRegisterer<Self>) -> ResourceRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("palkia:unknown-resources")
+  }
+}
+
+#[linkme::distributed_slice(crate::__private::RESOURCE_REGISTRATORS)]
+fn register_unknown_resources(
+  regi: ResourceRegistererErased,
+) -> ResourceVtable {
+  UnknownResources::register(regi.wrap())
+    .register_serde()
+    .into_vtable()
+}
+
+/// Writes one unknown thing back out as `{ friendly-name: data }`.
+pub(super) struct UnknownSerWrapper<'a> {
+  pub friendly_name: &'a str,
+  pub data: &'a Value,
+}
+
+impl<'a> Serialize for UnknownSerWrapper<'a> {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    let mut map = serializer.serialize_map(Some(1))?;
+    map.serialize_entry(self.friendly_name, self.data)?;
+    map.end()
+  }
+}
+
+/// Get the current policy for unknown friendly names.
+/// Outside of loading a world, it's always an error.
+pub(super) fn unknown_policy() -> UnknownPolicy {
+  with_load_context(|ctx| ctx.map(|ctx| ctx.options.unknown))
+    .unwrap_or_default()
+}
+
+/// Deal with the value of an unknown friendly name according to the current policy.
+///
+/// Returns the data if it should be preserved.
+pub(super) fn handle_unknown<'de, A>(
+  kind: &str,
+  friendly_name: &str,
+  map: &mut A,
+) -> Result<Option<Value>, A::Error>
+where
+  A: serde::de::MapAccess<'de>,
+{
+  match unknown_policy() {
+    UnknownPolicy::Error => Err(<A::Error as serde::de::Error>::custom(
+      format!("unknown {} friendly name {:?}", kind, friendly_name),
+    )),
+    UnknownPolicy::Skip => {
+      map.next_value::<IgnoredAny>()?;
+      Ok(None)
+    }
+    UnknownPolicy::Preserve => Ok(Some(map.next_value()?)),
+  }
+}
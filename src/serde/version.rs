@@ -0,0 +1,51 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::vtablesathome::ComponentVtables;
+
+use super::load::with_load_context;
+
+/// Get the schema version that worlds are saved with.
+///
+/// This is one more than the newest version any component
+/// [migrates from](crate::component::ComponentRegisterer::migrate_from),
+/// so it goes up automatically as you add migrations. With no migrations, it's 0.
+pub fn schema_version() -> u32 {
+  ComponentVtables::schema_version()
+}
+
+/// Writes the current schema version.
+pub(super) struct SchemaVersionSer;
+
+impl Serialize for SchemaVersionSer {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    schema_version().serialize(serializer)
+  }
+}
+
+/// Reads the schema version of the world being loaded, and remembers it
+/// in the load context for the components loaded after it.
+#[derive(Default)]
+pub(super) struct SchemaVersionDe;
+
+impl<'de> Deserialize<'de> for SchemaVersionDe {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    let version = u32::deserialize(deserializer)?;
+    with_load_context(|ctx| {
+      if let Some(ctx) = ctx {
+        ctx.version = version;
+      }
+    });
+    Ok(SchemaVersionDe)
+  }
+}
+
+/// Get the schema version of the world currently being loaded, if there is one.
+pub(super) fn loading_version() -> Option<u32> {
+  with_load_context(|ctx| ctx.map(|ctx| ctx.version))
+}
@@ -1,7 +1,7 @@
 use std::marker::PhantomData;
 
 use ahash::AHashSet;
-use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde::{Deserialize, Serialize};
 
 use crate::prelude::{Component, Entity};
 
@@ -9,6 +9,10 @@ use crate::prelude::{Component, Entity};
 ///
 /// You can't add this directly to a world because Palkia doesn't support generic Resources.
 /// Put it as a newtype struct.
+///
+/// The tracked entities aren't saved, so consider registering the newtype as
+/// [`transient_default`](crate::resource::ResourceRegisterer::transient_default);
+/// the create callbacks will fill it back in when the world is loaded.
 #[derive(Serialize, Deserialize)]
 pub struct TrackEntitiesWithComponent<C: 'static> {
   /// this gets reconstructed at ser/de time
@@ -39,7 +43,7 @@ where
 
 impl<C> TrackEntitiesWithComponent<C>
 where
-  C: Component + DeserializeOwned,
+  C: Component,
 {
   pub fn on_create(&mut self, entity: Entity) {
     if !self.entities.insert(entity) {
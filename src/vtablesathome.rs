@@ -5,14 +5,21 @@ use std::{any::Any, collections::BTreeMap, sync::OnceLock};
 use crate::{
   callback::{OnCreateCallback, OnRemoveCallback},
   component::ComponentRegistererErased,
+  entities::EntityMap,
   messages::MsgHandlerInner,
   prelude::Component,
   resource::{Resource, ResourceRegistererErased},
   TypeIdWrapper,
 };
 
+pub(crate) type SerializeFn<T> = fn(&T) -> &dyn erased_serde::Serialize;
 pub(crate) type DeserializeFn<T> =
   fn(&mut dyn erased_serde::Deserializer) -> erased_serde::Result<Box<T>>;
+pub(crate) type DefaultFn<T> = fn() -> Box<T>;
+pub(crate) type CloneFn<T> = fn(&T) -> Box<T>;
+pub(crate) type MapEntitiesFn<T> = fn(&mut T, &EntityMap);
+pub(crate) type MigrateFn =
+  fn(serde_value::Value) -> eyre::Result<serde_value::Value>;
 
 /// Information stored about each component.
 ///
@@ -28,7 +35,44 @@ pub struct ComponentVtable {
   pub create_cbs: Vec<OnCreateCallback>,
   pub remove_cbs: Vec<OnRemoveCallback>,
 
-  pub deser: DeserializeFn<dyn Component>,
+  /// These are `None` if the component isn't `Serialize + DeserializeOwned`.
+  pub ser: Option<SerializeFn<dyn Component>>,
+  pub deser: Option<DeserializeFn<dyn Component>>,
+  /// Transient components are never saved.
+  pub transient: bool,
+  /// If a transient component has a registered default, a placeholder is saved instead,
+  /// and this rebuilds it when loading.
+  pub rebuild: Option<DefaultFn<dyn Component>>,
+  /// Fast path for cloning, if the component registered one.
+  /// Otherwise it's cloned by round-tripping through serde.
+  pub clone: Option<CloneFn<dyn Component>>,
+  /// Rewrites the entities inside the component, if it has any.
+  pub map_entities: Option<MapEntitiesFn<dyn Component>>,
+  /// Whether this is sent to replicas of the world.
+  pub replicated: bool,
+  /// Maps the last schema version with an old layout to how to convert it
+  /// to the next layout.
+  pub migrations: BTreeMap<u32, MigrateFn>,
+}
+
+impl ComponentVtable {
+  /// Check if data saved with the given schema version is out of date.
+  pub(crate) fn is_outdated(&self, version: u32) -> bool {
+    self.migrations.range(version..).next().is_some()
+  }
+
+  /// Bring data saved with the given schema version up to date, running
+  /// every migration since then in order.
+  pub(crate) fn migrate(
+    &self,
+    version: u32,
+    mut data: serde_value::Value,
+  ) -> eyre::Result<serde_value::Value> {
+    for migrate in self.migrations.range(version..).map(|(_, it)| it) {
+      data = migrate(data)?;
+    }
+    Ok(data)
+  }
 }
 
 /// Public only for the benefit of macros
@@ -37,7 +81,18 @@ pub struct ResourceVtable {
   pub tid: TypeIdWrapper,
   pub friendly_name: &'static str,
 
-  pub deser: DeserializeFn<dyn Resource>,
+  /// These are `None` if the resource isn't `Serialize + DeserializeOwned`.
+  pub ser: Option<SerializeFn<dyn Resource>>,
+  pub deser: Option<DeserializeFn<dyn Resource>>,
+  /// Transient resources are never saved.
+  pub transient: bool,
+  /// If a transient resource has a registered default, this is inserted into
+  /// loaded worlds.
+  pub rebuild: Option<DefaultFn<dyn Resource>>,
+  /// Rewrites the entities inside the resource, if it has any.
+  pub map_entities: Option<MapEntitiesFn<dyn Resource>>,
+  /// Whether this is sent to replicas of the world.
+  pub replicated: bool,
 }
 
 pub(crate) fn default_friendly_type_name<T: Any>() -> &'static str {
@@ -51,6 +106,7 @@ pub(crate) struct ComponentVtables {
   tables: Vec<ComponentVtable>,
   by_tid: BTreeMap<TypeIdWrapper, usize>,
   by_friendly_name: BTreeMap<String, usize>,
+  schema_version: u32,
 }
 
 static COMPONENT_VTABLES: OnceLock<ComponentVtables> = OnceLock::new();
@@ -62,6 +118,7 @@ impl ComponentVtables {
         tables: Vec::new(),
         by_tid: BTreeMap::default(),
         by_friendly_name: BTreeMap::default(),
+        schema_version: 0,
       };
       for registrator in crate::__private::COMPONENT_REGISTRATORS {
         let erased = ComponentRegistererErased::new();
@@ -87,6 +144,10 @@ impl ComponentVtables {
           continue;
         }
 
+        if let Some(newest) = vtable.migrations.keys().next_back() {
+          me.schema_version = me.schema_version.max(newest + 1);
+        }
+
         me.by_tid.insert(vtable.tid, idx);
         me.by_friendly_name
           .insert(vtable.friendly_name.to_owned(), idx);
@@ -96,6 +157,11 @@ impl ComponentVtables {
     })
   }
 
+  /// One more than the newest version any component migrates from.
+  pub(crate) fn schema_version() -> u32 {
+    Self::get_inner().schema_version
+  }
+
   #[allow(unused)]
   pub(crate) fn by_type<C>() -> &'static ComponentVtable
   where
@@ -115,15 +181,12 @@ impl ComponentVtables {
     &vtables.tables[*idx]
   }
 
-  pub(crate) fn by_friendly_name(name: &str) -> &'static ComponentVtable {
+  pub(crate) fn try_by_friendly_name(
+    name: &str,
+  ) -> Option<&'static ComponentVtable> {
     let vtables = Self::get_inner();
-    let idx = vtables.by_friendly_name.get(name).unwrap_or_else(|| {
-      panic!(
-        "tried to access component with unknown friendly name {:?}",
-        name,
-      )
-    });
-    &vtables.tables[*idx]
+    let idx = vtables.by_friendly_name.get(name)?;
+    Some(&vtables.tables[*idx])
   }
 }
 /// Static registry of resources
@@ -187,14 +250,15 @@ impl ResourceVtables {
     &vtables.tables[*idx]
   }
 
-  pub(crate) fn by_friendly_name(name: &str) -> &'static ResourceVtable {
+  pub(crate) fn iter() -> impl Iterator<Item = &'static ResourceVtable> {
+    Self::get_inner().tables.iter()
+  }
+
+  pub(crate) fn try_by_friendly_name(
+    name: &str,
+  ) -> Option<&'static ResourceVtable> {
     let vtables = Self::get_inner();
-    let idx = vtables.by_friendly_name.get(name).unwrap_or_else(|| {
-      panic!(
-        "tried to access resource with unknown friendly name {:?}",
-        name,
-      )
-    });
-    &vtables.tables[*idx]
+    let idx = vtables.by_friendly_name.get(name)?;
+    Some(&vtables.tables[*idx])
   }
 }
@@ -5,6 +5,9 @@ pub(crate) mod storage;
 #[doc(hidden)]
 pub use storage::EntityAssoc;
 
+use std::collections::BTreeSet;
+
+use ahash::AHashMap;
 use crossbeam::channel;
 
 use crate::{
@@ -12,16 +15,17 @@ use crate::{
     AccessDispatcher, AccessEntityStats, AccessQuery, AccessResources,
     AccessSpawnEntities,
   },
-  builder::EntityBuilder,
+  builder::{EntityBuilder, EntityBuilderComponentTracker},
   callback::CallbackWorldAccess,
   component::Component,
-  entities::{Entity, EntityIter, EntityLiveness},
+  entities::{Entity, EntityIter, EntityLiveness, EntityMap},
   loop_panic,
   messages::{ListenerWorldAccess, Message, MsgHandlerInner},
   prelude::Query,
   resource::{ReadResource, Resource, ResourceLookupError, WriteResource},
+  serde::{clone_component, Snapshot},
   vtablesathome::ComponentVtables,
-  ToTypeIdWrapper,
+  ToTypeIdWrapper, TypeIdWrapper,
 };
 
 use self::storage::{EntityStorage, ResourceMap};
@@ -34,6 +38,9 @@ pub struct World {
 
   pub(crate) lazy_sender: channel::Sender<LazyUpdate>,
   lazy_channel: channel::Receiver<LazyUpdate>,
+
+  /// The last snapshot taken or restored, to reuse unchanged components from.
+  pub(crate) last_snapshot: Option<Snapshot>,
 }
 
 impl World {
@@ -45,6 +52,7 @@ impl World {
       resources: ResourceMap::new(),
       lazy_sender: tx,
       lazy_channel: rx,
+      last_snapshot: None,
     }
   }
 
@@ -75,6 +83,66 @@ impl World {
     EntityBuilder::new_lazy_world(self, entity)
   }
 
+  /// Set up a builder to change the components on an existing entity.
+  ///
+  /// The entity's components are moved into the builder, so you can get,
+  /// insert, and remove them just like a new entity. Once it's built, create
+  /// callbacks are run for new component types, and remove callbacks for removed ones.
+  ///
+  /// Panics if the entity is not alive.
+  pub fn edit<'w>(&'w mut self, entity: Entity) -> EntityBuilder<'w, 'w> {
+    let assoc = self.entities.take(entity);
+    EntityBuilder::new_edit(self, entity, assoc)
+  }
+
+  /// Set up a builder to change the components on an existing entity once
+  /// `finalize` has been called.
+  ///
+  /// Because the entity's components stay in the world until then, the builder
+  /// can only get components that were inserted into it. The entity can also
+  /// be one that's only been lazily spawned, as long as it's spawned before
+  /// this is built.
+  pub fn lazy_edit<'w>(&'w self, entity: Entity) -> EntityBuilder<'w, 'w> {
+    EntityBuilder::new_lazy_world_edit(self, entity)
+  }
+
+  /// Set up a builder to spawn a copy of the given entity, with a deep copy of
+  /// each of its components.
+  ///
+  /// Components are copied with their `Clone` impl if they registered one
+  /// with [`ComponentRegisterer::register_clone`](crate::component::ComponentRegisterer::register_clone),
+  /// and otherwise round-tripped through serde.
+  ///
+  /// Panics if the entity is not alive, or if a component can't be round-tripped.
+  pub fn clone_entity<'w>(
+    &'w mut self,
+    entity: Entity,
+  ) -> EntityBuilder<'w, 'w> {
+    let components = self.clone_components(entity);
+    let mut builder = self.spawn();
+    for comp in components {
+      builder.insert_raw(comp);
+    }
+    builder
+  }
+
+  /// Set up a builder to spawn a copy of the given entity once `finalize` has been called.
+  ///
+  /// The components are copied when it's finalized, not now, so this is fine
+  /// to call while the entity's components are borrowed, like from one of its
+  /// message handlers. Like [`World::lazy_edit`], the builder can only get
+  /// components that were inserted into it; those replace the copies. If the
+  /// entity is dead or still unfinished by then, the new one only gets the
+  /// inserted components.
+  ///
+  /// See [`World::clone_entity`].
+  pub fn lazy_clone_entity<'w>(
+    &'w self,
+    entity: Entity,
+  ) -> EntityBuilder<'w, 'w> {
+    self.lazy_spawn().lazily_cloning(entity)
+  }
+
   /// Despawn an entity immediately. Panics if the entity does not exist.
   pub fn despawn(&mut self, entity: Entity) {
     self.entities.despawn(entity);
@@ -123,6 +191,44 @@ impl World {
     self.resources.remove()
   }
 
+  /// Move all the entities from another world into this one, returning
+  /// what each of them became.
+  ///
+  /// Each entity gets a fresh ID, and the entities stored inside components are
+  /// rewritten to match with [`MapEntities`](crate::entities::MapEntities).
+  /// The other world's resources are handled according to `resources`; the
+  /// ones that are moved over get their entities rewritten too.
+  /// Then, creation callbacks are run for all the moved entities, so if you're
+  /// caching entities in a resource you moved over, you should invalidate it first.
+  /// Anything the callbacks do lazily waits for the next [`World::finalize`].
+  pub fn merge_from(
+    &mut self,
+    mut other: World,
+    resources: ResourceMergeMode,
+  ) -> EntityMap {
+    other.finalize();
+
+    let (map, moved) =
+      self.insert_remapped_quietly(other.entities.into_assocs());
+    let mut other_resources = other.resources;
+    other_resources.map_entities(&map);
+    for res in other_resources.into_values() {
+      let keep_old = match resources {
+        ResourceMergeMode::Ignore => continue,
+        ResourceMergeMode::KeepOld => true,
+        ResourceMergeMode::Clobber => false,
+      };
+      if !(keep_old && self.resources.contains_tid((*res).type_id_wrapper())) {
+        self.resources.insert_raw(res);
+      }
+    }
+
+    for entity in moved {
+      self.run_creation_callbacks(entity);
+    }
+    map
+  }
+
   /// Apply any and all lazy updates.
   pub fn finalize(&mut self) {
     let updates = self.lazy_channel.try_iter().collect::<Vec<_>>();
@@ -138,6 +244,16 @@ impl World {
     self.entities.iter()
   }
 
+  /// Check if there are lazy updates waiting to be finalized.
+  pub(crate) fn has_lazy_updates(&self) -> bool {
+    !self.lazy_channel.is_empty()
+  }
+
+  /// Throw out all the lazy updates waiting to be finalized.
+  pub(crate) fn discard_lazy_updates(&mut self) {
+    self.lazy_channel.try_iter().for_each(drop);
+  }
+
   /// Finish the spawning of an entity that's been lazily created but not
   /// instantiated fully.
   ///
@@ -161,10 +277,102 @@ impl World {
     self.run_creation_callbacks(target);
   }
 
+  /// Put the components of an edited entity back, and run callbacks for
+  /// whatever changed.
+  pub(crate) fn finish_edit(
+    &mut self,
+    target: Entity,
+    assoc: EntityAssoc,
+    original: BTreeSet<TypeIdWrapper>,
+    removed: Vec<Box<dyn Component>>,
+  ) {
+    self.entities.finish_spawn(target, assoc);
+
+    let assoc = self.entities.get(target);
+    let removed = removed
+      .into_iter()
+      .filter(|comp| {
+        !assoc.components().contains_key(&(**comp).type_id_wrapper())
+      })
+      .collect::<Vec<_>>();
+    self.run_removal_callback(target, EntityAssoc::new(removed));
+    self
+      .run_creation_callbacks_filtered(target, |tid| !original.contains(&tid));
+  }
+
+  /// Insert entities from another world, allocating new entities for them and
+  /// rewriting the entities stored in their components.
+  pub(crate) fn insert_remapped(
+    &mut self,
+    entities: AHashMap<Entity, EntityAssoc>,
+  ) -> EntityMap {
+    let (map, inserted) = self.insert_remapped_quietly(entities);
+    // Only run these once everything is in, so they can see each other
+    for entity in inserted {
+      self.run_creation_callbacks(entity);
+    }
+    map
+  }
+
+  /// Like [`insert_remapped`](Self::insert_remapped), but leave running the
+  /// creation callbacks on the new entities to the caller.
+  fn insert_remapped_quietly(
+    &mut self,
+    entities: AHashMap<Entity, EntityAssoc>,
+  ) -> (EntityMap, Vec<Entity>) {
+    let mut map = EntityMap::new(self.entities.spawn_dead());
+    let mut olds = entities.keys().copied().collect::<Vec<_>>();
+    olds.sort();
+    for &old in olds.iter() {
+      map.insert(old, self.entities.spawn_unfinished());
+    }
+
+    for (old, mut assoc) in entities {
+      assoc.map_entities(&map);
+      self.entities.finish_spawn(map.map(old), assoc);
+    }
+
+    let inserted = olds.into_iter().map(|old| map.map(old)).collect();
+    (map, inserted)
+  }
+
+  /// Deep-copy all the components on an entity, in order.
+  pub(crate) fn clone_components(
+    &self,
+    entity: Entity,
+  ) -> Vec<Box<dyn Component>> {
+    self
+      .entities
+      .get(entity)
+      .iter()
+      .map(|(tid, comp)| {
+        let lock = comp.try_read().unwrap_or_else(|_| {
+          panic!(
+            "tried to clone {:x}'s component of type {} while it was mutably borrowed",
+            entity, tid.type_name
+          )
+        });
+        clone_component(&**lock)
+      })
+      .collect()
+  }
+
   pub(crate) fn run_creation_callbacks(&self, e: Entity) {
+    self.run_creation_callbacks_filtered(e, |_| true);
+  }
+
+  /// Run creation callbacks only for the component types passing the filter.
+  pub(crate) fn run_creation_callbacks_filtered(
+    &self,
+    e: Entity,
+    filter: impl Fn(TypeIdWrapper) -> bool,
+  ) {
     let access = CallbackWorldAccess::new(self);
 
     for (tid, comp) in self.entities.get(e).components() {
+      if !filter(*tid) {
+        continue;
+      }
       let vtable = ComponentVtables::by_tid(*tid);
       for cb in &vtable.create_cbs {
         // i am *pretty* sure this will never be locked?
@@ -173,7 +381,8 @@ impl World {
       }
     }
   }
-  fn run_removal_callback(&self, e: Entity, comps: EntityAssoc) {
+
+  pub(crate) fn run_removal_callback(&self, e: Entity, comps: EntityAssoc) {
     let access = CallbackWorldAccess::new(self);
     for (tid, comp) in comps.into_iter() {
       let vtable = ComponentVtables::by_tid(tid);
@@ -247,9 +456,36 @@ impl AccessSpawnEntities for World {
   }
 }
 
+/// What to do with another world's resources when merging it into this one
+/// with [`World::merge_from`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResourceMergeMode {
+  /// Drop the other world's resources. This is the default.
+  #[default]
+  Ignore,
+  /// Move over the resources this world doesn't have, and keep this world's
+  /// version of the ones both have.
+  KeepOld,
+  /// Move over all the resources, replacing this world's version of the
+  /// ones both have.
+  Clobber,
+}
+
+#[allow(clippy::enum_variant_names)]
 pub(crate) enum LazyUpdate {
   FinishEntity(Vec<Box<dyn Component>>, Entity),
   DespawnEntity(Entity),
+  EditEntity {
+    entity: Entity,
+    inserted: Vec<Box<dyn Component>>,
+    removed: Vec<TypeIdWrapper>,
+  },
+  CloneEntity {
+    source: Entity,
+    entity: Entity,
+    inserted: Vec<Box<dyn Component>>,
+    removed: BTreeSet<TypeIdWrapper>,
+  },
 }
 
 impl LazyUpdate {
@@ -259,6 +495,28 @@ impl LazyUpdate {
         world.entities.finish_spawn(entity, EntityAssoc::new(comps));
         world.run_creation_callbacks(entity);
       }
+      LazyUpdate::CloneEntity {
+        source,
+        entity,
+        inserted,
+        removed,
+      } => {
+        let mut tracker = EntityBuilderComponentTracker::new();
+        if world.entities.liveness(source) == EntityLiveness::Alive {
+          for comp in world.clone_components(source) {
+            if !removed.contains(&(*comp).type_id_wrapper()) {
+              tracker.insert_raw(comp);
+            }
+          }
+        }
+        for comp in inserted {
+          tracker.insert_raw(comp);
+        }
+        world
+          .entities
+          .finish_spawn(entity, EntityAssoc::new(tracker.components));
+        world.run_creation_callbacks(entity);
+      }
       LazyUpdate::DespawnEntity(entity) => {
         if world.entities.liveness(entity) == EntityLiveness::Alive {
           let prev = world.entities.despawn(entity);
@@ -266,6 +524,34 @@ impl LazyUpdate {
         }
         // Otherwise, it was double-killed, we hope
       }
+      LazyUpdate::EditEntity {
+        entity,
+        inserted,
+        removed,
+      } => {
+        if world.entities.liveness(entity) != EntityLiveness::Alive {
+          // It was killed before the edit could go through
+          return;
+        }
+
+        let assoc = world.entities.get_mut(entity);
+        let removed = removed
+          .into_iter()
+          .filter_map(|tid| assoc.remove(tid))
+          .collect::<Vec<_>>();
+        let mut created = BTreeSet::new();
+        for comp in inserted {
+          let tid = (*comp).type_id_wrapper();
+          if assoc.insert_raw(comp).is_none() {
+            created.insert(tid);
+          }
+        }
+
+        world.run_removal_callback(entity, EntityAssoc::new(removed));
+        world.run_creation_callbacks_filtered(entity, |tid| {
+          created.contains(&tid)
+        });
+      }
     }
   }
 }
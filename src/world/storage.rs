@@ -1,6 +1,10 @@
 use std::{
   collections::BTreeMap,
-  sync::{RwLock, TryLockError},
+  sync::{
+    atomic::{AtomicU64, Ordering},
+    LockResult, RwLock, RwLockReadGuard, RwLockWriteGuard, TryLockError,
+    TryLockResult,
+  },
 };
 
 use ahash::AHashMap;
@@ -8,12 +12,13 @@ use generational_arena::Arena;
 use indexmap::IndexMap;
 
 use crate::{
-  entities::EntityIter,
+  entities::{EntityIter, EntityMap},
   prelude::{Component, Entity, EntityLiveness},
   resource::{
     ReadResource, Resource, ResourceLookupError, ResourceLookupErrorKind,
     WriteResource,
   },
+  vtablesathome::{ComponentVtables, ResourceVtables},
   ToTypeIdWrapper, TypeIdWrapper,
 };
 
@@ -51,6 +56,14 @@ impl EntityStorage {
     Entity(lock.insert(()))
   }
 
+  /// Make an entity that is guaranteed to never be alive.
+  pub fn spawn_dead(&mut self) -> Entity {
+    let alloc = self.allocator.get_mut().unwrap();
+    let idx = alloc.insert(());
+    alloc.remove(idx);
+    Entity(idx)
+  }
+
   pub fn finish_spawn(&mut self, target: Entity, assoc: EntityAssoc) {
     match self.assocs.insert(target, assoc) {
       None => {} // all good
@@ -76,6 +89,15 @@ impl EntityStorage {
     }
   }
 
+  /// Take the data associated with the given entity out of the storage,
+  /// leaving it partially spawned. Put it back with [`Self::finish_spawn`].
+  pub fn take(&mut self, target: Entity) -> EntityAssoc {
+    match self.assocs.remove(&target) {
+      Some(it) => it,
+      None => panic!("tried to take the data of an unfinished entity"),
+    }
+  }
+
   /// Get the data associated with the given entity.
   pub fn get(&self, entity: Entity) -> &EntityAssoc {
     match self.assocs.get(&entity) {
@@ -84,6 +106,19 @@ impl EntityStorage {
     }
   }
 
+  /// Get the data associated with the given entity, if it's been finished.
+  pub fn try_get(&self, entity: Entity) -> Option<&EntityAssoc> {
+    self.assocs.get(&entity)
+  }
+
+  /// Get mutable access to the data associated with the given entity.
+  pub fn get_mut(&mut self, entity: Entity) -> &mut EntityAssoc {
+    match self.assocs.get_mut(&entity) {
+      Some(it) => it,
+      None => panic!("tried to get an unfinished entity"),
+    }
+  }
+
   pub fn len(&self) -> usize {
     self.assocs.len()
   }
@@ -119,6 +154,11 @@ impl EntityStorage {
       iter: self.assocs.keys().copied(),
     }
   }
+
+  /// Throw away the allocator and get all the finished entities' data.
+  pub fn into_assocs(self) -> AHashMap<Entity, EntityAssoc> {
+    self.assocs
+  }
 }
 
 /// Data stored under each entity.
@@ -136,12 +176,20 @@ impl EntityAssoc {
   ) -> Self {
     let components = components
       .into_iter()
-      .map(|comp| ((*comp).type_id_wrapper(), RwLock::new(comp)))
+      .map(|comp| ((*comp).type_id_wrapper(), ComponentEntry::new(comp)))
       .collect();
     Self { components }
   }
 
-  #[allow(unused)]
+  /// Make an assoc out of entries that already exist, keeping their ticks.
+  pub(crate) fn from_entries(
+    entries: impl IntoIterator<Item = (TypeIdWrapper, ComponentEntry)>,
+  ) -> Self {
+    Self {
+      components: entries.into_iter().collect(),
+    }
+  }
+
   pub(crate) fn empty() -> Self {
     Self {
       components: IndexMap::default(),
@@ -165,6 +213,48 @@ impl EntityAssoc {
     self.components.len()
   }
 
+  /// Insert a component, returning the old one of that type if it existed.
+  /// New components go after all the old ones.
+  pub(crate) fn insert_raw(
+    &mut self,
+    component: Box<dyn Component>,
+  ) -> Option<Box<dyn Component>> {
+    let tid = (*component).type_id_wrapper();
+    self
+      .components
+      .insert(tid, ComponentEntry::new(component))
+      .map(|old| old.into_inner().unwrap())
+  }
+
+  /// Rewrite the entities in every component that knows how.
+  pub(crate) fn map_entities(&mut self, map: &EntityMap) {
+    for (tid, comp) in self.components.iter_mut() {
+      let vtable = ComponentVtables::by_tid(*tid);
+      if let Some(map_entities) = vtable.map_entities {
+        map_entities(&mut **comp.get_mut().unwrap(), map);
+      }
+    }
+  }
+
+  /// Remove the component of the given type, keeping the order of the rest.
+  pub(crate) fn remove(
+    &mut self,
+    tid: TypeIdWrapper,
+  ) -> Option<Box<dyn Component>> {
+    self
+      .components
+      .shift_remove(&tid)
+      .map(|old| old.into_inner().unwrap())
+  }
+
+  /// Remove the entry of the given type as-is, keeping the order of the rest.
+  pub(crate) fn remove_entry(
+    &mut self,
+    tid: TypeIdWrapper,
+  ) -> Option<ComponentEntry> {
+    self.components.shift_remove(&tid)
+  }
+
   pub(crate) fn components(
     &self,
   ) -> &IndexMap<TypeIdWrapper, ComponentEntry, ahash::RandomState> {
@@ -172,9 +262,76 @@ impl EntityAssoc {
   }
 }
 
+/// Source of change ticks. This is global so ticks are unique across worlds,
+/// and a snapshot from one world never mistakes another's components for its
+/// own.
+static CHANGE_TICK: AtomicU64 = AtomicU64::new(0);
+
+fn next_tick() -> u64 {
+  CHANGE_TICK.fetch_add(1, Ordering::Relaxed)
+}
+
 /// How each component is stored. Right now this uses naive locking; in the future we might
 /// do something fancier.
-pub(crate) type ComponentEntry = RwLock<Box<dyn Component>>;
+///
+/// Each entry also remembers the tick it was last (possibly) changed at.
+/// Borrowing it mutably counts as a change, whether or not anything was
+/// actually written. Snapshots use this to skip re-serializing components
+/// that haven't changed.
+pub(crate) struct ComponentEntry {
+  lock: RwLock<Box<dyn Component>>,
+  changed: AtomicU64,
+}
+
+impl ComponentEntry {
+  pub(crate) fn new(component: Box<dyn Component>) -> Self {
+    Self::with_tick(component, next_tick())
+  }
+
+  /// Make an entry that pretends it was last changed at the given tick.
+  pub(crate) fn with_tick(component: Box<dyn Component>, tick: u64) -> Self {
+    Self {
+      lock: RwLock::new(component),
+      changed: AtomicU64::new(tick),
+    }
+  }
+
+  /// The tick this was last mutably borrowed at.
+  pub(crate) fn changed(&self) -> u64 {
+    self.changed.load(Ordering::Relaxed)
+  }
+
+  pub(crate) fn read(
+    &self,
+  ) -> LockResult<RwLockReadGuard<'_, Box<dyn Component>>> {
+    self.lock.read()
+  }
+
+  pub(crate) fn try_read(
+    &self,
+  ) -> TryLockResult<RwLockReadGuard<'_, Box<dyn Component>>> {
+    self.lock.try_read()
+  }
+
+  pub(crate) fn try_write(
+    &self,
+  ) -> TryLockResult<RwLockWriteGuard<'_, Box<dyn Component>>> {
+    let lock = self.lock.try_write();
+    if lock.is_ok() {
+      self.changed.store(next_tick(), Ordering::Relaxed);
+    }
+    lock
+  }
+
+  pub(crate) fn get_mut(&mut self) -> LockResult<&mut Box<dyn Component>> {
+    *self.changed.get_mut() = next_tick();
+    self.lock.get_mut()
+  }
+
+  pub(crate) fn into_inner(self) -> LockResult<Box<dyn Component>> {
+    self.lock.into_inner()
+  }
+}
 
 /// World storage for the resources
 pub(crate) struct ResourceMap {
@@ -280,8 +437,29 @@ impl ResourceMap {
       .map(|old| old.into_inner().unwrap())
   }
 
+  /// Remove a resource by its type ID.
+  ///
+  /// If the value is poisoned, silently return `None`.
+  pub fn remove_raw(
+    &mut self,
+    tid: TypeIdWrapper,
+  ) -> Option<Box<dyn Resource>> {
+    self.map.remove(&tid)?.into_inner().ok()
+  }
+
+  pub fn get_raw(
+    &self,
+    tid: TypeIdWrapper,
+  ) -> Option<&RwLock<Box<dyn Resource>>> {
+    self.map.get(&tid)
+  }
+
   pub fn contains<T: Resource>(&self) -> bool {
-    self.map.contains_key(&TypeIdWrapper::of::<T>())
+    self.contains_tid(TypeIdWrapper::of::<T>())
+  }
+
+  pub fn contains_tid(&self, tid: TypeIdWrapper) -> bool {
+    self.map.contains_key(&tid)
   }
 
   pub fn iter(
@@ -291,7 +469,28 @@ impl ResourceMap {
     self.map.iter().map(|(tid, res)| (*tid, res))
   }
 
+  #[allow(unused)]
   pub fn len(&self) -> usize {
     self.map.len()
   }
+
+  /// Rewrite the entities in every resource that knows how.
+  pub fn map_entities(&mut self, map: &EntityMap) {
+    for (tid, res) in self.map.iter_mut() {
+      let vtable = ResourceVtables::by_tid(*tid);
+      if let Some(map_entities) = vtable.map_entities {
+        map_entities(&mut **res.get_mut().unwrap(), map);
+      }
+    }
+  }
+
+  /// Take all the resources out of the map.
+  ///
+  /// Poisoned resources are silently dropped.
+  pub fn into_values(self) -> impl Iterator<Item = Box<dyn Resource>> {
+    self
+      .map
+      .into_values()
+      .filter_map(|res| res.into_inner().ok())
+  }
 }
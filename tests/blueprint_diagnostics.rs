@@ -0,0 +1,86 @@
+//! Check that instantiation errors point into the blueprint files.
+
+use miette::Diagnostic;
+use palkia::prelude::*;
+use serde::{Deserialize, Serialize};
+
+const BASE: &str = r#"
+mob {
+  has-hp start-hp="lots"
+}
+"#;
+
+const MONSTERS: &str = r#"
+goblin {
+  (splice)mob
+}
+
+goblin-chief {
+  (splice)goblin
+}
+
+ghost {
+  (splice)spooky
+}
+"#;
+
+/// The text a diagnostic's first label points at, and its file's name.
+fn pointed(diag: &dyn Diagnostic) -> (String, String) {
+  let span = *diag.labels().unwrap().next().unwrap().inner();
+  let contents = diag.source_code().unwrap().read_span(&span, 0, 0).unwrap();
+  (
+    contents.name().unwrap().to_owned(),
+    String::from_utf8(contents.data().to_vec()).unwrap(),
+  )
+}
+
+#[test]
+fn diagnostics() {
+  let mut fab = EntityFabricator::<()>::new();
+  fab.load_str(BASE, "base.kdl").unwrap();
+  fab.load_str(MONSTERS, "monsters.kdl").unwrap();
+  let mut world = World::new();
+
+  let err = fab
+    .instantiate("goblin-chief", world.spawn(), &())
+    .unwrap_err();
+  let (file, text) = pointed(&err);
+  assert_eq!(file, "base.kdl");
+  assert!(text.contains("has-hp"), "{}", text);
+
+  // Then each splice that brought it in, innermost first
+  let splices = err
+    .related()
+    .unwrap()
+    .map(|it| {
+      let (file, text) = pointed(it);
+      (it.to_string(), file, text)
+    })
+    .collect::<Vec<_>>();
+  assert_eq!(splices.len(), 2);
+  assert_eq!(splices[0].0, "spliced in by the blueprint goblin");
+  assert_eq!(splices[0].1, "monsters.kdl");
+  assert!(splices[0].2.contains("(splice)mob"));
+  assert_eq!(splices[1].0, "spliced in by the blueprint goblin-chief");
+  assert!(splices[1].2.contains("(splice)goblin"));
+
+  let err = fab.instantiate("ghost", world.spawn(), &()).unwrap_err();
+  let (file, text) = pointed(&err);
+  assert_eq!(file, "monsters.kdl");
+  assert!(text.contains("(splice)spooky"), "{}", text);
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct HasHp {
+  start_hp: u32,
+}
+
+impl Component for HasHp {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("has-hp")
+  }
+}
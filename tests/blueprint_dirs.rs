@@ -0,0 +1,138 @@
+//! Check loading blueprints from directories in layers.
+
+use std::{fs, path::PathBuf};
+
+use miette::SourceCode;
+use palkia::{
+  fabricator::blueprint::{BlueprintLoadError, BlueprintParseError},
+  prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+/// Write the files into a fresh temp directory.
+fn setup(name: &str, files: &[(&str, &str)]) -> PathBuf {
+  let root = std::env::temp_dir().join(format!(
+    "palkia-{}-{}",
+    name,
+    std::process::id()
+  ));
+  let _ = fs::remove_dir_all(&root);
+  for (path, src) in files {
+    let path = root.join(path);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, src).unwrap();
+  }
+  root
+}
+
+#[test]
+fn layers() {
+  let root = setup(
+    "layers",
+    &[
+      ("base/mobs/goblin.kdl", "goblin { name \"goblin\"; hp 10; }"),
+      // Loaded after `mobs/goblin.kdl` because it sorts after it
+      ("base/mobs/goblin2.kdl", "goblin { hp 12; }"),
+      ("base/items.kdl", "sword { name \"sword\"; }"),
+      ("base/readme.txt", "not a blueprint"),
+      ("mymod/goblin.kdl", "goblin { name \"gobbo\"; }"),
+      ("mymod/sword.kdl", "sword merge=\"clobber\" { hp 1; }"),
+    ],
+  );
+
+  let mut fab = EntityFabricator::<()>::new();
+  fab.push_layer("base");
+  fab.load_dir(root.join("base")).unwrap();
+  fab.push_layer("mymod");
+  fab.load_paths([root.join("mymod")]).unwrap();
+
+  let mut world = World::new();
+  let e = fab.instantiate("goblin", world.spawn(), &()).unwrap();
+  assert_eq!(world.query::<&Name>(e).unwrap().0, "gobbo");
+  assert_eq!(world.query::<&Hp>(e).unwrap().0, 12);
+  let e = fab.instantiate("sword", world.spawn(), &()).unwrap();
+  assert!(world.query::<&Name>(e).is_none());
+
+  let origins = fab
+    .blueprint_origins("goblin")
+    .unwrap()
+    .iter()
+    .map(|it| {
+      let file = PathBuf::from(it.file.as_str());
+      let file = file.strip_prefix(&root).unwrap().to_owned();
+      (it.layer.as_deref().unwrap().to_owned(), file)
+    })
+    .collect::<Vec<_>>();
+  assert_eq!(
+    origins,
+    [
+      ("base".to_owned(), PathBuf::from("base/mobs/goblin.kdl")),
+      ("base".to_owned(), PathBuf::from("base/mobs/goblin2.kdl")),
+      ("mymod".to_owned(), PathBuf::from("mymod/goblin.kdl")),
+    ]
+  );
+  // Clobbering forgets the old origins
+  assert_eq!(fab.blueprint_origins("sword").unwrap().len(), 1);
+
+  fs::remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn load_errors() {
+  let root = setup("load-errors", &[("bad.kdl", "oops")]);
+
+  let mut fab = EntityFabricator::<()>::new();
+  let err = fab.load_dir(root.join("missing")).unwrap_err();
+  assert!(matches!(err, BlueprintLoadError::Io { .. }), "{}", err);
+
+  fab.push_layer("mymod");
+  let err = fab.load_dir(&root).unwrap_err();
+  let BlueprintLoadError::Parse(BlueprintParseError::Deser(err)) = err else {
+    panic!("wrong error: {}", err);
+  };
+  // The error says which layer it came from
+  let contents = err.src.read_span(&err.span, 0, 0).unwrap();
+  let name = contents.name().unwrap();
+  assert!(name.starts_with("mymod: "), "{}", name);
+
+  fs::remove_dir_all(root).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn symlink_loop() {
+  let root = setup("symlink-loop", &[("mobs/goblin.kdl", "goblin { hp 10; }")]);
+  std::os::unix::fs::symlink(&root, root.join("mobs/loop")).unwrap();
+
+  let mut fab = EntityFabricator::<()>::new();
+  fab.load_dir(&root).unwrap();
+  assert_eq!(fab.loaded_paths().count(), 1);
+
+  fs::remove_dir_all(root).unwrap();
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Name(String);
+
+impl Component for Name {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("name")
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Hp(u32);
+
+impl Component for Hp {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("hp")
+  }
+}
@@ -0,0 +1,150 @@
+//! Check abstract blueprints and querying blueprint metadata.
+
+use kdl::KdlValue;
+use palkia::{fabricator::InstantiationError, prelude::*};
+use serde::{Deserialize, Serialize};
+
+const BLUEPRINTS: &str = r#"
+mob abstract=true {
+  hp 10
+}
+
+legend abstract=true {
+  (splice)mob
+}
+
+goblin biome=(meta)"swamp" rarity=(meta)1 {
+  (splice)mob
+}
+
+bog-king biome=(meta)"swamp" rarity=(meta)3 {
+  (splice)legend
+}
+
+yeti biome=(meta)"tundra" rarity=(meta)2 {
+  (splice)mob
+}
+"#;
+
+#[test]
+fn meta() {
+  let mut fab = EntityFabricator::<()>::new();
+  fab.load_str(BLUEPRINTS, "blueprints.kdl").unwrap();
+
+  let swamp = fab.blueprints_where(|meta| {
+    meta.get("biome") == Some(&KdlValue::String("swamp".to_owned()))
+  });
+  assert_eq!(swamp, ["bog-king", "goblin"]);
+  let rare = fab.blueprints_where(|meta| {
+    meta
+      .get("rarity")
+      .and_then(KdlValue::as_i64)
+      .is_some_and(|it| it >= 2)
+  });
+  assert_eq!(rare, ["bog-king", "yeti"]);
+  let bases = fab.blueprints_where(|meta| meta.is_abstract());
+  assert_eq!(bases, ["legend", "mob"]);
+
+  assert!(fab.inherits_from("bog-king", "mob"));
+  assert!(fab.inherits_from("bog-king", "legend"));
+  assert!(!fab.inherits_from("goblin", "legend"));
+  assert!(!fab.inherits_from("mob", "mob"));
+
+  // Metadata can be merged in later
+  fab
+    .load_str("goblin merge=\"merge\" rarity=(meta)5 {}", "mod.kdl")
+    .unwrap();
+  let goblin = fab.blueprint_meta("goblin").unwrap();
+  assert_eq!(goblin.get("rarity"), Some(&KdlValue::Base10(5)));
+  assert_eq!(
+    goblin.get("biome"),
+    Some(&KdlValue::String("swamp".to_owned()))
+  );
+}
+
+#[test]
+fn abstract_blueprints() {
+  let mut fab = EntityFabricator::<()>::new();
+  fab.load_str(BLUEPRINTS, "blueprints.kdl").unwrap();
+  let mut world = World::new();
+
+  let err = fab.instantiate("mob", world.spawn(), &()).unwrap_err();
+  assert!(
+    matches!(err, InstantiationError::Abstract(ref name) if name == "mob")
+  );
+
+  // But things that splice them in are fine
+  let e = fab.instantiate("bog-king", world.spawn(), &()).unwrap();
+  assert_eq!(world.query::<&Hp>(e).unwrap().0, 10);
+
+  assert!(fab.load_str("rat abstract=\"yes\" {}", "bad.kdl").is_err());
+  assert!(fab
+    .load_str("rat abstract=(meta)\"yes\" {}", "bad.kdl")
+    .is_err());
+  assert!(fab
+    .load_str("rat merge=(meta)\"sideways\" {}", "bad.kdl")
+    .is_err());
+  assert!(fab
+    .load_str("rat rarity=(meta)1 rarity=(meta)2 {}", "bad.kdl")
+    .is_err());
+}
+
+#[test]
+fn meta_isnt_params() {
+  let mut fab = EntityFabricator::<()>::new();
+  fab
+    .load_str(
+      r#"
+rat biome=(meta)"swamp" level=1 {
+  hp "{level * 5}"
+}
+"#,
+      "rats.kdl",
+    )
+    .unwrap();
+  let mut world = World::new();
+
+  let rat = fab.blueprint_meta("rat").unwrap();
+  assert!(rat.get("biome").is_some());
+  assert!(rat.get("level").is_none());
+  let e = fab
+    .instantiate_with("rat", &[("level", 2.into())], world.spawn(), &())
+    .unwrap();
+  assert_eq!(world.query::<&Hp>(e).unwrap().0, 10);
+  assert!(fab
+    .instantiate_with("rat", &[("biome", "bog".into())], world.spawn(), &())
+    .is_err());
+
+  // Metadata alone doesn't make the blueprint a template
+  fab
+    .load_str("bat biome=(meta)\"cave\" { tag \"{level}\"; }", "bats.kdl")
+    .unwrap();
+  let e = fab.instantiate("bat", world.spawn(), &()).unwrap();
+  assert_eq!(world.query::<&Tag>(e).unwrap().0, "{level}");
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Hp(u32);
+
+impl Component for Hp {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("hp")
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Tag(String);
+
+impl Component for Tag {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("tag")
+  }
+}
@@ -0,0 +1,181 @@
+//! Check passing parameters to blueprints.
+
+use palkia::{
+  fabricator::{
+    blueprint::{BlueprintLookupError, BlueprintParamErrorKind},
+    InstantiationError,
+  },
+  prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+const BLUEPRINTS: &str = r#"
+goblin level=1 title="grunt" {
+  name "goblin {title}, level {level}"
+  has-hp start-hp="{level * 10}"
+}
+
+boss {
+  (splice)goblin level=5 title="chief"
+  name "goblin chief"
+}
+
+named-goblin name=null level=(param)2 {
+  (splice)goblin level="{level + 1}"
+  name "{name}"
+}
+
+literal {
+  name "{not a template}"
+}
+
+braces level=1 {
+  name "{{level {level}}}"
+}
+"#;
+
+fn fab() -> EntityFabricator<()> {
+  let mut fab = EntityFabricator::new();
+  fab.load_str(BLUEPRINTS, "blueprints.kdl").unwrap();
+  fab
+}
+
+#[test]
+fn params() {
+  let fab = fab();
+  let mut world = World::new();
+
+  let e = fab.instantiate("goblin", world.spawn(), &()).unwrap();
+  assert_eq!(world.query::<&Name>(e).unwrap().0, "goblin grunt, level 1");
+  assert_eq!(world.query::<&HasHp>(e).unwrap().start_hp, 10);
+
+  let e = fab
+    .instantiate_with("goblin", &[("level", 3.into())], world.spawn(), &())
+    .unwrap();
+  assert_eq!(world.query::<&Name>(e).unwrap().0, "goblin grunt, level 3");
+  assert_eq!(world.query::<&HasHp>(e).unwrap().start_hp, 30);
+
+  let e = fab.instantiate("boss", world.spawn(), &()).unwrap();
+  assert_eq!(world.query::<&Name>(e).unwrap().0, "goblin chief");
+  assert_eq!(world.query::<&HasHp>(e).unwrap().start_hp, 50);
+
+  let e = fab
+    .instantiate_with(
+      "named-goblin",
+      &[("name", "gob".into())],
+      world.spawn(),
+      &(),
+    )
+    .unwrap();
+  assert_eq!(world.query::<&Name>(e).unwrap().0, "gob");
+  assert_eq!(world.query::<&HasHp>(e).unwrap().start_hp, 30);
+
+  // Blueprints without parameters don't get templated
+  let e = fab.instantiate("literal", world.spawn(), &()).unwrap();
+  assert_eq!(world.query::<&Name>(e).unwrap().0, "{not a template}");
+
+  // But ones with parameters need their literal braces doubled
+  let e = fab.instantiate("braces", world.spawn(), &()).unwrap();
+  assert_eq!(world.query::<&Name>(e).unwrap().0, "{level 1}");
+}
+
+#[test]
+fn bad_params() {
+  let fab = fab();
+  let mut world = World::new();
+
+  let kind = |err: InstantiationError| match err {
+    InstantiationError::BlueprintLookupError(BlueprintLookupError::Param(
+      err,
+    )) => err.kind,
+    err => panic!("wrong error: {}", err),
+  };
+
+  let err = fab
+    .instantiate("named-goblin", world.spawn(), &())
+    .unwrap_err();
+  assert!(
+    matches!(kind(err), BlueprintParamErrorKind::Missing { ref param, .. } if param == "name")
+  );
+
+  let err = fab
+    .instantiate_with("goblin", &[("level", "high".into())], world.spawn(), &())
+    .unwrap_err();
+  assert!(matches!(
+    kind(err),
+    BlueprintParamErrorKind::WrongType {
+      expected: "an integer",
+      found: "a string",
+      ..
+    }
+  ));
+
+  let err = fab
+    .instantiate_with("goblin", &[("levle", 3.into())], world.spawn(), &())
+    .unwrap_err();
+  assert!(
+    matches!(kind(err), BlueprintParamErrorKind::Unknown { ref param, .. } if param == "levle")
+  );
+
+  let mut fab = EntityFabricator::<()>::new();
+  fab
+    .load_str(
+      r#"
+goblin level=1 {
+  has-hp start-hp="{level * lots}"
+}
+"#,
+      "bad.kdl",
+    )
+    .unwrap();
+  let err = fab.instantiate("goblin", world.spawn(), &()).unwrap_err();
+  let BlueprintParamErrorKind::Template(msg) = kind(err) else {
+    panic!("wrong error");
+  };
+  assert!(msg.contains("lots"), "{}", msg);
+
+  // Each param can only be declared once, marked or not
+  assert!(fab
+    .load_str("twice level=1 level=(param)2 {}", "twice.kdl")
+    .is_err());
+  assert!(fab
+    .load_str("twice level=(param)1 level=2 {}", "twice.kdl")
+    .is_err());
+
+  let err = fab.instantiate("nobody", world.spawn(), &()).unwrap_err();
+  let InstantiationError::BlueprintLookupError(err) = err else {
+    panic!("wrong error");
+  };
+  assert_eq!(
+    err,
+    BlueprintLookupError::BlueprintNotFound("nobody".into())
+  );
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Name(String);
+
+impl Component for Name {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("name")
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct HasHp {
+  start_hp: u32,
+}
+
+impl Component for HasHp {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("has-hp")
+  }
+}
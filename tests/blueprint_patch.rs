@@ -0,0 +1,136 @@
+//! Check patching inherited components in blueprints.
+
+use palkia::{
+  fabricator::{blueprint::BlueprintLookupError, InstantiationError},
+  prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+const BLUEPRINTS: &str = r#"
+mob {
+  stats strength=3 speed=5 {
+    resist fire=1
+  }
+  position 0 0
+}
+
+fast-mob {
+  (splice)mob
+  (patch)stats speed=10 {
+    resist ice=2
+  }
+  (patch)position 4 5
+}
+
+fireproof-mob {
+  (splice)fast-mob
+  (patch)stats {
+    resist fire=9
+  }
+}
+"#;
+
+const MOD: &str = r#"
+mob {
+  (patch)stats strength=4
+}
+
+fast-mob {
+  (patch)stats speed=11
+}
+"#;
+
+#[test]
+fn patch() {
+  let mut fab = EntityFabricator::<()>::new();
+  fab.load_str(BLUEPRINTS, "blueprints.kdl").unwrap();
+  let mut world = World::new();
+
+  let e = fab.instantiate("fast-mob", world.spawn(), &()).unwrap();
+  let stats = world.query::<&Stats>(e).unwrap();
+  assert_eq!((stats.strength, stats.speed), (3, 10));
+  assert_eq!(stats.resist, Resist { fire: 1, ice: 2 });
+  drop(stats);
+  assert_eq!(*world.query::<&Position>(e).unwrap(), Position(4, 5));
+
+  let e = fab
+    .instantiate("fireproof-mob", world.spawn(), &())
+    .unwrap();
+  let stats = world.query::<&Stats>(e).unwrap();
+  assert_eq!(stats.speed, 10);
+  assert_eq!(stats.resist, Resist { fire: 9, ice: 2 });
+  drop(stats);
+
+  // Patches in later files merge too
+  fab.load_str(MOD, "mod.kdl").unwrap();
+  let e = fab.instantiate("fast-mob", world.spawn(), &()).unwrap();
+  let stats = world.query::<&Stats>(e).unwrap();
+  assert_eq!((stats.strength, stats.speed), (4, 11));
+  drop(stats);
+
+  // But restating the whole component clobbers them
+  fab
+    .load_str("fast-mob { stats strength=1 speed=1; }", "mod2.kdl")
+    .unwrap();
+  let e = fab.instantiate("fast-mob", world.spawn(), &()).unwrap();
+  let stats = world.query::<&Stats>(e).unwrap();
+  assert_eq!((stats.strength, stats.speed), (1, 1));
+  assert_eq!(stats.resist, Resist::default());
+}
+
+#[test]
+fn nothing_to_patch() {
+  let mut fab = EntityFabricator::<()>::new();
+  fab
+    .load_str("lonely { (patch)stats speed=1; }", "lonely.kdl")
+    .unwrap();
+  let mut world = World::new();
+  let err = fab.instantiate("lonely", world.spawn(), &()).unwrap_err();
+  assert!(matches!(
+    err,
+    InstantiationError::BlueprintLookupError(
+      BlueprintLookupError::NothingToPatch { .. }
+    )
+  ));
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Stats {
+  #[serde(default)]
+  strength: u32,
+  #[serde(default)]
+  speed: u32,
+  #[serde(default)]
+  resist: Resist,
+}
+
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+struct Resist {
+  #[serde(default)]
+  fire: u32,
+  #[serde(default)]
+  ice: u32,
+}
+
+impl Component for Stats {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("stats")
+  }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[register_component]
+struct Position(i32, i32);
+
+impl Component for Position {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("position")
+  }
+}
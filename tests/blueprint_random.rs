@@ -0,0 +1,244 @@
+//! Check `(choose)` and `(maybe)` blueprint elements.
+
+use palkia::{
+  fabricator::{
+    blueprint::{BlueprintLibrary, BlueprintLookupError},
+    ValidationErrorKind,
+  },
+  prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+const BLUEPRINTS: &str = r#"
+warrior {
+  weapon "sword"
+}
+
+goblin {
+  hp 10
+  (choose)loadout {
+    weapon "club"
+    weapon "spear" choose-weight=2
+    (splice)warrior
+  }
+  (maybe)hat chance=0.5 {
+    hat "pointy"
+  }
+}
+
+always {
+  (maybe)hat chance=1 {
+    hat "top"
+  }
+  (maybe)never chance=0 {
+    hp 1
+  }
+}
+"#;
+
+fn roll(fab: &EntityFabricator<()>, name: &str) -> (Option<String>, bool) {
+  let mut world = World::new();
+  let e = fab.instantiate(name, world.spawn(), &()).unwrap();
+  let weapon = world.query::<&Weapon>(e).map(|it| it.0.clone());
+  let hat = world.query::<&Hat>(e).is_some();
+  (weapon, hat)
+}
+
+#[test]
+fn random() {
+  let mut fab = EntityFabricator::<()>::new();
+  fab.load_str(BLUEPRINTS, "blueprints.kdl").unwrap();
+
+  fab.seed_rng(1234);
+  let rolls = (0..200).map(|_| roll(&fab, "goblin")).collect::<Vec<_>>();
+  for weapon in ["club", "spear", "sword"] {
+    assert!(rolls.iter().any(|it| it.0.as_deref() == Some(weapon)));
+  }
+  assert!(rolls.iter().any(|it| it.1));
+  assert!(rolls.iter().any(|it| !it.1));
+
+  // Reseeding gives the same results again
+  fab.seed_rng(1234);
+  let again = (0..200).map(|_| roll(&fab, "goblin")).collect::<Vec<_>>();
+  assert_eq!(rolls, again);
+
+  for _ in 0..20 {
+    assert_eq!(roll(&fab, "always"), (None, true));
+  }
+}
+
+#[test]
+fn weight_fields() {
+  let mut fab = EntityFabricator::<()>::new();
+  fab
+    .load_str(
+      r#"
+chest {
+  (choose)loot {
+    loot item="gold" weight=5 choose-weight=3
+    loot item="rock" weight=20
+  }
+}
+"#,
+      "chest.kdl",
+    )
+    .unwrap();
+
+  // Only `choose-weight` is taken off, so the component keeps its own weight
+  let mut world = World::new();
+  let mut seen = Vec::new();
+  for _ in 0..50 {
+    let e = fab.instantiate("chest", world.spawn(), &()).unwrap();
+    let loot = world.query::<&Loot>(e).unwrap();
+    let expected = if loot.item == "gold" { 5 } else { 20 };
+    assert_eq!(loot.weight, expected, "{}", loot.item);
+    seen.push(loot.item.clone());
+  }
+  assert!(seen.iter().any(|it| it == "gold"));
+  assert!(seen.iter().any(|it| it == "rock"));
+}
+
+#[test]
+fn bad_random() {
+  let mut fab = EntityFabricator::<()>::new();
+  for bad in [
+    "rat { (choose)x; }",
+    "rat { (choose)x 1 { hp 1; } }",
+    "rat { (choose)x { hp 1 choose-weight=0; } }",
+    "rat { (maybe)x { hp 1; } }",
+    "rat { (maybe)x chance=1.5 { hp 1; } }",
+  ] {
+    assert!(fab.load_str(bad, "bad.kdl").is_err(), "{}", bad);
+  }
+
+  // Everything that could be rolled gets validated
+  fab
+    .load_str(
+      "rat { (choose)x { hp 1; (maybe)y chance=0 { ectoplasm 3; }; }; }",
+      "rat.kdl",
+    )
+    .unwrap();
+  let errs = fab.validate();
+  assert_eq!(errs.len(), 1);
+  assert!(matches!(
+    errs[0].kind,
+    ValidationErrorKind::NoAssembler(ref name) if name == "ectoplasm"
+  ));
+}
+
+#[test]
+fn validate_every_roll() {
+  let rat = r#"
+    rat {
+      (choose)x {
+        hp 1
+        hp "lots"
+        (patch)weapon;
+      }
+      (maybe)y chance=0.5 {
+        weapon "teeth"
+      }
+    }
+  "#;
+  let mut fab = EntityFabricator::<()>::new();
+  fab.load_str(rat, "rat.kdl").unwrap();
+  let mut lib = BlueprintLibrary::new();
+  lib.load_str(rat, "rat.kdl").unwrap();
+
+  // The patch fails whenever it gets picked, even though it isn't the first
+  // option
+  let err = lib.lookup_every_roll("rat", 100).err().unwrap();
+  assert!(matches!(
+    err,
+    BlueprintLookupError::NothingToPatch { ref component, .. }
+      if component == "weapon"
+  ));
+
+  // Every time, not just when the patch gets rolled
+  for _ in 0..20 {
+    let errs = fab.validate_with(&());
+    let kinds = errs.iter().map(|it| &it.kind).collect::<Vec<_>>();
+    assert!(
+      matches!(
+        kinds[..],
+        [ValidationErrorKind::Lookup(
+          BlueprintLookupError::NothingToPatch { .. }
+        )]
+      ),
+      "{:?}",
+      errs
+    );
+  }
+
+  let rat =
+    "rat { (choose)x { hp 1; hp \"lots\"; }; (maybe)y chance=0.5 { hp 2; }; }";
+  fab.reload_str(rat, "rat.kdl").unwrap();
+  lib.reload_str(rat, "rat.kdl").unwrap();
+  // Two options times two
+  let rolls = lib.lookup_every_roll("rat", 100).unwrap();
+  assert_eq!(rolls.len(), 4);
+  assert_eq!(lib.lookup_every_roll("rat", 3).unwrap().len(), 3);
+  for _ in 0..20 {
+    let errs = fab.validate_with(&());
+    assert_eq!(errs.len(), 1, "{:?}", errs);
+    assert!(matches!(
+      errs[0].kind,
+      ValidationErrorKind::AssemblerError(ref name, _) if name == "hp"
+    ));
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Hp(u32);
+
+impl Component for Hp {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("hp")
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Weapon(String);
+
+impl Component for Weapon {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("weapon")
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Hat(String);
+
+impl Component for Hat {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("hat")
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Loot {
+  item: String,
+  weight: u32,
+}
+
+impl Component for Loot {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("loot")
+  }
+}
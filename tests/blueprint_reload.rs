@@ -0,0 +1,165 @@
+//! Check hot-reloading blueprints.
+
+use std::{
+  fs::{self, File},
+  time::{Duration, SystemTime},
+};
+
+use palkia::{fabricator::watch::BlueprintWatcher, prelude::*};
+use serde::{Deserialize, Serialize};
+
+fn stats(fab: &EntityFabricator<()>, name: &str) -> (String, u32) {
+  let mut world = World::new();
+  let e = fab.instantiate(name, world.spawn(), &()).unwrap();
+  let name = world
+    .query::<&Name>(e)
+    .map(|it| it.0.clone())
+    .unwrap_or_default();
+  let hp = world.query::<&Hp>(e).map(|it| it.0).unwrap_or_default();
+  (name, hp)
+}
+
+#[test]
+fn reload() {
+  let mut fab = EntityFabricator::<()>::new();
+  fab
+    .load_str(
+      "goblin { name \"goblin\"; hp 10; }\nrat { hp 1; }",
+      "base.kdl",
+    )
+    .unwrap();
+  fab
+    .load_str("goblin { name \"gobbo\"; }", "mod.kdl")
+    .unwrap();
+  assert_eq!(stats(&fab, "goblin"), ("gobbo".to_owned(), 10));
+
+  // The mod still overrides the new version
+  fab
+    .reload_str("goblin { name \"goblin\"; hp 20; }", "base.kdl")
+    .unwrap();
+  assert_eq!(stats(&fab, "goblin"), ("gobbo".to_owned(), 20));
+  // Blueprints that aren't in the new version are gone
+  let mut world = World::new();
+  assert!(fab.instantiate("rat", world.spawn(), &()).is_err());
+
+  // Bad versions don't replace good ones
+  assert!(fab.reload_str("goblin", "base.kdl").is_err());
+  assert_eq!(stats(&fab, "goblin"), ("gobbo".to_owned(), 20));
+
+  fab.reload_str("", "mod.kdl").unwrap();
+  assert_eq!(stats(&fab, "goblin"), ("goblin".to_owned(), 20));
+}
+
+#[test]
+fn reload_same_name() {
+  let mut fab = EntityFabricator::<()>::new();
+  fab.push_layer("base");
+  fab
+    .load_str("goblin { name \"goblin\"; hp 10; }", "goblin.kdl")
+    .unwrap();
+  fab.push_layer("mymod");
+  fab.load_str("goblin { hp 12; }", "goblin.kdl").unwrap();
+  assert_eq!(stats(&fab, "goblin"), ("goblin".to_owned(), 12));
+
+  // Only the last one is replaced
+  fab.reload_str("goblin { hp 14; }", "goblin.kdl").unwrap();
+  assert_eq!(stats(&fab, "goblin"), ("goblin".to_owned(), 14));
+
+  fab
+    .reload_str_in_layer(
+      "goblin { name \"gobbo\"; }",
+      "goblin.kdl",
+      Some("base"),
+    )
+    .unwrap();
+  assert_eq!(stats(&fab, "goblin"), ("gobbo".to_owned(), 14));
+}
+
+#[test]
+fn watch() {
+  let root =
+    std::env::temp_dir().join(format!("palkia-watch-{}", std::process::id()));
+  let _ = fs::remove_dir_all(&root);
+  fs::create_dir_all(&root).unwrap();
+  let path = root.join("goblin.kdl");
+  fs::write(&path, "goblin { hp 10; }").unwrap();
+
+  let mut fab = EntityFabricator::<()>::new();
+  fab.load_dir(&root).unwrap();
+  let mut watcher = BlueprintWatcher::new(&fab);
+  assert_eq!(watcher.files().collect::<Vec<_>>(), [path.as_path()]);
+  assert!(watcher.poll(&mut fab).is_empty());
+
+  // Make sure the modification time changes even on coarse filesystems
+  let touch = |secs| {
+    File::options()
+      .write(true)
+      .open(&path)
+      .unwrap()
+      .set_modified(SystemTime::now() + Duration::from_secs(secs))
+      .unwrap();
+  };
+
+  fs::write(&path, "goblin { hp 20; }").unwrap();
+  touch(10);
+  let reloaded = watcher.poll(&mut fab);
+  assert_eq!(reloaded.len(), 1);
+  assert!(reloaded[0].1.is_ok());
+  assert_eq!(stats(&fab, "goblin").1, 20);
+  assert!(watcher.poll(&mut fab).is_empty());
+
+  fs::write(&path, "goblin {").unwrap();
+  touch(20);
+  let reloaded = watcher.poll(&mut fab);
+  assert!(reloaded[0].1.is_err());
+  assert_eq!(stats(&fab, "goblin").1, 20);
+  // It's not retried until it changes again
+  assert!(watcher.poll(&mut fab).is_empty());
+
+  // Files loaded after the watcher was made are watched too
+  let rat = root.join("rat.kdl");
+  fs::write(&rat, "rat { hp 1; }").unwrap();
+  fab.load_paths([&rat]).unwrap();
+  assert!(watcher.poll(&mut fab).is_empty());
+  assert_eq!(
+    watcher.files().collect::<Vec<_>>(),
+    [path.as_path(), rat.as_path()]
+  );
+  fs::write(&rat, "rat { hp 2; }").unwrap();
+  File::options()
+    .write(true)
+    .open(&rat)
+    .unwrap()
+    .set_modified(SystemTime::now() + Duration::from_secs(10))
+    .unwrap();
+  assert_eq!(watcher.poll(&mut fab).len(), 1);
+  assert_eq!(stats(&fab, "rat").1, 2);
+
+  fs::remove_dir_all(root).unwrap();
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Name(String);
+
+impl Component for Name {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("name")
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Hp(u32);
+
+impl Component for Hp {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("hp")
+  }
+}
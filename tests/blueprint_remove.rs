@@ -0,0 +1,111 @@
+//! Check removing inherited components from blueprints.
+
+use palkia::prelude::*;
+use serde::{Deserialize, Serialize};
+
+const BLUEPRINTS: &str = r#"
+mob {
+  name "mob"
+  physic-body
+  has-hp start-hp=10
+}
+
+ghost {
+  (splice)mob
+  (remove)physic-body
+  name "ghost"
+}
+
+zombie {
+  (splice)mob
+  name "zombie"
+}
+"#;
+
+const MOD: &str = r#"
+zombie {
+  (remove)has-hp
+}
+
+mob {
+  (remove)name
+}
+"#;
+
+#[test]
+fn remove() {
+  let mut fab = EntityFabricator::<()>::new();
+  fab.load_str(BLUEPRINTS, "blueprints.kdl").unwrap();
+  let mut world = World::new();
+
+  let e = fab.instantiate("ghost", world.spawn(), &()).unwrap();
+  assert_eq!(world.query::<&Name>(e).unwrap().0, "ghost");
+  assert!(world.query::<&PhysicBody>(e).is_none());
+  assert_eq!(world.query::<&HasHp>(e).unwrap().start_hp, 10);
+
+  fab.load_str(MOD, "mod.kdl").unwrap();
+
+  // Removes the spliced-in component
+  let e = fab.instantiate("zombie", world.spawn(), &()).unwrap();
+  assert_eq!(world.query::<&Name>(e).unwrap().0, "zombie");
+  assert!(world.query::<&HasHp>(e).is_none());
+  assert!(world.query::<&PhysicBody>(e).is_some());
+
+  // Removes the merged component
+  let e = fab.instantiate("mob", world.spawn(), &()).unwrap();
+  assert!(world.query::<&Name>(e).is_none());
+  assert!(world.query::<&HasHp>(e).is_some());
+
+  // Adding it back after removing it works
+  fab
+    .load_str("mob { name \"mob again\"; }", "mod2.kdl")
+    .unwrap();
+  let e = fab.instantiate("mob", world.spawn(), &()).unwrap();
+  assert_eq!(world.query::<&Name>(e).unwrap().0, "mob again");
+
+  // Removes can't have anything else
+  assert!(fab
+    .load_str("bad { (remove)name \"oops\"; }", "bad.kdl")
+    .is_err());
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Name(String);
+
+impl Component for Name {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("name")
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct PhysicBody;
+
+impl Component for PhysicBody {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("physic-body")
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct HasHp {
+  start_hp: u32,
+}
+
+impl Component for HasHp {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("has-hp")
+  }
+}
@@ -0,0 +1,99 @@
+//! Check validating a whole blueprint library at once.
+
+use miette::SourceCode;
+use palkia::{
+  fabricator::{
+    blueprint::BlueprintLookupError, ValidationError, ValidationErrorKind,
+  },
+  prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+const BLUEPRINTS: &str = r#"
+goblin {
+  hp 10
+}
+
+ghost {
+  (splice)spooky
+  ectoplasm 3
+}
+
+ouroboros {
+  (splice)snake
+}
+
+snake {
+  (splice)ouroboros
+}
+
+fat-goblin {
+  (splice)goblin
+  hp "lots"
+}
+
+leveled level=(param)1 {
+  hp "{level * 10}"
+}
+
+bad-splice {
+  (splice)leveled level="high"
+}
+"#;
+
+/// The text each error points at.
+fn pointed(err: &ValidationError) -> String {
+  let contents = err.src.read_span(&err.span, 0, 0).unwrap();
+  String::from_utf8(contents.data().to_vec()).unwrap()
+}
+
+#[test]
+fn validate() {
+  let mut fab = EntityFabricator::<()>::new();
+  fab.load_str(BLUEPRINTS, "blueprints.kdl").unwrap();
+
+  let errs = fab.validate();
+  let expected = [
+    ("bad-splice", "level=\"high\""),
+    ("ghost", "(splice)spooky"),
+    ("ghost", "ectoplasm 3"),
+    ("ouroboros", "(splice)ouroboros"),
+    ("snake", "(splice)snake"),
+  ];
+  assert_eq!(errs.len(), expected.len());
+  for (err, (blueprint, text)) in errs.iter().zip(expected) {
+    assert_eq!(err.blueprint, blueprint);
+    assert!(pointed(err).contains(text), "{:?}", pointed(err));
+  }
+  assert!(matches!(
+    errs[2].kind,
+    ValidationErrorKind::NoAssembler(ref name) if name == "ectoplasm"
+  ));
+  assert!(matches!(
+    errs[3].kind,
+    ValidationErrorKind::Lookup(BlueprintLookupError::InheritanceLoop(..))
+  ));
+
+  // Dry-running finds the components that don't load
+  let errs = fab.validate_with(&());
+  let bad_hp = errs
+    .iter()
+    .filter(|err| matches!(err.kind, ValidationErrorKind::AssemblerError(..)))
+    .collect::<Vec<_>>();
+  assert_eq!(bad_hp.len(), 1);
+  assert_eq!(bad_hp[0].blueprint, "fat-goblin");
+  assert!(pointed(bad_hp[0]).contains("hp \"lots\""));
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Hp(u32);
+
+impl Component for Hp {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("hp")
+  }
+}
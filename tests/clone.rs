@@ -0,0 +1,166 @@
+//! Check that cloning entities deep-copies their components.
+
+use palkia::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[test]
+fn clone() {
+  let mut world = World::new();
+
+  let original = world
+    .spawn()
+    .with(Slime { hp: 20 })
+    .with(Inventory(vec!["sword".to_string(), "gold".to_string()]))
+    .with(CopyCounter(0))
+    .build();
+  let copy = world.clone_entity(original).build();
+
+  assert_ne!(original, copy);
+  assert_eq!(world.len_of(copy), 3);
+  {
+    let (slime, inv) = world.query::<(&Slime, &Inventory)>(copy).unwrap();
+    assert_eq!(slime.hp, 20);
+    assert_eq!(inv.0, ["sword", "gold"]);
+  }
+
+  world.query::<&mut Inventory>(copy).unwrap().0.clear();
+  assert_eq!(world.query::<&Inventory>(original).unwrap().0.len(), 2);
+
+  // Only the one with the fast path goes through Clone
+  assert_eq!(world.query::<&CopyCounter>(original).unwrap().0, 0);
+  assert_eq!(world.query::<&CopyCounter>(copy).unwrap().0, 1);
+}
+
+#[test]
+fn lazy_clone() {
+  let mut world = World::new();
+
+  world.spawn_1(Slime { hp: 20 });
+  for _ in 0..4 {
+    world.dispatch_to_all(MsgSplit);
+    world.finalize();
+  }
+
+  assert_eq!(world.len(), 16);
+  for e in world.entities() {
+    assert_eq!(world.query::<&Slime>(e).unwrap().hp, 20 / 16);
+  }
+}
+
+#[test]
+fn lazy_clone_while_writing() {
+  let mut world = World::new();
+
+  let original = world
+    .spawn()
+    .with(Ooze { hp: 20 })
+    .with(CopyCounter(0))
+    .build();
+  world.dispatch(original, MsgSplit);
+  world.finalize();
+
+  assert_eq!(world.len(), 2);
+  assert_eq!(world.query::<&Ooze>(original).unwrap().hp, 10);
+  let copy = world.entities().find(|e| *e != original).unwrap();
+  // The copy was taken on finalize, after the handler halved it
+  assert_eq!(world.query::<&Ooze>(copy).unwrap().hp, 10);
+  assert_eq!(world.query::<&CopyCounter>(copy).unwrap().0, 1);
+}
+
+#[test]
+fn lazy_clone_missing() {
+  let mut world = World::new();
+
+  // The source is lazily spawned, so it's finished first
+  let original = world.lazy_spawn().with(Slime { hp: 20 }).build();
+  let mut cloner = world.lazy_clone_entity(original);
+  assert!(!cloner.has_component::<Slime>());
+  assert_eq!(cloner.len(), 0);
+  cloner.insert(CopyCounter(0));
+  let copy = cloner.build();
+  world.finalize();
+  assert_eq!(world.len_of(copy), 2);
+  assert_eq!(world.query::<&Slime>(copy).unwrap().hp, 20);
+
+  // The source is gone by the time the copy would be taken
+  let mut cloner = world.lazy_clone_entity(original);
+  assert!(cloner.has_component::<Slime>());
+  assert_eq!(cloner.len(), 1);
+  cloner.insert(CopyCounter(0));
+  let copy = cloner.build();
+  world.despawn(original);
+  world.finalize();
+  assert_eq!(world.len_of(copy), 1);
+  assert!(world.query::<&Slime>(copy).is_none());
+
+  // And it's dead before the builder is even made
+  let copy = world.lazy_clone_entity(original).build();
+  world.finalize();
+  assert_eq!(world.len_of(copy), 0);
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Slime {
+  hp: u32,
+}
+
+impl Component for Slime {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.handle_read(|this, msg: MsgSplit, e, access| {
+      let hp = this.hp / 2;
+      access.lazy_clone_entity(e).with(Slime { hp }).build();
+      access.lazy_edit(e).with(Slime { hp }).build();
+      msg
+    })
+  }
+}
+
+/// Like a slime, but splits while it has its own component borrowed mutably.
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Ooze {
+  hp: u32,
+}
+
+impl Component for Ooze {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.handle_write(|this, msg: MsgSplit, e, access| {
+      this.hp /= 2;
+      access.lazy_clone_entity(e).build();
+      msg
+    })
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component(marker)]
+struct Inventory(Vec<String>);
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct CopyCounter(u32);
+
+impl Clone for CopyCounter {
+  fn clone(&self) -> Self {
+    Self(self.0 + 1)
+  }
+}
+
+impl Component for CopyCounter {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.register_clone()
+  }
+}
+
+#[derive(Message, Clone)]
+struct MsgSplit;
@@ -0,0 +1,138 @@
+//! Check compacting worlds before saving them.
+
+use palkia::{
+  entities::{EntityMap, MapEntities},
+  manually_register_resource,
+  prelude::*,
+  resource::ResourceRegisterer,
+  serde::WorldLayout,
+};
+use serde::{Deserialize, Serialize};
+
+#[test]
+fn compact() {
+  let mut world = World::new();
+  // Churn through a bunch of entities to leave holes and bump generations
+  for _ in 0..3 {
+    let doomed = (0..4).map(|_| world.spawn_empty()).collect::<Vec<_>>();
+    for e in doomed {
+      world.despawn(e);
+    }
+  }
+  let alice = world.spawn_1(Name("alice".to_owned()));
+  let doomed = world.spawn_empty();
+  world.despawn(doomed);
+  let bob = world
+    .spawn()
+    .with(Name("bob".to_owned()))
+    .with(Follows(alice))
+    .build();
+  let ghost = world.spawn().with(Follows(doomed)).build();
+  world.insert_resource(Leader(Some(bob)));
+
+  let mut save = Vec::new();
+  world
+    .serialize_compacted(
+      &mut ron::Serializer::new(&mut save, None).unwrap(),
+      WorldLayout::EntityMap,
+    )
+    .unwrap();
+  let map = world.compaction_map();
+  assert_eq!(map.len(), 3);
+
+  // The world itself is left alone
+  assert_eq!(world.query::<&Name>(alice).unwrap().0, "alice");
+  assert_eq!(world.query::<&Follows>(bob).unwrap().0, alice);
+  assert_eq!(world.read_resource::<Leader>().unwrap().0, Some(bob));
+  assert_ne!(alice.decompose(), (0, 0));
+
+  let mut world2: World = ron::de::from_bytes(&save).unwrap();
+  let mut ids = world2.entities().map(Entity::decompose).collect::<Vec<_>>();
+  ids.sort();
+  assert_eq!(ids, [(0, 1), (1, 1), (2, 1)]);
+
+  let (alice2, bob2, ghost2) = (map.map(alice), map.map(bob), map.map(ghost));
+  assert_eq!(world2.query::<&Name>(alice2).unwrap().0, "alice");
+  assert_eq!(world2.query::<&Name>(bob2).unwrap().0, "bob");
+  assert_eq!(world2.query::<&Follows>(bob2).unwrap().0, alice2);
+  assert_eq!(world2.read_resource::<Leader>().unwrap().0, Some(bob2));
+
+  // Dead entities stay dead, even after spawning more
+  let carol = world2.spawn_empty();
+  let dead = world2.query::<&Follows>(ghost2).unwrap().0;
+  assert_ne!(carol, dead);
+  assert_eq!(world2.liveness(dead), EntityLiveness::Dead);
+}
+
+#[test]
+fn compact_empty() {
+  let mut world = World::new();
+  let doomed = world.spawn_empty();
+  world.despawn(doomed);
+
+  let mut save = Vec::new();
+  world
+    .serialize_compacted(
+      &mut ron::Serializer::new(&mut save, None).unwrap(),
+      WorldLayout::EntityList,
+    )
+    .unwrap();
+  let map = world.compaction_map();
+  assert!(map.is_empty());
+  let mut world2: World = ron::de::from_bytes(&save).unwrap();
+  assert_eq!(world2.len(), 0);
+  let e = world2.spawn_empty();
+  assert_eq!(e.decompose().0, 0);
+  assert_ne!(e, map.map(doomed));
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Name(String);
+
+impl Component for Name {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("name")
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Follows(Entity);
+
+impl MapEntities for Follows {
+  fn map_entities(&mut self, map: &EntityMap) {
+    self.0.map_entities(map);
+  }
+}
+
+impl Component for Follows {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("follows").register_map_entities()
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Leader(Option<Entity>);
+manually_register_resource!(Leader);
+
+impl MapEntities for Leader {
+  fn map_entities(&mut self, map: &EntityMap) {
+    self.0.map_entities(map);
+  }
+}
+
+impl Resource for Leader {
+  fn register(builder: ResourceRegisterer<Self>) -> ResourceRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("leader").register_map_entities()
+  }
+}
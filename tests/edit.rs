@@ -0,0 +1,179 @@
+//! Check that editing existing entities works and runs the right callbacks.
+
+use palkia::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[test]
+fn edit() {
+  let mut world = World::new();
+  world.insert_resource(PoisonTracker(0));
+
+  let goblin = world
+    .spawn()
+    .with(Health(10))
+    .with(Name("Gob".to_string()))
+    .build();
+  assert_eq!(world.get_resource::<PoisonTracker>().unwrap().0, 0);
+
+  let mut editor = world.edit(goblin);
+  assert!(editor.has_component::<Health>());
+  editor.get_component_mut::<Health>().unwrap().0 -= 3;
+  editor.insert(Poisoned);
+  assert!(editor.remove::<Name>());
+  assert!(!editor.remove::<Name>());
+  assert_eq!(editor.len(), 2);
+  editor.build();
+
+  assert_eq!(world.len_of(goblin), 2);
+  assert_eq!(world.query::<&Health>(goblin).unwrap().0, 7);
+  assert!(world.query::<&Name>(goblin).is_none());
+  assert_eq!(world.get_resource::<PoisonTracker>().unwrap().0, 1);
+
+  // Clobbering a component keeps the type there, so no callbacks
+  world.edit(goblin).with(Poisoned).build();
+  assert_eq!(world.get_resource::<PoisonTracker>().unwrap().0, 1);
+
+  let mut editor = world.edit(goblin);
+  editor.remove::<Poisoned>();
+  editor.build();
+  assert_eq!(world.get_resource::<PoisonTracker>().unwrap().0, 0);
+  assert_eq!(world.liveness(goblin), EntityLiveness::Alive);
+}
+
+#[test]
+fn edit_dropped() {
+  let mut world = World::new();
+  world.insert_resource(PoisonTracker(0));
+  let goblin = world
+    .spawn()
+    .with(Health(10))
+    .with(Name("Gob".to_string()))
+    .build();
+
+  // Like bailing out of an edit with `?`
+  let mut editor = world.edit(goblin);
+  editor.insert(Poisoned);
+  editor.remove::<Name>();
+  drop(editor);
+
+  assert_eq!(world.len_of(goblin), 2);
+  assert_eq!(world.query::<&Name>(goblin).unwrap().0, "Gob");
+  assert!(world.query::<&Poisoned>(goblin).is_none());
+  assert_eq!(world.get_resource::<PoisonTracker>().unwrap().0, 0);
+}
+
+#[test]
+fn lazy_edit() {
+  let mut world = World::new();
+  world.insert_resource(PoisonTracker(0));
+
+  let goblins = (0..10)
+    .map(|_| world.spawn().with(Health(10)).build())
+    .collect::<Vec<_>>();
+
+  world.dispatch_to_all(MsgPoisonCloud);
+  for &goblin in goblins.iter() {
+    assert!(world.query::<&Poisoned>(goblin).is_none());
+  }
+  world.finalize();
+
+  assert_eq!(world.get_resource::<PoisonTracker>().unwrap().0, 10);
+  for &goblin in goblins.iter() {
+    world.query::<&Poisoned>(goblin).unwrap();
+    assert_eq!(world.query::<&Health>(goblin).unwrap().0, 5);
+  }
+
+  let world_ref = &world;
+  for &goblin in goblins.iter() {
+    let mut editor = world_ref.lazy_edit(goblin);
+    assert!(editor.has_component::<Poisoned>());
+    assert!(editor.remove::<Poisoned>());
+    assert!(!editor.has_component::<Poisoned>());
+    assert_eq!(editor.len(), 1);
+    editor.build();
+  }
+  world.finalize();
+  assert_eq!(world.get_resource::<PoisonTracker>().unwrap().0, 0);
+}
+
+#[test]
+fn lazy_edit_dead() {
+  let mut world = World::new();
+  let goblin = world.spawn_1(Health(10));
+
+  world.lazy_edit(goblin).with(Poisoned).build();
+  world.despawn(goblin);
+  world.finalize();
+
+  assert_eq!(world.liveness(goblin), EntityLiveness::Dead);
+}
+
+#[test]
+fn lazy_edit_lazy_spawned() {
+  let mut world = World::new();
+  world.insert_resource(PoisonTracker(0));
+
+  let goblin = world.lazy_spawn().with(Health(10)).build();
+  let mut editor = world.lazy_edit(goblin);
+  // It's not finished yet, so there's nothing live to see
+  assert!(!editor.has_component::<Health>());
+  assert!(!editor.remove::<Health>());
+  assert!(editor.insert(Poisoned).is_none());
+  assert!(editor.get_component::<Poisoned>().is_some());
+  assert_eq!(editor.len(), 1);
+  editor.build();
+  world.finalize();
+
+  assert_eq!(world.len_of(goblin), 2);
+  assert_eq!(world.query::<&Health>(goblin).unwrap().0, 10);
+  assert_eq!(world.get_resource::<PoisonTracker>().unwrap().0, 1);
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Health(u32);
+
+impl Component for Health {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.handle_read(|this, msg: MsgPoisonCloud, e, access| {
+      access
+        .lazy_edit(e)
+        .with(Health(this.0 / 2))
+        .with(Poisoned)
+        .build();
+      msg
+    })
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component(marker)]
+struct Name(String);
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Poisoned;
+
+impl Component for Poisoned {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder
+      .register_create_callback(|_, _, access| {
+        access.write_resource::<PoisonTracker>().unwrap().0 += 1;
+      })
+      .register_remove_callback(|_, _, access| {
+        access.write_resource::<PoisonTracker>().unwrap().0 -= 1;
+      })
+  }
+}
+
+#[derive(Resource, Serialize, Deserialize)]
+struct PoisonTracker(u32);
+
+#[derive(Message, Clone)]
+struct MsgPoisonCloud;
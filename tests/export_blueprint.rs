@@ -0,0 +1,219 @@
+//! Check exporting live entities back out as blueprints.
+
+use palkia::{
+  fabricator::{EntityFabricator, ExportError},
+  prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+const BLUEPRINTS: &str = r#"
+goblin {
+  name "goblin"
+  position 0 0
+  has-hp start-hp=10
+}
+
+wanderer {
+  name "wanderer"
+  (choose)spot {
+    position 0 0
+    position 5 5
+  }
+}
+
+sure-thing {
+  name "sure thing"
+  (maybe)legendary chance=1.0 { legendary; }
+}
+
+leveled level=1 title=null {
+  name "{title}"
+  has-hp start-hp="{level * 10}"
+}
+"#;
+
+fn fabricator() -> EntityFabricator<()> {
+  let mut fab = EntityFabricator::new();
+  fab.register_serde::<Name>("name");
+  fab.register_serde::<Position>("position");
+  fab.register_serde::<HasHp>("has-hp");
+  fab.register_serde::<Legendary>("legendary");
+  fab.load_str(BLUEPRINTS, "blueprints.kdl").unwrap();
+  fab
+}
+
+#[test]
+fn export_diff() {
+  let mut fab = fabricator();
+  let mut world = World::new();
+  let boss = fab.instantiate("goblin", world.spawn(), &()).unwrap();
+  world
+    .edit(boss)
+    .with(HasHp { start_hp: 50 })
+    .with(Legendary)
+    .build();
+
+  let mut node = fab.export_blueprint(&world, boss, Some("goblin")).unwrap();
+  node.set_name("goblin-boss");
+
+  let kids = node.children().unwrap().nodes();
+  let names = kids
+    .iter()
+    .map(|kid| kid.name().value())
+    .collect::<Vec<_>>();
+  assert_eq!(names, ["goblin", "has-hp", "legendary"]);
+  assert_eq!(kids[0].ty().unwrap().value(), "splice");
+
+  fab.load_str(&node.to_string(), "exported.kdl").unwrap();
+  let copy = fab.instantiate("goblin-boss", world.spawn(), &()).unwrap();
+  assert_eq!(world.query::<&Name>(copy).unwrap().0, "goblin");
+  assert_eq!(*world.query::<&Position>(copy).unwrap(), Position(0, 0));
+  assert_eq!(world.query::<&HasHp>(copy).unwrap().start_hp, 50);
+  assert!(world.query::<&Legendary>(copy).is_some());
+}
+
+#[test]
+fn export_removed() {
+  let mut fab = fabricator();
+  let mut world = World::new();
+  let ghost = fab.instantiate("goblin", world.spawn(), &()).unwrap();
+  {
+    let mut edit = world.edit(ghost);
+    edit.remove::<Position>();
+    edit.remove::<HasHp>();
+    edit.build();
+  }
+
+  let mut node = fab.export_blueprint(&world, ghost, Some("goblin")).unwrap();
+  node.set_name("goblin-ghost");
+
+  let kids = node.children().unwrap().nodes();
+  let names = kids
+    .iter()
+    .map(|kid| kid.name().value())
+    .collect::<Vec<_>>();
+  assert_eq!(names, ["goblin", "position", "has-hp"]);
+  assert_eq!(kids[1].ty().unwrap().value(), "remove");
+  assert_eq!(kids[2].ty().unwrap().value(), "remove");
+
+  fab.load_str(&node.to_string(), "exported.kdl").unwrap();
+  let copy = fab.instantiate("goblin-ghost", world.spawn(), &()).unwrap();
+  assert_eq!(world.query::<&Name>(copy).unwrap().0, "goblin");
+  assert!(world.query::<&Position>(copy).is_none());
+  assert!(world.query::<&HasHp>(copy).is_none());
+}
+
+#[test]
+fn export_unchanged() {
+  let fab = fabricator();
+  let mut world = World::new();
+  let goblin = fab.instantiate("goblin", world.spawn(), &()).unwrap();
+
+  let node = fab
+    .export_blueprint(&world, goblin, Some("goblin"))
+    .unwrap();
+  assert_eq!(node.children().unwrap().nodes().len(), 1);
+}
+
+#[test]
+fn export_without_base() {
+  let mut fab = fabricator();
+  let mut world = World::new();
+  let e = world
+    .spawn()
+    .with(Name("bob".to_owned()))
+    .with(Position(3, 4))
+    .build();
+
+  let mut node = fab.export_blueprint(&world, e, None).unwrap();
+  assert_eq!(node.children().unwrap().nodes().len(), 2);
+  node.set_name("bob");
+
+  fab.load_str(&node.to_string(), "exported.kdl").unwrap();
+  let copy = fab.instantiate("bob", world.spawn(), &()).unwrap();
+  assert_eq!(world.query::<&Name>(copy).unwrap().0, "bob");
+  assert_eq!(*world.query::<&Position>(copy).unwrap(), Position(3, 4));
+}
+
+#[test]
+fn export_from_unclear_bases() {
+  let fab = fabricator();
+  let mut world = World::new();
+  let e = world
+    .spawn()
+    .with(Name("wanderer".to_owned()))
+    .with(Position(5, 5))
+    .build();
+
+  let err = fab
+    .export_blueprint(&world, e, Some("wanderer"))
+    .unwrap_err();
+  assert!(
+    matches!(err, ExportError::RandomBase(ref name) if name == "wanderer")
+  );
+  let err = fab
+    .export_blueprint(&world, e, Some("leveled"))
+    .unwrap_err();
+  assert!(
+    matches!(err, ExportError::RequiredParams(_, ref params) if params == &["title"])
+  );
+
+  // But ones that can only go one way are fine
+  let e = fab.instantiate("sure-thing", world.spawn(), &()).unwrap();
+  let node = fab.export_blueprint(&world, e, Some("sure-thing")).unwrap();
+  assert_eq!(node.children().unwrap().nodes().len(), 1);
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Name(String);
+
+impl Component for Name {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("name")
+  }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[register_component]
+struct Position(i32, i32);
+
+impl Component for Position {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("position")
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct HasHp {
+  start_hp: u32,
+}
+
+impl Component for HasHp {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("has-hp")
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Legendary;
+
+impl Component for Legendary {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("legendary")
+  }
+}
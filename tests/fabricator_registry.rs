@@ -0,0 +1,130 @@
+//! Check that the fabricator can load components by their friendly names
+//! without registering factories for them.
+
+use kdl::KdlNode;
+use palkia::{
+  builder::EntityBuilder,
+  fabricator::{factory::ComponentFactory, InstantiationError},
+  prelude::*,
+};
+use serde::{Deserialize, Serialize};
+
+const BLUEPRINTS: &str = r#"
+goblin {
+  name "goblin"
+  position 1 2
+  has-hp start-hp=10
+  legendary
+}
+
+scarecrow {
+  name "scarecrow"
+  has-hp start-hp=1
+}
+
+ghost {
+  ectoplasm 3
+}
+"#;
+
+#[test]
+fn fallback_to_registry() {
+  let mut fab = EntityFabricator::<()>::new();
+  fab.load_str(BLUEPRINTS, "blueprints.kdl").unwrap();
+
+  let mut world = World::new();
+  let e = fab.instantiate("goblin", world.spawn(), &()).unwrap();
+  assert_eq!(world.query::<&Name>(e).unwrap().0, "goblin");
+  assert_eq!(*world.query::<&Position>(e).unwrap(), Position(1, 2));
+  assert_eq!(world.query::<&HasHp>(e).unwrap().start_hp, 10);
+  assert!(world.query::<&Legendary>(e).is_some());
+
+  let err = fab.instantiate("ghost", world.spawn(), &()).unwrap_err();
+  assert!(
+    matches!(err, InstantiationError::NoAssembler(ref name, _) if name == "ectoplasm")
+  );
+}
+
+#[test]
+fn factories_win() {
+  let mut fab = EntityFabricator::<u32>::new();
+  fab.register("has-hp", ScaledHp);
+  fab.load_str(BLUEPRINTS, "blueprints.kdl").unwrap();
+
+  let mut world = World::new();
+  let e = fab.instantiate("scarecrow", world.spawn(), &5).unwrap();
+  assert_eq!(world.query::<&Name>(e).unwrap().0, "scarecrow");
+  assert_eq!(world.query::<&HasHp>(e).unwrap().start_hp, 5);
+}
+
+/// Multiplies the HP by the difficulty in the context.
+struct ScaledHp;
+
+impl ComponentFactory<u32> for ScaledHp {
+  fn assemble<'a, 'w>(
+    &self,
+    mut builder: EntityBuilder<'a, 'w>,
+    node: &KdlNode,
+    ctx: &u32,
+  ) -> eyre::Result<EntityBuilder<'a, 'w>> {
+    let hp: HasHp = knurdy::deserialize_node(node)?;
+    builder.insert(HasHp {
+      start_hp: hp.start_hp * ctx,
+    });
+    Ok(builder)
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Name(String);
+
+impl Component for Name {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("name")
+  }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[register_component]
+struct Position(i32, i32);
+
+impl Component for Position {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("position")
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct HasHp {
+  start_hp: u32,
+}
+
+impl Component for HasHp {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("has-hp")
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Legendary;
+
+impl Component for Legendary {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("legendary")
+  }
+}
@@ -0,0 +1,296 @@
+//! Check saving worlds and components as KDL.
+
+use std::collections::BTreeMap;
+
+use kdl::KdlDocument;
+use palkia::{
+  fabricator::EntityFabricator,
+  manually_register_resource,
+  prelude::*,
+  resource::ResourceRegisterer,
+  serde::{kdl::component_from_kdl, ComponentSerWrapper, LoadOptions, Value},
+};
+use serde::{Deserialize, Serialize};
+
+fn make_world() -> (World, Entity, Entity) {
+  let mut world = World::new();
+  world.insert_resource(Turn(12));
+  let e1 = world
+    .spawn()
+    .with(Name("alice".to_owned()))
+    .with(HasHp {
+      start_hp: 10,
+      resistances: vec![Element::Fire, Element::Other("gloom".to_owned())],
+    })
+    .with(Position(1, -2))
+    .with(Player)
+    .build();
+  let doomed = world.spawn_1(Name("carol".to_owned()));
+  let e2 = world.spawn_1(Name("bob".to_owned()));
+  world.despawn(doomed);
+  (world, e1, e2)
+}
+
+fn check(world: &World, e1: Entity, e2: Entity) {
+  assert_eq!(world.len(), 2);
+  assert_eq!(world.query::<&Name>(e1).unwrap().0, "alice");
+  assert_eq!(
+    *world.query::<&HasHp>(e1).unwrap(),
+    HasHp {
+      start_hp: 10,
+      resistances: vec![Element::Fire, Element::Other("gloom".to_owned())],
+    }
+  );
+  assert_eq!(*world.query::<&Position>(e1).unwrap(), Position(1, -2));
+  assert!(world.query::<&Player>(e1).is_some());
+  assert_eq!(world.query::<&Name>(e2).unwrap().0, "bob");
+  assert_eq!(world.read_resource::<Turn>().unwrap().0, 12);
+}
+
+#[test]
+fn world_roundtrip() {
+  let (mut world, e1, e2) = make_world();
+
+  let text = world.to_kdl().unwrap().to_string();
+  let doc: KdlDocument = text.parse().unwrap();
+  let names = doc
+    .nodes()
+    .iter()
+    .map(|node| node.name().value())
+    .collect::<Vec<_>>();
+  assert_eq!(
+    names,
+    ["version", "layout", "allocator", "entities", "resources"]
+  );
+  let entities = doc.get("entities").unwrap().children().unwrap();
+  assert_eq!(entities.nodes().len(), 2);
+
+  let mut world2 = World::from_kdl(&doc).unwrap();
+  check(&world2, e1, e2);
+
+  // The freed slot gets reused
+  let (idx, _) = world.spawn().build().decompose();
+  let (idx2, _) = world2.spawn().build().decompose();
+  assert_eq!(idx, idx2);
+}
+
+#[test]
+fn component_looks_like_blueprint() {
+  let hp = HasHp {
+    start_hp: 10,
+    resistances: vec![Element::Fire, Element::Other("gloom".to_owned())],
+  };
+  let node = ComponentSerWrapper::new(&hp).to_kdl().unwrap();
+  assert_eq!(node.name().value(), "has-hp");
+  assert_eq!(
+    node.get("start-hp").map(|entry| entry.value().as_i64()),
+    Some(Some(10))
+  );
+
+  let back = component_from_kdl(&node).unwrap();
+  let back = back.downcast::<HasHp>().ok().unwrap();
+  assert_eq!(*back, hp);
+
+  // And the fabricator can read it too
+  let mut fab = EntityFabricator::<()>::new();
+  fab.register_serde::<HasHp>("has-hp");
+  fab
+    .load_str(&format!("saved-goblin {{\n{}\n}}", node), "saved.kdl")
+    .unwrap();
+  let mut world = World::new();
+  let e = fab.instantiate("saved-goblin", world.spawn(), &()).unwrap();
+  assert_eq!(*world.query::<&HasHp>(e).unwrap(), hp);
+}
+
+#[test]
+fn hand_written_save() {
+  let doc: KdlDocument = r#"
+    version 1
+    allocator {
+      - 0 null
+    }
+    resources {
+      turn 3
+    }
+    entities {
+      entity 0 0 {
+        name "dave"
+        position 4 5
+      }
+    }
+  "#
+  .parse()
+  .unwrap();
+
+  let (world, report) = World::from_kdl_with(&doc, Default::default()).unwrap();
+  assert!(report.is_empty());
+  assert_eq!(world.len(), 1);
+  let e = world.entities().next().unwrap();
+  assert_eq!(world.query::<&Name>(e).unwrap().0, "dave");
+  assert_eq!(*world.query::<&Position>(e).unwrap(), Position(4, 5));
+  assert_eq!(world.read_resource::<Turn>().unwrap().0, 3);
+}
+
+#[test]
+fn hand_written_old_save() {
+  // The version is read first even when it's written last
+  let doc: KdlDocument = r#"
+    allocator {
+      - 0 null
+    }
+    entities {
+      entity 0 0 {
+        position x=4 y=5
+        has-hp start-hp=10 {
+          resistances "fire"
+        }
+      }
+    }
+    resources
+    version 0
+  "#
+  .parse()
+  .unwrap();
+
+  let world = World::from_kdl(&doc).unwrap();
+  let e = world.entities().next().unwrap();
+  assert_eq!(*world.query::<&Position>(e).unwrap(), Position(4, 5));
+  assert_eq!(
+    *world.query::<&HasHp>(e).unwrap(),
+    HasHp {
+      start_hp: 10,
+      resistances: vec![Element::Fire],
+    }
+  );
+}
+
+#[test]
+fn map_keys_roundtrip() {
+  let cravings = Cravings {
+    by_food: BTreeMap::from([
+      ("ice_cream".to_owned(), 3),
+      ("hot-dog".to_owned(), 5),
+    ]),
+  };
+  let node = ComponentSerWrapper::new(&cravings).to_kdl().unwrap();
+  let by_food = node.children().unwrap().get("by-food").unwrap();
+  assert!(by_food.get("ice_cream").is_some());
+  assert!(by_food.get("hot-dog").is_some());
+  let back = component_from_kdl(&node).unwrap();
+  assert_eq!(*back.downcast::<Cravings>().ok().unwrap(), cravings);
+
+  // Lenient loads read everything ahead of time, and still get it right
+  let mut world = World::new();
+  let e = world.spawn_1(cravings.clone());
+  let doc = world.to_kdl().unwrap();
+  for lenient in [false, true] {
+    let options = LoadOptions {
+      lenient,
+      ..Default::default()
+    };
+    let (world2, report) = World::from_kdl_with(&doc, options).unwrap();
+    assert!(report.is_empty());
+    assert_eq!(*world2.query::<&Cravings>(e).unwrap(), cravings);
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Name(String);
+
+impl Component for Name {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("name")
+  }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[register_component]
+struct HasHp {
+  start_hp: u32,
+  #[serde(default)]
+  resistances: Vec<Element>,
+}
+
+impl Component for HasHp {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("has-hp")
+  }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Element {
+  Fire,
+  Other(String),
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[register_component]
+struct Position(i32, i32);
+
+impl Component for Position {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder
+      .set_friendly_name("position")
+      .migrate_from(0, |old| {
+        #[derive(Deserialize)]
+        struct Old {
+          x: i32,
+          y: i32,
+        }
+        let old: Old = old.deserialize_into()?;
+        Ok(Value::Seq(vec![Value::I32(old.x), Value::I32(old.y)]))
+      })
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[register_component]
+struct Cravings {
+  by_food: BTreeMap<String, u32>,
+}
+
+impl Component for Cravings {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("cravings")
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Player;
+
+impl Component for Player {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("player")
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Turn(u32);
+manually_register_resource!(Turn);
+
+impl Resource for Turn {
+  fn register(builder: ResourceRegisterer<Self>) -> ResourceRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("turn")
+  }
+}
@@ -0,0 +1,126 @@
+//! Check saving worlds with different entity layouts.
+
+use palkia::{prelude::*, serde::WorldLayout};
+use serde::{Deserialize, Serialize};
+
+fn make_world() -> (World, Entity, Entity) {
+  let mut world = World::new();
+  world.insert_resource(Turn(12));
+  let e1 = world
+    .spawn()
+    .with(Name("alice".to_owned()))
+    .with(Hp(10))
+    .build();
+  let doomed = world.spawn_1(Hp(0));
+  let e2 = world.spawn_1(Name("bob".to_owned()));
+  world.despawn(doomed);
+  (world, e1, e2)
+}
+
+fn check(world: &World, e1: Entity, e2: Entity) {
+  assert_eq!(world.len(), 2);
+  assert_eq!(world.query::<&Name>(e1).unwrap().0, "alice");
+  assert_eq!(world.query::<&Hp>(e1).unwrap().0, 10);
+  assert_eq!(world.query::<&Name>(e2).unwrap().0, "bob");
+  assert_eq!(world.read_resource::<Turn>().unwrap().0, 12);
+}
+
+fn to_json(world: &World, layout: WorldLayout) -> serde_json::Result<String> {
+  let mut out = Vec::new();
+  world.serialize_with(&mut serde_json::Serializer::new(&mut out), layout)?;
+  Ok(String::from_utf8(out).unwrap())
+}
+
+fn to_bincode(world: &World, layout: WorldLayout) -> Vec<u8> {
+  let mut out = Vec::new();
+  world
+    .serialize_with(
+      &mut bincode::Serializer::new(&mut out, bincode::DefaultOptions::new()),
+      layout,
+    )
+    .unwrap();
+  out
+}
+
+#[test]
+fn json() {
+  let (world, e1, e2) = make_world();
+
+  // Entity keys don't work in JSON
+  assert!(serde_json::to_string(&world).is_err());
+  assert!(to_json(&world, WorldLayout::EntityMap).is_err());
+
+  let json = to_json(&world, WorldLayout::EntityList).unwrap();
+  let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+  let entities = value["entities"].as_array().unwrap();
+  assert_eq!(entities.len(), 2);
+  let alice = entities
+    .iter()
+    .find(|it| it["id"] == serde_json::json!([0, 0]))
+    .unwrap();
+  assert_eq!(alice["components"][0]["name"], "alice");
+
+  let world2: World = serde_json::from_str(&json).unwrap();
+  check(&world2, e1, e2);
+}
+
+#[test]
+fn ron_both_layouts() {
+  let (world, e1, e2) = make_world();
+
+  for layout in [WorldLayout::EntityMap, WorldLayout::EntityList] {
+    let mut out = Vec::new();
+    world
+      .serialize_with(
+        &mut ron::Serializer::new(&mut out, None).unwrap(),
+        layout,
+      )
+      .unwrap();
+    let world2: World = ron::de::from_bytes(&out).unwrap();
+    check(&world2, e1, e2);
+  }
+}
+
+#[test]
+fn bincode_both_layouts() {
+  let (world, e1, e2) = make_world();
+
+  for layout in [WorldLayout::EntityMap, WorldLayout::EntityList] {
+    let bin = to_bincode(&world, layout);
+    let world2 = World::deserialize(&mut bincode::Deserializer::from_slice(
+      &bin,
+      bincode::DefaultOptions::new(),
+    ))
+    .unwrap();
+    check(&world2, e1, e2);
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Name(String);
+
+impl Component for Name {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("name")
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Hp(i32);
+
+impl Component for Hp {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("hp")
+  }
+}
+
+#[derive(Serialize, Deserialize, Resource)]
+struct Turn(u32);
@@ -0,0 +1,227 @@
+//! Check the errors from loading broken saves, and loading them leniently.
+
+use palkia::{
+  manually_register_resource,
+  prelude::*,
+  resource::ResourceRegisterer,
+  serde::{LoadOptions, LoadReport, WorldLayout},
+};
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::json;
+
+/// Save a world as JSON, then break the second entity's stats.
+fn broken_save() -> (serde_json::Value, Entity, Entity) {
+  let mut world = World::new();
+  world.insert_resource(Turn(12));
+  let e1 = world
+    .spawn()
+    .with(Name("alice".to_owned()))
+    .with(Stats {
+      hp: 10,
+      inventory: vec![1, 2],
+    })
+    .build();
+  let e2 = world
+    .spawn()
+    .with(Name("bob".to_owned()))
+    .with(Stats {
+      hp: 20,
+      inventory: vec![3, 4],
+    })
+    .build();
+
+  let mut out = Vec::new();
+  world
+    .serialize_with(
+      &mut serde_json::Serializer::new(&mut out),
+      WorldLayout::EntityList,
+    )
+    .unwrap();
+  let mut save: serde_json::Value = serde_json::from_slice(&out).unwrap();
+
+  let (idx, gen) = e2.decompose();
+  let bob = save["entities"]
+    .as_array_mut()
+    .unwrap()
+    .iter_mut()
+    .find(|it| it["id"] == json!([idx, gen]))
+    .unwrap();
+  let stats = bob["components"]
+    .as_array_mut()
+    .unwrap()
+    .iter_mut()
+    .find_map(|it| it.get_mut("stats"))
+    .unwrap();
+  stats["inventory"][1] = json!("a sword");
+
+  (save, e1, e2)
+}
+
+fn load(
+  save: &serde_json::Value,
+  lenient: bool,
+) -> Result<(World, LoadReport), serde_json::Error> {
+  let options = LoadOptions {
+    lenient,
+    ..Default::default()
+  };
+  World::deserialize_with(save, options)
+}
+
+#[test]
+fn error_says_where() {
+  let (save, _, e2) = broken_save();
+  let err = load(&save, false).err().unwrap().to_string();
+  let expected = format!(
+    "entity {:x}, component `stats`, field `inventory[1]`: invalid type",
+    e2
+  );
+  assert!(err.contains(&expected), "{}", err);
+}
+
+#[test]
+fn lenient_component() {
+  let (save, e1, e2) = broken_save();
+  let (world, report) = load(&save, true).unwrap();
+
+  assert_eq!(world.query::<&Stats>(e1).unwrap().hp, 10);
+  assert_eq!(world.query::<&Name>(e2).unwrap().0, "bob");
+  assert!(world.query::<&Stats>(e2).is_none());
+  assert_eq!(world.read_resource::<Turn>().unwrap().0, 12);
+
+  assert_eq!(report.broken.len(), 1);
+  let broken = &report.broken[0];
+  assert_eq!(broken.entity, Some(e2));
+  assert_eq!(broken.component.as_deref(), Some("stats"));
+  assert_eq!(broken.field.as_deref(), Some("inventory[1]"));
+}
+
+#[test]
+fn lenient_entity_and_resource() {
+  let (mut save, e1, _) = broken_save();
+  save["entities"]
+    .as_array_mut()
+    .unwrap()
+    .push(json!({ "id": [5, 0], "components": "not a list" }));
+  save["resources"]["turn"] = json!("twelve");
+
+  let (world, report) = load(&save, true).unwrap();
+  assert_eq!(world.len(), 2);
+  assert_eq!(world.query::<&Name>(e1).unwrap().0, "alice");
+  assert!(world.read_resource::<Turn>().is_err());
+
+  assert_eq!(report.broken.len(), 3);
+  let turn = report
+    .broken
+    .iter()
+    .find(|it| it.resource.as_deref() == Some("turn"))
+    .unwrap();
+  assert_eq!(turn.entity, None);
+  assert!(turn
+    .to_string()
+    .starts_with("resource `turn`: invalid type"));
+  assert!(report.broken.iter().any(|it| it.entity
+    == Some(Entity::recompose(5, 0))
+    && it.component.is_none()));
+}
+
+#[test]
+fn nested_load() {
+  let (mut save, e1, _) = broken_save();
+  let alice = save["entities"]
+    .as_array_mut()
+    .unwrap()
+    .iter_mut()
+    .find(|it| it["id"] == json!(e1.decompose()))
+    .unwrap();
+  alice["components"]
+    .as_array_mut()
+    .unwrap()
+    .insert(0, json!({ "pocket": Pocket::EMPTY }));
+
+  // Loading the pocket world doesn't make the outer load forget it's lenient
+  let (world, report) = load(&save, true).unwrap();
+  assert!(world.query::<&Pocket>(e1).is_some());
+  assert_eq!(report.broken.len(), 1);
+  assert_eq!(report.broken[0].component.as_deref(), Some("stats"));
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Name(String);
+
+impl Component for Name {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("name")
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Stats {
+  hp: u32,
+  inventory: Vec<u32>,
+}
+
+impl Component for Stats {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("stats")
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Turn(u32);
+manually_register_resource!(Turn);
+
+impl Resource for Turn {
+  fn register(builder: ResourceRegisterer<Self>) -> ResourceRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("turn")
+  }
+}
+
+/// Holds a whole saved world, which is loaded along with it.
+#[register_component]
+struct Pocket;
+
+impl Pocket {
+  const EMPTY: &'static str =
+    r#"{"version":0,"allocator":[],"entities":{},"resources":{}}"#;
+}
+
+impl Serialize for Pocket {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    Self::EMPTY.serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for Pocket {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    let save = String::deserialize(deserializer)?;
+    serde_json::from_str::<World>(&save).map_err(D::Error::custom)?;
+    Ok(Pocket)
+  }
+}
+
+impl Component for Pocket {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("pocket")
+  }
+}
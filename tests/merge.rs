@@ -0,0 +1,126 @@
+//! Check merging one world into another.
+
+use palkia::{
+  entities::{EntityMap, MapEntities},
+  manually_register_resource,
+  prelude::*,
+  resource::ResourceRegisterer,
+  world::ResourceMergeMode,
+};
+use serde::{Deserialize, Serialize};
+
+fn level(name: &str, size: usize) -> World {
+  let mut world = World::new();
+  world.insert_resource(LevelName(name.to_string()));
+  world.insert_resource(Population(0));
+
+  let mut prev = None;
+  for _ in 0..size {
+    prev = Some(world.spawn_1(Follower(prev)));
+  }
+  world
+}
+
+#[test]
+fn merge_entities() {
+  let mut world = level("town", 10);
+  let dungeon = level("dungeon", 5);
+  let dungeon_entities = dungeon.iter().collect::<Vec<_>>();
+  assert_eq!(world.get_resource::<Population>().unwrap().0, 10);
+
+  let map = world.merge_from(dungeon, ResourceMergeMode::Ignore);
+  assert_eq!(map.len(), 5);
+  assert_eq!(world.len(), 15);
+  assert_eq!(world.get_resource::<Population>().unwrap().0, 15);
+  assert_eq!(world.get_resource::<LevelName>().unwrap().0, "town");
+
+  for old in dungeon_entities {
+    let new = map.get(old).unwrap();
+    let follower = world.query::<&Follower>(new).unwrap();
+    if let Some(leader) = follower.0 {
+      assert_eq!(world.liveness(leader), EntityLiveness::Alive);
+      assert!(map.iter().any(|(_, new)| new == leader));
+    }
+  }
+}
+
+#[test]
+fn merge_resources() {
+  let mut world = level("town", 1);
+  let mut dungeon = level("dungeon", 1);
+  dungeon.insert_resource(Gold(100));
+
+  world.merge_from(dungeon, ResourceMergeMode::KeepOld);
+  assert_eq!(world.get_resource::<LevelName>().unwrap().0, "town");
+  assert_eq!(world.get_resource::<Gold>().unwrap().0, 100);
+
+  let dungeon = level("dungeon", 1);
+  world.merge_from(dungeon, ResourceMergeMode::Clobber);
+  assert_eq!(world.get_resource::<LevelName>().unwrap().0, "dungeon");
+  // The merged population counter is clobbered, then counts its new entity
+  assert_eq!(world.get_resource::<Population>().unwrap().0, 2);
+}
+
+#[test]
+fn merge_resource_entities() {
+  let mut world = level("town", 3);
+  let mut dungeon = level("dungeon", 3);
+  let boss = dungeon.entities().last().unwrap();
+  dungeon.insert_resource(Boss(Some(boss)));
+
+  let map = world.merge_from(dungeon, ResourceMergeMode::KeepOld);
+  let new_boss = world.get_resource::<Boss>().unwrap().0.unwrap();
+  assert_eq!(new_boss, map.map(boss));
+  assert_eq!(world.liveness(new_boss), EntityLiveness::Alive);
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Follower(Option<Entity>);
+
+impl MapEntities for Follower {
+  fn map_entities(&mut self, map: &EntityMap) {
+    self.0.map_entities(map);
+  }
+}
+
+impl Component for Follower {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder
+      .register_map_entities()
+      .register_create_callback(|_, _, access| {
+        access.write_resource::<Population>().unwrap().0 += 1;
+      })
+  }
+}
+
+#[derive(Resource, Serialize, Deserialize)]
+struct LevelName(String);
+
+#[derive(Resource, Serialize, Deserialize)]
+struct Population(u32);
+
+#[derive(Resource, Serialize, Deserialize)]
+struct Gold(u32);
+
+#[derive(Serialize, Deserialize)]
+struct Boss(Option<Entity>);
+manually_register_resource!(Boss);
+
+impl MapEntities for Boss {
+  fn map_entities(&mut self, map: &EntityMap) {
+    self.0.map_entities(map);
+  }
+}
+
+impl Resource for Boss {
+  fn register(builder: ResourceRegisterer<Self>) -> ResourceRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.register_map_entities()
+  }
+}
@@ -0,0 +1,279 @@
+//! Check that old saves get migrated.
+
+use std::collections::BTreeMap;
+
+use bincode::Options;
+use palkia::{
+  prelude::*,
+  serde::{schema_version, LoadOptions, Value, WorldLayout},
+};
+use serde::{Deserialize, Serialize};
+
+#[test]
+fn migrate() {
+  // Migrations from 0, 1, and 2 are registered
+  assert_eq!(schema_version(), 3);
+
+  let mut world1 = World::new();
+  let e1 = world1.spawn_1(LegacyPosition { x: 3, y: -4 });
+  let e2 = world1
+    .spawn()
+    .with(LegacyPosition { x: 10, y: 20 })
+    .with(Health(5))
+    .build();
+
+  // Pretend the legacy component was saved by an old version of the game.
+  // It goes through both position migrations.
+  let current = ron::to_string(&world1).unwrap();
+  assert!(current.starts_with("(version:3,"));
+  let old = current
+    .replacen("(version:3,", "(version:0,", 1)
+    .replace("\"legacy-position\"", "\"position\"");
+
+  let world2: World = ron::from_str(&old).unwrap();
+  assert_eq!(
+    *world2.query::<&Position>(e1).unwrap(),
+    Position::new(3, -4)
+  );
+  assert_eq!(
+    *world2.query::<&Position>(e2).unwrap(),
+    Position::new(10, 20)
+  );
+  assert_eq!(world2.query::<&Health>(e2).unwrap().0, 5);
+
+  // Saves at the current version are loaded as-is
+  let world3: World = ron::from_str(&ron::to_string(&world2).unwrap()).unwrap();
+  assert_eq!(
+    *world3.query::<&Position>(e1).unwrap(),
+    Position::new(3, -4)
+  );
+}
+
+#[test]
+fn migrate_out_of_order() {
+  // JSON objects have their keys sorted, so the entities come before the
+  // version
+  let save = |world: &World| {
+    let mut out = Vec::new();
+    world
+      .serialize_with(
+        &mut serde_json::Serializer::new(&mut out),
+        WorldLayout::EntityList,
+      )
+      .unwrap();
+    serde_json::from_slice::<serde_json::Value>(&out).unwrap()
+  };
+
+  let mut world1 = World::new();
+  let e = world1.spawn_1(Position::new(1, 2));
+  let current = save(&world1).to_string();
+  assert!(current.find("\"entities\"") < current.find("\"version\""));
+  let world2: World = serde_json::from_str(&current).unwrap();
+  assert_eq!(*world2.query::<&Position>(e).unwrap(), Position::new(1, 2));
+
+  let mut world3 = World::new();
+  let e = world3.spawn_1(LegacyPosition { x: 3, y: -4 });
+  let mut old = save(&world3);
+  old["version"] = 0.into();
+  let old = old
+    .to_string()
+    .replace("\"legacy-position\"", "\"position\"");
+  let world4: World = serde_json::from_str(&old).unwrap();
+  assert_eq!(*world4.query::<&Position>(e).unwrap(), Position::new(3, -4));
+}
+
+#[test]
+fn migrate_import() {
+  let mut world1 = World::new();
+  let e = world1
+    .spawn()
+    .with(LegacyPosition { x: 3, y: -4 })
+    .with(Health(5))
+    .build();
+
+  // Like a prefab exported by an old version of the game
+  let current = ron::to_string(&world1.export_entities([e])).unwrap();
+  assert!(current.starts_with("(version:3,"));
+  let old = current
+    .replacen("(version:3,", "(version:0,", 1)
+    .replace("\"legacy-position\"", "\"position\"");
+
+  let mut world2 = World::new();
+  let map = world2
+    .import_entities(&mut ron::Deserializer::from_str(&old).unwrap())
+    .unwrap();
+  let e2 = map.map(e);
+  assert_eq!(
+    *world2.query::<&Position>(e2).unwrap(),
+    Position::new(3, -4)
+  );
+  assert_eq!(world2.query::<&Health>(e2).unwrap().0, 5);
+}
+
+#[test]
+fn migrate_error() {
+  let mut world1 = World::new();
+  world1.spawn_1(Cursed);
+
+  let old =
+    ron::to_string(&world1)
+      .unwrap()
+      .replacen("(version:3,", "(version:2,", 1);
+  let err = ron::from_str::<World>(&old).err().unwrap();
+  assert!(err
+    .to_string()
+    .contains("entity 0@0, component `cursed`: when migrating"));
+}
+
+#[test]
+fn unversioned_bincode() {
+  let mut world1 = World::new();
+  let e = world1.spawn_1(Health(5));
+
+  // Old saves didn't have the version or the layout, which are four bytes each
+  let current = bincode::serialize(&world1).unwrap();
+  let old = &current[8..];
+
+  assert!(bincode::deserialize::<World>(old).is_err());
+  let options = LoadOptions {
+    unversioned: true,
+    ..Default::default()
+  };
+  let options_bin = bincode::DefaultOptions::new()
+    .with_fixint_encoding()
+    .allow_trailing_bytes();
+  let (world2, _) = World::deserialize_with(
+    &mut bincode::Deserializer::from_slice(old, options_bin),
+    options,
+  )
+  .unwrap();
+  assert_eq!(world2.query::<&Health>(e).unwrap().0, 5);
+}
+
+#[test]
+fn baseline_saves() {
+  // Saved before worlds had a version or a layout, which loads as version 0
+  let old = r#"(
+    allocator: [Some((0, ())), None, None, None],
+    entities: {(0, 0): [{"position": (x: 3, y: -4)}]},
+    resources: {},
+  )"#;
+  let world: World = ron::from_str(old).unwrap();
+  let e = Entity::recompose(0, 0);
+  assert_eq!(*world.query::<&Position>(e).unwrap(), Position::new(3, -4));
+
+  // The same sort of save in bincode
+  #[rustfmt::skip]
+  let old: &[u8] = &[
+    // The allocator: four slots, the first taken with generation 0
+    4, 0, 0, 0, 0, 0, 0, 0,
+    1, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0,
+    // One entity, at index 0 and generation 0
+    1, 0, 0, 0, 0, 0, 0, 0,
+    0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    // With one component
+    1, 0, 0, 0, 0, 0, 0, 0,
+    1, 0, 0, 0, 0, 0, 0, 0,
+    18, 0, 0, 0, 0, 0, 0, 0,
+    b'm', b'i', b'g', b'r', b'a', b't', b'i', b'o', b'n', b's', b':', b':',
+    b'H', b'e', b'a', b'l', b't', b'h',
+    5, 0, 0, 0,
+    // And no resources
+    0, 0, 0, 0, 0, 0, 0, 0,
+  ];
+  // It can't be told apart from a newer save, so it has to be marked
+  assert!(bincode::deserialize::<World>(old).is_err());
+  let options = LoadOptions {
+    unversioned: true,
+    ..Default::default()
+  };
+  let options_bin = bincode::DefaultOptions::new()
+    .with_fixint_encoding()
+    .allow_trailing_bytes();
+  let (world, _) = World::deserialize_with(
+    &mut bincode::Deserializer::from_slice(old, options_bin),
+    options,
+  )
+  .unwrap();
+  assert_eq!(world.query::<&Health>(e).unwrap().0, 5);
+}
+
+/// How positions used to look
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct LegacyPosition {
+  x: i32,
+  y: i32,
+}
+
+impl Component for LegacyPosition {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("legacy-position")
+  }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[register_component]
+struct Position {
+  xy: [i32; 2],
+  floor: i32,
+}
+
+impl Position {
+  fn new(x: i32, y: i32) -> Self {
+    Self {
+      xy: [x, y],
+      floor: 0,
+    }
+  }
+}
+
+impl Component for Position {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder
+      .set_friendly_name("position")
+      // {x, y} -> [x, y]
+      .migrate_from(0, |old| {
+        #[derive(Deserialize)]
+        struct Old {
+          x: i32,
+          y: i32,
+        }
+        let old: Old = old.deserialize_into()?;
+        Ok(Value::Seq(vec![Value::I32(old.x), Value::I32(old.y)]))
+      })
+      // [x, y] -> {xy: [x, y], floor: 0}
+      .migrate_from(1, |old| {
+        let mut new = BTreeMap::new();
+        new.insert(Value::String("xy".to_owned()), old);
+        new.insert(Value::String("floor".to_owned()), Value::I32(0));
+        Ok(Value::Map(new))
+      })
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Cursed;
+
+impl Component for Cursed {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("cursed").migrate_from(2, |_| {
+      eyre::bail!("saves from version 2 were cursed");
+    })
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component(marker)]
+struct Health(u32);
@@ -0,0 +1,202 @@
+//! Check diffing worlds and applying the patches.
+
+use palkia::{
+  manually_register_resource,
+  prelude::*,
+  resource::ResourceRegisterer,
+  serde::{Change, ComponentPatch, PatchError, WorldPatch},
+};
+use serde::{Deserialize, Serialize};
+
+fn make_world() -> (World, Entity, Entity) {
+  let mut world = World::new();
+  world.insert_resource(Turn(1));
+  let alice = world
+    .spawn()
+    .with(Name("alice".to_owned()))
+    .with(Position(0, 0))
+    .build();
+  let bob = world
+    .spawn()
+    .with(Name("bob".to_owned()))
+    .with(Position(5, 5))
+    .build();
+  (world, alice, bob)
+}
+
+fn copy(world: &World) -> World {
+  ron::from_str(&ron::to_string(world).unwrap()).unwrap()
+}
+
+#[test]
+fn diff_and_apply() {
+  let (mut server, alice, bob) = make_world();
+  let mut client = copy(&server);
+  assert!(client.diff(&server).is_empty());
+
+  *server.query::<&mut Position>(alice).unwrap() = Position(1, 0);
+  server.edit(alice).with(Poisoned).build();
+  let mut editor = server.edit(bob);
+  editor.remove::<Position>();
+  editor.build();
+  server.write_resource::<Turn>().unwrap().0 = 2;
+  let carol = server.spawn_1(Name("carol".to_owned()));
+
+  let patch = client.diff(&server);
+  assert_eq!(patch.added_entities, [carol]);
+  assert!(patch.removed_entities.is_empty());
+  let changes = patch
+    .components
+    .iter()
+    .map(|it| (it.entity, it.name.as_str()))
+    .collect::<Vec<_>>();
+  assert_eq!(
+    changes,
+    [
+      (alice, "position"),
+      (alice, "poisoned"),
+      (bob, "position"),
+      (carol, "name"),
+    ]
+  );
+  assert_eq!(patch.components[2].change, Change::Removed);
+  assert_eq!(patch.resources.len(), 1);
+  assert_eq!(patch.resources[0].name, "turn");
+
+  // Send it over the wire
+  let json = serde_json::to_string(&patch).unwrap();
+  let patch: WorldPatch = serde_json::from_str(&json).unwrap();
+
+  client.apply_patch(&patch).unwrap();
+  assert!(client.diff(&server).is_empty());
+  assert_eq!(*client.query::<&Position>(alice).unwrap(), Position(1, 0));
+  assert!(client.query::<&Poisoned>(alice).is_some());
+  assert!(client.query::<&Position>(bob).is_none());
+  assert_eq!(client.query::<&Name>(carol).unwrap().0, "carol");
+  assert_eq!(client.read_resource::<Turn>().unwrap().0, 2);
+
+  // Both worlds spawn the same entities from here on
+  assert_eq!(client.spawn_empty(), server.spawn_empty());
+}
+
+#[test]
+fn despawns() {
+  let (mut server, alice, bob) = make_world();
+  let mut client = copy(&server);
+  server.despawn(bob);
+
+  let patch = client.diff(&server);
+  assert_eq!(patch.removed_entities, [bob]);
+  assert!(patch.components.is_empty());
+
+  client.apply_patch(&patch).unwrap();
+  assert_eq!(client.len(), 1);
+  assert_eq!(client.query::<&Name>(alice).unwrap().0, "alice");
+  assert!(client.diff(&server).is_empty());
+}
+
+#[test]
+fn lazy_updates() {
+  let (server, alice, _) = make_world();
+  let mut client = copy(&server);
+
+  // Changing components leaves what's queued alone
+  *server.query::<&mut Position>(alice).unwrap() = Position(1, 0);
+  client.lazy_despawn(alice);
+  client.apply_patch(&client.diff(&server)).unwrap();
+  assert_eq!(*client.query::<&Position>(alice).unwrap(), Position(1, 0));
+  client.finalize();
+  assert_eq!(client.liveness(alice), EntityLiveness::Dead);
+
+  // But replacing the allocator has to wait until it's finalized
+  let (mut server, _, bob) = make_world();
+  let mut client = copy(&server);
+  server.despawn(bob);
+  let pending = client.lazy_spawn().with(Name("carol".to_owned())).build();
+  let patch = client.diff(&server);
+  let err = client.apply_patch(&patch).unwrap_err();
+  assert!(matches!(err, PatchError::Unfinalized), "{}", err);
+  client.finalize();
+  client.despawn(pending);
+  client.apply_patch(&patch).unwrap();
+  assert!(client.diff(&server).is_empty());
+}
+
+#[test]
+fn bad_patches() {
+  let (mut world, alice, _) = make_world();
+
+  let mut patch = WorldPatch::default();
+  patch.components.push(ComponentPatch {
+    entity: alice,
+    name: "position".to_owned(),
+    change: Change::Changed(palkia::serde::Value::String("up".to_owned())),
+  });
+  let err = world.apply_patch(&patch).unwrap_err();
+  assert!(matches!(err, PatchError::Deserialize { .. }), "{}", err);
+
+  patch.components[0].name = "velocity".to_owned();
+  let err = world.apply_patch(&patch).unwrap_err();
+  assert!(matches!(err, PatchError::UnknownComponent(_)), "{}", err);
+
+  let mut patch = WorldPatch::default();
+  patch.removed_entities.push(Entity::recompose(10, 0));
+  let err = world.apply_patch(&patch).unwrap_err();
+  assert!(matches!(err, PatchError::NotAlive(_)), "{}", err);
+
+  // Nothing got changed
+  assert_eq!(*world.query::<&Position>(alice).unwrap(), Position(0, 0));
+  assert_eq!(world.len(), 2);
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Name(String);
+
+impl Component for Name {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("name")
+  }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[register_component]
+struct Position(i32, i32);
+
+impl Component for Position {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("position")
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Poisoned;
+
+impl Component for Poisoned {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("poisoned")
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Turn(u32);
+manually_register_resource!(Turn);
+
+impl Resource for Turn {
+  fn register(builder: ResourceRegisterer<Self>) -> ResourceRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("turn")
+  }
+}
@@ -0,0 +1,281 @@
+//! Check replicating a world into another one over a pipe.
+
+use std::{
+  collections::VecDeque,
+  io::{self, Read, Write},
+  sync::mpsc::{self, Receiver, Sender},
+};
+
+use palkia::{
+  entities::{EntityMap, MapEntities},
+  manually_register_resource,
+  prelude::*,
+  replication::{Packet, ReplicaApplier, Replicator},
+  resource::ResourceRegisterer,
+};
+use serde::{Deserialize, Serialize};
+
+/// In-process pipe; reading blocks until something's written.
+fn pipe() -> (PipeWriter, PipeReader) {
+  let (tx, rx) = mpsc::channel();
+  (
+    PipeWriter(tx),
+    PipeReader {
+      rx,
+      buf: VecDeque::new(),
+    },
+  )
+}
+
+struct PipeWriter(Sender<Vec<u8>>);
+
+impl Write for PipeWriter {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self
+      .0
+      .send(buf.to_vec())
+      .map_err(|_| io::ErrorKind::BrokenPipe)?;
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+struct PipeReader {
+  rx: Receiver<Vec<u8>>,
+  buf: VecDeque<u8>,
+}
+
+impl Read for PipeReader {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    if self.buf.is_empty() {
+      match self.rx.recv() {
+        Ok(chunk) => self.buf.extend(chunk),
+        Err(_) => return Ok(0),
+      }
+    }
+    self.buf.read(buf)
+  }
+}
+
+#[test]
+fn replicate() {
+  let (mut tx, mut rx) = pipe();
+  let mut host = World::new();
+  let mut replica = World::new();
+  let mut replicator = Replicator::new();
+  let mut applier = ReplicaApplier::new();
+
+  host.insert_resource(Turn(1));
+  // This isn't replicated, so the host's entity IDs end up different from the
+  // replica's
+  let rock = host.spawn_1(Secret("just a rock".to_owned()));
+  let alice = host
+    .spawn()
+    .with(Position(0, 0))
+    .with(Secret("the butler did it".to_owned()))
+    .build();
+  let bob = host
+    .spawn()
+    .with(Position(3, 4))
+    .with(Follows(alice))
+    .build();
+
+  replicator.send(&host, &mut tx).unwrap();
+  applier.receive(&mut replica, &mut rx).unwrap();
+
+  assert_eq!(replica.len(), 2);
+  assert_eq!(applier.local(rock), None);
+  let alice2 = applier.local(alice).unwrap();
+  let bob2 = applier.local(bob).unwrap();
+  assert_ne!(alice2, alice);
+  assert_eq!(*replica.query::<&Position>(alice2).unwrap(), Position(0, 0));
+  assert!(replica.query::<&Secret>(alice2).is_none());
+  assert_eq!(replica.query::<&Follows>(bob2).unwrap().0, alice2);
+  assert_eq!(replica.read_resource::<Turn>().unwrap().0, 1);
+
+  // Only what changed gets sent again
+  *host.query::<&mut Position>(alice).unwrap() = Position(1, 0);
+  // This is mutably borrowed but doesn't really change
+  *host.query::<&mut Position>(bob).unwrap() = Position(3, 4);
+  let mut editor = host.edit(bob);
+  editor.remove::<Follows>();
+  editor.build();
+  let packets = replicator.update(&host);
+  assert_eq!(packets.len(), 2);
+  assert!(matches!(
+    &packets[0],
+    Packet::Component { entity, name, .. } if *entity == alice && name == "position"
+  ));
+  assert_eq!(
+    packets[1],
+    Packet::RemoveComponent {
+      entity: bob,
+      name: "follows".to_owned()
+    }
+  );
+  for packet in packets {
+    applier.apply(&mut replica, packet).unwrap();
+  }
+  assert_eq!(*replica.query::<&Position>(alice2).unwrap(), Position(1, 0));
+  assert!(replica.query::<&Follows>(bob2).is_none());
+
+  host.despawn(alice);
+  host.write_resource::<Turn>().unwrap().0 = 2;
+  replicator.send(&host, &mut tx).unwrap();
+  applier.receive(&mut replica, &mut rx).unwrap();
+  assert_eq!(replica.len(), 1);
+  assert_eq!(replica.liveness(alice2), EntityLiveness::Dead);
+  assert_eq!(replica.read_resource::<Turn>().unwrap().0, 2);
+
+  // Nothing new
+  assert!(replicator.update(&host).is_empty());
+}
+
+#[test]
+fn replicate_across_threads() {
+  let (mut tx, mut rx) = pipe();
+  let client = std::thread::spawn(move || {
+    let mut replica = World::new();
+    let mut applier = ReplicaApplier::new();
+    for _ in 0..3 {
+      applier.receive(&mut replica, &mut rx).unwrap();
+    }
+    let mut positions = replica
+      .entities()
+      .map(|e| *replica.query::<&Position>(e).unwrap())
+      .collect::<Vec<_>>();
+    positions.sort();
+    positions
+  });
+
+  let mut host = World::new();
+  let mut replicator = Replicator::new();
+  let e = host.spawn_1(Position(0, 0));
+  replicator.send(&host, &mut tx).unwrap();
+  host.spawn_1(Position(1, 1));
+  replicator.send(&host, &mut tx).unwrap();
+  host.query::<&mut Position>(e).unwrap().0 = 5;
+  replicator.send(&host, &mut tx).unwrap();
+
+  assert_eq!(client.join().unwrap(), [Position(1, 1), Position(5, 0)]);
+}
+
+#[test]
+fn skip_bad_update() {
+  let mut host = World::new();
+  let mut replicator = Replicator::new();
+  let mut stream = Vec::new();
+
+  // The replica never hears about this entity being spawned...
+  let e = host.spawn_1(Position(0, 0));
+  replicator.send(&host, io::sink()).unwrap();
+  // ...so it can't apply this update
+  host.query::<&mut Position>(e).unwrap().0 = 1;
+  host.insert_resource(Turn(1));
+  replicator.send(&host, &mut stream).unwrap();
+  // But it can apply the next ones
+  host.write_resource::<Turn>().unwrap().0 = 2;
+  replicator.send(&host, &mut stream).unwrap();
+  let mut other_host = World::new();
+  other_host.spawn_1(Position(7, 7));
+  Replicator::new().send(&other_host, &mut stream).unwrap();
+
+  let mut replica = World::new();
+  let mut applier = ReplicaApplier::new();
+  let mut reader = stream.as_slice();
+  assert!(applier.receive(&mut replica, &mut reader).is_err());
+  applier.receive(&mut replica, &mut reader).unwrap();
+  assert_eq!(replica.read_resource::<Turn>().unwrap().0, 2);
+  applier.receive(&mut replica, &mut reader).unwrap();
+  assert_eq!(replica.len(), 1);
+  assert!(reader.is_empty());
+}
+
+#[test]
+fn failed_send() {
+  let mut host = World::new();
+  let mut replicator = Replicator::new();
+  host.insert_resource(Turn(1));
+  host.spawn_1(Position(2, 3));
+
+  // The replica missed this one, so it all gets sent again
+  let (mut tx, rx) = pipe();
+  drop(rx);
+  assert!(replicator.send(&host, &mut tx).is_err());
+
+  let (mut tx, mut rx) = pipe();
+  replicator.send(&host, &mut tx).unwrap();
+  let mut replica = World::new();
+  ReplicaApplier::new()
+    .receive(&mut replica, &mut rx)
+    .unwrap();
+  assert_eq!(replica.len(), 1);
+  assert_eq!(replica.read_resource::<Turn>().unwrap().0, 1);
+}
+
+#[derive(
+  Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+#[register_component]
+struct Position(i32, i32);
+
+impl Component for Position {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("position").replicated()
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Follows(Entity);
+
+impl MapEntities for Follows {
+  fn map_entities(&mut self, map: &EntityMap) {
+    self.0.map_entities(map);
+  }
+}
+
+impl Component for Follows {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder
+      .set_friendly_name("follows")
+      .register_map_entities()
+      .replicated()
+  }
+}
+
+/// Not replicated.
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Secret(String);
+
+impl Component for Secret {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("secret")
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Turn(u32);
+manually_register_resource!(Turn);
+
+impl Resource for Turn {
+  fn register(builder: ResourceRegisterer<Self>) -> ResourceRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("turn").replicated()
+  }
+}
@@ -0,0 +1,170 @@
+//! Check moving some entities between worlds.
+
+use bincode::Options;
+use palkia::{entities::MapEntities, prelude::*, serde::WorldLayout};
+use serde::{Deserialize, Serialize};
+
+#[test]
+fn export_import() {
+  let mut world1 = World::new();
+
+  let outsider = world1.spawn_1(Item("pebble".to_string()));
+  let sword = world1.spawn_1(Item("sword".to_string()));
+  let shield = world1.spawn_1(Item("shield".to_string()));
+  let player = world1
+    .spawn()
+    .with(Inventory {
+      items: vec![sword, shield],
+      owner_of_last_map: Some(outsider),
+    })
+    .build();
+
+  let bin =
+    bincode::serialize(&world1.export_entities([player, sword, shield]))
+      .unwrap();
+
+  let mut world2 = World::new();
+  world2.insert_resource(ItemCount(0));
+  for _ in 0..10 {
+    world2.spawn_1(Item("junk".to_string()));
+  }
+  assert_eq!(world2.get_resource::<ItemCount>().unwrap().0, 10);
+
+  let map = world2
+    .import_entities(&mut bincode::Deserializer::from_slice(
+      &bin,
+      bincode::options()
+        .with_fixint_encoding()
+        .allow_trailing_bytes(),
+    ))
+    .unwrap();
+
+  assert_eq!(map.len(), 3);
+  assert_eq!(world2.len(), 13);
+  assert_eq!(world2.get_resource::<ItemCount>().unwrap().0, 12);
+  assert!(map.get(outsider).is_none());
+
+  let player2 = map.get(player).unwrap();
+  let inv = world2.query::<&Inventory>(player2).unwrap();
+  assert_eq!(inv.items, [map.map(sword), map.map(shield)]);
+  assert_eq!(world2.query::<&Item>(inv.items[0]).unwrap().0, "sword");
+  assert_eq!(world2.query::<&Item>(inv.items[1]).unwrap().0, "shield");
+  assert_eq!(
+    world2.liveness(inv.owner_of_last_map.unwrap()),
+    EntityLiveness::Dead
+  );
+}
+
+#[test]
+fn import_leaves_lazy_updates() {
+  let mut world1 = World::new();
+  let sword = world1.spawn_1(Item("sword".to_string()));
+  let bin = bincode::serialize(&world1.export_entities([sword])).unwrap();
+
+  let mut world2 = World::new();
+  world2.insert_resource(ItemCount(0));
+  let pending = world2
+    .lazy_spawn()
+    .with(Item("pending".to_string()))
+    .build();
+  world2
+    .import_entities(&mut bincode::Deserializer::from_slice(
+      &bin,
+      bincode::options()
+        .with_fixint_encoding()
+        .allow_trailing_bytes(),
+    ))
+    .unwrap();
+
+  // Importing doesn't finalize the caller's queued spawn
+  assert_eq!(world2.len(), 1);
+  assert_eq!(world2.get_resource::<ItemCount>().unwrap().0, 1);
+  world2.finalize();
+  assert_eq!(world2.query::<&Item>(pending).unwrap().0, "pending");
+  assert_eq!(world2.get_resource::<ItemCount>().unwrap().0, 2);
+}
+
+#[test]
+fn export_as_json() {
+  let mut world1 = World::new();
+  let sword = world1.spawn_1(Item("sword".to_string()));
+  let player = world1
+    .spawn()
+    .with(Inventory {
+      items: vec![sword],
+      owner_of_last_map: None,
+    })
+    .build();
+
+  let json = serde_json::to_string(
+    &world1.export_entities_with([player, sword], WorldLayout::EntityList),
+  )
+  .unwrap();
+
+  let mut world2 = World::new();
+  let map = world2
+    .import_entities(&mut serde_json::Deserializer::from_str(&json))
+    .unwrap();
+  let inv = world2.query::<&Inventory>(map.map(player)).unwrap();
+  assert_eq!(world2.query::<&Item>(inv.items[0]).unwrap().0, "sword");
+}
+
+#[test]
+fn export_dead_entity() {
+  let mut world = World::new();
+  let sword = world.spawn_1(Item("sword".to_string()));
+  let shield = world.spawn_1(Item("shield".to_string()));
+  world.despawn(shield);
+
+  let err = bincode::serialize(&world.export_entities([sword, shield]))
+    .unwrap_err()
+    .to_string();
+  assert!(
+    err.contains(&format!("entity {:x} isn't alive", shield)),
+    "{}",
+    err
+  );
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Inventory {
+  items: Vec<Entity>,
+  owner_of_last_map: Option<Entity>,
+}
+
+impl MapEntities for Inventory {
+  fn map_entities(&mut self, map: &palkia::entities::EntityMap) {
+    self.items.map_entities(map);
+    self.owner_of_last_map.map_entities(map);
+  }
+}
+
+impl Component for Inventory {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.register_map_entities()
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Item(String);
+
+impl Component for Item {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.register_create_callback(|_, _, access| {
+      if let Ok(mut count) = access.write_resource::<ItemCount>() {
+        count.0 += 1;
+      }
+    })
+  }
+}
+
+#[derive(Resource, Serialize, Deserialize)]
+struct ItemCount(u32);
@@ -0,0 +1,220 @@
+//! Check taking snapshots of worlds and rolling back to them.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use palkia::{
+  manually_register_resource, prelude::*, resource::ResourceRegisterer,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[test]
+fn rollback() {
+  let mut world = World::new();
+  world.insert_resource(Turn(1));
+  let alice = world
+    .spawn()
+    .with(Name("alice".to_owned()))
+    .with(Hp(10))
+    .build();
+  let carol = world.spawn_1(Name("carol".to_owned()));
+
+  let snapshot = world.snapshot();
+  assert_eq!(snapshot.len(), 2);
+
+  world.query::<&mut Hp>(alice).unwrap().0 = 3;
+  let mut editor = world.edit(alice);
+  editor.remove::<Name>();
+  editor.build();
+  let bob = world.spawn_1(Name("bob".to_owned()));
+  world.despawn(carol);
+  world.insert_resource(Turn(2));
+
+  world.restore(&snapshot);
+  assert_eq!(world.len(), 2);
+  assert_eq!(world.query::<&Name>(alice).unwrap().0, "alice");
+  assert_eq!(world.query::<&Hp>(alice).unwrap().0, 10);
+  assert_eq!(world.query::<&Name>(carol).unwrap().0, "carol");
+  assert_eq!(world.liveness(bob), EntityLiveness::Dead);
+  assert_eq!(world.read_resource::<Turn>().unwrap().0, 1);
+
+  // The same slot comes back around again
+  let (idx, _) = world.spawn_empty().decompose();
+  assert_eq!(idx, bob.decompose().0);
+
+  // And it can be restored more than once
+  world.query::<&mut Hp>(alice).unwrap().0 = 0;
+  world.restore(&snapshot);
+  assert_eq!(world.query::<&Hp>(alice).unwrap().0, 10);
+  assert_eq!(world.len(), 2);
+}
+
+#[test]
+fn changes_since_last() {
+  let mut world = World::new();
+  world.insert_resource(Turn(1));
+  let alice = world
+    .spawn()
+    .with(Name("alice".to_owned()))
+    .with(Hp(10))
+    .build();
+  let carol = world.spawn_1(Name("carol".to_owned()));
+
+  let first = world.snapshot();
+  assert_eq!(first.spawned().len(), 2);
+  assert_eq!(first.changed_resources(), ["turn"]);
+
+  let nothing = world.snapshot();
+  assert!(nothing.spawned().is_empty());
+  assert!(nothing.despawned().is_empty());
+  assert!(nothing.changed_components().is_empty());
+  assert!(nothing.changed_resources().is_empty());
+
+  world.query::<&mut Hp>(alice).unwrap().0 = 3;
+  let bob = world.spawn_1(Name("bob".to_owned()));
+  world.despawn(carol);
+  world.write_resource::<Turn>().unwrap().0 = 2;
+
+  let second = world.snapshot();
+  assert_eq!(second.spawned(), [bob]);
+  assert_eq!(second.despawned(), [carol]);
+  assert_eq!(second.changed_components(), [(alice, "hp")]);
+  assert_eq!(second.changed_resources(), ["turn"]);
+
+  let mut editor = world.edit(alice);
+  editor.remove::<Hp>();
+  editor.build();
+  let third = world.snapshot();
+  // Editing rebuilds the entity, so its name counts as changed too
+  let mut changed = third.changed_components().to_vec();
+  changed.sort();
+  assert_eq!(changed, [(alice, "hp"), (alice, "name")]);
+}
+
+#[test]
+fn lazy_updates() {
+  let mut world = World::new();
+  let alice = world.spawn_1(Name("alice".to_owned()));
+
+  // Taking a snapshot doesn't finalize anything
+  let pending = world.lazy_spawn().with(Name("pending".to_owned())).build();
+  let snapshot = world.snapshot();
+  assert_eq!(snapshot.len(), 1);
+  assert!(!snapshot.contains(pending));
+  world.finalize();
+  assert_eq!(world.query::<&Name>(pending).unwrap().0, "pending");
+
+  // Restoring throws out what was queued after the snapshot
+  world.restore(&snapshot);
+  assert_eq!(world.liveness(pending), EntityLiveness::Dead);
+  world.lazy_despawn(alice);
+  let bob = world.lazy_spawn().with(Name("bob".to_owned())).build();
+  world.restore(&snapshot);
+  world.finalize();
+  assert_eq!(world.len(), 1);
+  assert_eq!(world.query::<&Name>(alice).unwrap().0, "alice");
+  assert_eq!(world.liveness(bob), EntityLiveness::Dead);
+}
+
+static SERS: AtomicUsize = AtomicUsize::new(0);
+static DESERS: AtomicUsize = AtomicUsize::new(0);
+
+#[test]
+fn only_changes_are_serialized() {
+  let mut world = World::new();
+  let e1 = world.spawn_1(Counted(1));
+  let e2 = world.spawn_1(Counted(2));
+
+  let snapshot = world.snapshot();
+  assert_eq!(SERS.load(Ordering::SeqCst), 2);
+
+  // Reading doesn't count as changing
+  assert_eq!(world.query::<&Counted>(e1).unwrap().0, 1);
+  world.snapshot();
+  assert_eq!(SERS.load(Ordering::SeqCst), 2);
+
+  world.query::<&mut Counted>(e2).unwrap().0 = 20;
+  world.snapshot();
+  assert_eq!(SERS.load(Ordering::SeqCst), 3);
+
+  world.restore(&snapshot);
+  assert_eq!(DESERS.load(Ordering::SeqCst), 1);
+  assert_eq!(world.query::<&Counted>(e2).unwrap().0, 2);
+
+  // Restored components are known to match the snapshot
+  world.restore(&snapshot);
+  world.snapshot();
+  assert_eq!(DESERS.load(Ordering::SeqCst), 1);
+  assert_eq!(SERS.load(Ordering::SeqCst), 3);
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Name(String);
+
+impl Component for Name {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("name")
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Hp(u32);
+
+impl Component for Hp {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("hp")
+  }
+}
+
+/// Counts how many times it's been serialized and deserialized.
+#[register_component]
+struct Counted(u32);
+
+impl Serialize for Counted {
+  fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+  where
+    S: Serializer,
+  {
+    SERS.fetch_add(1, Ordering::SeqCst);
+    self.0.serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for Counted {
+  fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+  where
+    D: Deserializer<'de>,
+  {
+    DESERS.fetch_add(1, Ordering::SeqCst);
+    u32::deserialize(deserializer).map(Counted)
+  }
+}
+
+impl Component for Counted {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("counted")
+  }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Turn(u32);
+manually_register_resource!(Turn);
+
+impl Resource for Turn {
+  fn register(builder: ResourceRegisterer<Self>) -> ResourceRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("turn")
+  }
+}
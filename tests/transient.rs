@@ -0,0 +1,141 @@
+//! Check that transient components and resources aren't saved.
+
+use palkia::{
+  manually_register_resource, prelude::*, resource::ResourceRegisterer,
+  util::TrackEntitiesWithComponent,
+};
+use serde::{Deserialize, Serialize};
+
+#[test]
+fn transient() {
+  let mut world = World::new();
+  world.insert_resource(AudioDevice { volume: 11 });
+  world.insert_resource(SpriteTracker::default());
+  world.insert_resource(Score(100));
+
+  let e1 = world
+    .spawn()
+    .with(Position(1, 2))
+    .with(SoundChannel(7))
+    .with(Sprite { texture_id: 99 })
+    .build();
+  let e2 = world.spawn_1(Position(3, 4));
+  assert!(world
+    .read_resource::<SpriteTracker>()
+    .unwrap()
+    .0
+    .has_entity(e1));
+
+  let save = ron::to_string(&world).unwrap();
+  assert!(!save.contains("sound-channel"));
+  assert!(!save.contains("audio-device"));
+  assert!(!save.contains("sprite-tracker"));
+  // Placeholder, but not the data
+  assert!(save.contains("sprite"));
+  assert!(!save.contains("99"));
+
+  let world2: World = ron::from_str(&save).unwrap();
+  assert_eq!(world2.query::<&Position>(e1).unwrap().0, 1);
+  assert!(world2.query::<&SoundChannel>(e1).is_none());
+  assert_eq!(world2.query::<&Sprite>(e1).unwrap().texture_id, 0);
+  assert!(world2.query::<&Sprite>(e2).is_none());
+
+  assert!(world2.read_resource::<AudioDevice>().is_err());
+  assert_eq!(world2.read_resource::<Score>().unwrap().0, 100);
+  // Rebuilt from default and filled in by the create callbacks
+  let tracker = world2.read_resource::<SpriteTracker>().unwrap();
+  assert!(tracker.0.has_entity(e1));
+  assert!(!tracker.0.has_entity(e2));
+}
+
+#[test]
+#[should_panic]
+fn transient_clone() {
+  let mut world = World::new();
+  let e = world.spawn_1(SoundChannel(1));
+  world.clone_entity(e).build();
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Position(i32, i32);
+
+impl Component for Position {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("position")
+  }
+}
+
+/// Not serializable at all.
+#[register_component(transient)]
+struct SoundChannel(#[allow(dead_code)] u32);
+
+impl Component for SoundChannel {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("sound-channel").transient()
+  }
+}
+
+/// Rebuilt from default when loaded.
+#[derive(Default)]
+#[register_component(transient)]
+struct Sprite {
+  texture_id: u32,
+}
+
+impl Component for Sprite {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder
+      .set_friendly_name("sprite")
+      .transient_default()
+      .register_create_callback(|_, e, access| {
+        access
+          .write_resource::<SpriteTracker>()
+          .unwrap()
+          .0
+          .on_create(e);
+      })
+  }
+}
+
+struct AudioDevice {
+  #[allow(dead_code)]
+  volume: u32,
+}
+manually_register_resource!(AudioDevice, transient);
+
+impl Resource for AudioDevice {
+  fn register(builder: ResourceRegisterer<Self>) -> ResourceRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("audio-device").transient()
+  }
+}
+
+#[derive(Default)]
+struct SpriteTracker(TrackEntitiesWithComponent<Sprite>);
+manually_register_resource!(SpriteTracker, transient);
+
+impl Resource for SpriteTracker {
+  fn register(builder: ResourceRegisterer<Self>) -> ResourceRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder
+      .set_friendly_name("sprite-tracker")
+      .transient_default()
+  }
+}
+
+#[derive(Serialize, Deserialize, Resource)]
+struct Score(u32);
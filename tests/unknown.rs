@@ -0,0 +1,139 @@
+//! Check loading saves with components and resources that don't exist anymore.
+
+use palkia::{
+  manually_register_resource,
+  prelude::*,
+  resource::ResourceRegisterer,
+  serde::{LoadOptions, LoadReport, UnknownComponents, UnknownPolicy},
+};
+use serde::{Deserialize, Serialize};
+
+/// Save a world, then pretend the `Removed` component and resource were
+/// deleted from the game by renaming them to something unregistered.
+fn save_with_removed() -> (String, Entity, Entity) {
+  let mut world = World::new();
+  world.insert_resource(RemovedResource("hello".to_owned()));
+  let e1 = world
+    .spawn()
+    .with(Kept(1))
+    .with(Removed(vec![3, 4]))
+    .build();
+  let e2 = world.spawn_1(Kept(2));
+
+  let save = ron::to_string(&world)
+    .unwrap()
+    .replace("\"removed\"", "\"nonexistent\"")
+    .replace("\"removed-resource\"", "\"nonexistent-resource\"");
+  (save, e1, e2)
+}
+
+fn load(
+  save: &str,
+  unknown: UnknownPolicy,
+) -> Result<(World, LoadReport), ron::Error> {
+  let mut de = ron::Deserializer::from_str(save).unwrap();
+  let options = LoadOptions {
+    unknown,
+    ..Default::default()
+  };
+  World::deserialize_with(&mut de, options)
+}
+
+#[test]
+fn unknown_error() {
+  let (save, _, _) = save_with_removed();
+  assert!(ron::from_str::<World>(&save).is_err());
+
+  let err = load(&save, UnknownPolicy::Error).err().unwrap();
+  assert!(err.to_string().contains("nonexistent"));
+}
+
+#[test]
+fn unknown_skip() {
+  let (save, e1, e2) = save_with_removed();
+  let (world, report) = load(&save, UnknownPolicy::Skip).unwrap();
+
+  assert_eq!(world.query::<&Kept>(e1).unwrap().0, 1);
+  assert_eq!(world.query::<&Kept>(e2).unwrap().0, 2);
+  assert_eq!(world.len_of(e1), 1);
+
+  assert_eq!(
+    report.skipped_components,
+    vec![(e1, "nonexistent".to_owned())]
+  );
+  assert_eq!(report.skipped_resources, vec!["nonexistent-resource"]);
+
+  // Nothing is written back out
+  let resave = ron::to_string(&world).unwrap();
+  assert!(!resave.contains("nonexistent"));
+}
+
+#[test]
+fn unknown_preserve() {
+  let (save, e1, e2) = save_with_removed();
+  let (world, report) = load(&save, UnknownPolicy::Preserve).unwrap();
+  assert!(report.is_empty());
+
+  assert_eq!(world.query::<&Kept>(e1).unwrap().0, 1);
+  assert_eq!(world.query::<&UnknownComponents>(e1).unwrap().0.len(), 1);
+  assert!(world.query::<&UnknownComponents>(e2).is_none());
+
+  // Saving and loading it again keeps the data around, and if the component
+  // comes back, it's all still there.
+  let resave = ron::to_string(&world)
+    .unwrap()
+    .replace("\"nonexistent\"", "\"removed\"")
+    .replace("\"nonexistent-resource\"", "\"removed-resource\"");
+  let world2: World = ron::from_str(&resave).unwrap();
+
+  assert_eq!(world2.query::<&Kept>(e1).unwrap().0, 1);
+  assert_eq!(*world2.query::<&Removed>(e1).unwrap(), Removed(vec![3, 4]));
+  assert!(world2.query::<&UnknownComponents>(e1).is_none());
+  assert_eq!(
+    *world2.read_resource::<RemovedResource>().unwrap(),
+    RemovedResource("hello".to_owned())
+  );
+}
+
+#[derive(Serialize, Deserialize)]
+#[register_component]
+struct Kept(u32);
+
+impl Component for Kept {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("kept")
+  }
+}
+
+/// Ron is picky about structs, so make sure the data survives being
+/// stored as a [`palkia::serde::Value`].
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+#[register_component]
+struct Removed(Vec<i32>);
+
+impl Component for Removed {
+  fn register(builder: ComponentRegisterer<Self>) -> ComponentRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("removed")
+  }
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+struct RemovedResource(String);
+manually_register_resource!(RemovedResource);
+
+impl Resource for RemovedResource {
+  fn register(builder: ResourceRegisterer<Self>) -> ResourceRegisterer<Self>
+  where
+    Self: Sized,
+  {
+    builder.set_friendly_name("removed-resource")
+  }
+}